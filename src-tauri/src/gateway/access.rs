@@ -0,0 +1,180 @@
+// 网关访问控制：IP 允许/拒绝列表与客户端 Key，局域网共享时避免网关成为开放中继
+
+use crate::gateway::config::{ApiType, GatewayConfig};
+use crate::gateway::errors::{self, ErrorKind};
+use crate::gateway::proxy::ProxyState;
+use crate::gateway::secrets;
+use crate::gateway::server;
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderMap, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use ipnet::IpNet;
+use sha2::{Digest, Sha256};
+use std::net::{IpAddr, SocketAddr};
+use tauri::Runtime;
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+
+/// 请求入口的访问检查（每次请求读取最新配置，修改访问规则无需重启）
+pub async fn guard<R: Runtime>(
+    State(state): State<ProxyState<R>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let decision = {
+        let config = state.config.read().await;
+        check_access(&config, peer.ip(), req.headers())
+    };
+    match decision {
+        Ok(()) => next.run(req).await,
+        Err((kind, message)) => {
+            eprintln!("Rejected gateway request from {}: {}", peer.ip(), message);
+            errors::error_response(&state.api_type, kind, &message, None)
+        }
+    }
+}
+
+/// 检查来源 IP 与客户端 Key
+/// 顺序：denylist -> 本机放行 -> allowlist -> 客户端 Key
+pub fn check_access(
+    config: &GatewayConfig,
+    peer: IpAddr,
+    headers: &HeaderMap,
+) -> Result<(), (ErrorKind, String)> {
+    let peer = peer.to_canonical();
+
+    if matches_any(&config.denied_ips, peer) {
+        return Err((ErrorKind::Forbidden, format!("Address {} is denied", peer)));
+    }
+    if peer.is_loopback() {
+        return Ok(());
+    }
+    if !config.allowed_ips.is_empty() && !matches_any(&config.allowed_ips, peer) {
+        return Err((ErrorKind::Forbidden, format!("Address {} is not allowed", peer)));
+    }
+
+    let Some(presented) = presented_key(headers) else {
+        return Err((ErrorKind::Unauthorized, "Client key required".to_string()));
+    };
+    let presented = Sha256::digest(presented.as_bytes());
+    let valid = config
+        .client_keys
+        .iter()
+        .filter(|k| k.enabled)
+        .filter_map(|k| secrets::resolve(&k.key))
+        .any(|k| Sha256::digest(k.as_bytes()) == presented);
+    if !valid {
+        return Err((ErrorKind::Unauthorized, "Invalid client key".to_string()));
+    }
+    Ok(())
+}
+
+/// 客户端提供的 Key：x-api-key 或 Authorization: Bearer
+fn presented_key(headers: &HeaderMap) -> Option<&str> {
+    let from_header = headers.get("x-api-key").and_then(|v| v.to_str().ok());
+    let from_bearer = headers
+        .get("authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    from_header
+        .or(from_bearer)
+        .map(str::trim)
+        .filter(|k| !k.is_empty())
+}
+
+/// 解析 IP / CIDR 规则（单个 IP 视为 /32 或 /128）
+fn parse_rule(rule: &str) -> Result<IpNet, String> {
+    let rule = rule.trim();
+    rule.parse::<IpNet>()
+        .or_else(|_| rule.parse::<IpAddr>().map(IpNet::from))
+        .map_err(|_| format!("Invalid IP rule: {}", rule))
+}
+
+fn matches_any(rules: &[String], ip: IpAddr) -> bool {
+    rules
+        .iter()
+        .filter_map(|r| parse_rule(r).ok())
+        .any(|net| net.contains(&ip))
+}
+
+/// 校验访问控制配置（保存配置前调用）
+/// 新改为对外监听的地址必须至少有一个启用的客户端 Key；
+/// 沿用已有配置中的对外地址（如旧版本迁移来的 0.0.0.0）只提示不拦截，运行时会拒绝远程请求
+pub fn validate(config: &GatewayConfig, current: &GatewayConfig) -> Result<(), String> {
+    for rule in config.allowed_ips.iter().chain(&config.denied_ips) {
+        parse_rule(rule)?;
+    }
+    for origin in &config.cors_allowed_origins {
+        HeaderValue::from_str(origin.trim()).map_err(|_| format!("Invalid CORS origin: {}", origin))?;
+    }
+
+    let previous = listeners(current);
+    for (i, bind_address) in exposed_without_key(config)? {
+        if previous[i].0 == bind_address {
+            continue;
+        }
+        return Err(format!(
+            "Listening on {} exposes the gateway beyond this machine; add at least one client key first",
+            bind_address
+        ));
+    }
+    Ok(())
+}
+
+/// 运行时提示：对外监听但没有可用的客户端 Key 时，远程请求都会被拒绝
+pub fn exposure_notice(config: &GatewayConfig) -> Option<String> {
+    let (_, bind_address) = exposed_without_key(config).ok()?.into_iter().next()?;
+    Some(format!(
+        "Listening on {} without a client key: remote clients are rejected until a client key is added",
+        bind_address
+    ))
+}
+
+/// 各监听端口的 (地址, 是否启用)，顺序：Anthropic、Responses、Chat、统一端口
+fn listeners(config: &GatewayConfig) -> [(&str, bool); 4] {
+    [
+        (config.bind_address(&ApiType::Anthropic), config.anthropic_enabled),
+        (config.bind_address(&ApiType::OpenAIResponses), config.responses_enabled),
+        (config.bind_address(&ApiType::OpenAIChat), config.chat_enabled),
+        (config.unified_bind_address.as_str(), config.unified_enabled),
+    ]
+}
+
+/// 返回对外监听（非回环）且没有可用客户端 Key 的已启用监听（下标, 地址）
+fn exposed_without_key(config: &GatewayConfig) -> Result<Vec<(usize, &str)>, String> {
+    let has_client_key = config
+        .client_keys
+        .iter()
+        .any(|k| k.enabled && secrets::resolve(&k.key).is_some());
+    let mut exposed = Vec::new();
+    for (i, (bind_address, enabled)) in listeners(config).into_iter().enumerate() {
+        let addr = server::parse_bind_address(bind_address)?;
+        if enabled && !addr.is_loopback() && !has_client_key {
+            exposed.push((i, bind_address));
+        }
+    }
+    Ok(exposed)
+}
+
+/// CORS：只监听本机时不限制来源，对外开放时只允许配置的来源
+pub fn cors_layer(config: &GatewayConfig, bind_address: &str) -> CorsLayer {
+    let loopback = server::parse_bind_address(bind_address)
+        .map(|addr| addr.is_loopback())
+        .unwrap_or(true);
+    if loopback {
+        return CorsLayer::permissive();
+    }
+
+    let origins: Vec<HeaderValue> = config
+        .cors_allowed_origins
+        .iter()
+        .filter_map(|o| HeaderValue::from_str(o.trim()).ok())
+        .collect();
+    CorsLayer::new()
+        .allow_origin(AllowOrigin::list(origins))
+        .allow_methods(Any)
+        .allow_headers(Any)
+}
@@ -0,0 +1,201 @@
+// 会话亲和：同一会话的后续请求固定发往首次成功的供应商与 Key，以复用上游 prompt cache
+
+use crate::gateway::config::ApiType;
+use dashmap::DashMap;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+/// 会话亲和设置
+#[derive(Debug, Clone, Copy)]
+pub struct AffinitySettings {
+    pub enabled: bool,
+    pub ttl_seconds: u64,
+    pub max_sessions: usize,
+}
+
+/// 会话固定的供应商与 Key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionPin {
+    pub provider_id: String,
+    pub key_id: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+struct PinnedSession {
+    pin: SessionPin,
+    last_used: u64,
+}
+
+pub struct SessionAffinity {
+    sessions: DashMap<String, PinnedSession>,
+}
+
+impl Default for SessionAffinity {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SessionAffinity {
+    pub fn new() -> Self {
+        Self {
+            sessions: DashMap::new(),
+        }
+    }
+
+    /// 查询会话固定的供应商与 Key；过期的记录会被删除
+    pub fn pinned(&self, fingerprint: &str, now: u64, ttl_seconds: u64) -> Option<SessionPin> {
+        {
+            let entry = self.sessions.get(fingerprint)?;
+            if now <= entry.last_used.saturating_add(ttl_seconds) {
+                return Some(entry.pin.clone());
+            }
+        }
+        self.sessions.remove(fingerprint);
+        None
+    }
+
+    /// 请求成功后记录固定关系
+    /// 已固定到其他供应商/Key 且仍有效时保持原固定（本次只是临时回退），否则固定到当前供应商/Key
+    pub fn pin(&self, fingerprint: &str, pin: SessionPin, now: u64, settings: &AffinitySettings) {
+        if let Some(mut entry) = self.sessions.get_mut(fingerprint) {
+            if entry.pin == pin {
+                entry.last_used = now;
+                return;
+            }
+            if now <= entry.last_used.saturating_add(settings.ttl_seconds) {
+                return;
+            }
+        }
+
+        if self.sessions.len() >= settings.max_sessions.max(1) {
+            self.evict_expired(now, settings.ttl_seconds);
+        }
+        if self.sessions.len() >= settings.max_sessions.max(1) {
+            self.evict_oldest();
+        }
+
+        self.sessions.insert(
+            fingerprint.to_string(),
+            PinnedSession {
+                pin,
+                last_used: now,
+            },
+        );
+    }
+
+    /// 供应商熔断时解除所有固定到它的会话，下次请求重新选择
+    pub fn release_provider(&self, provider_id: &str) {
+        self.sessions.retain(|_, s| s.pin.provider_id != provider_id);
+    }
+
+    /// Key 冷却时解除固定到该 Key 的会话
+    pub fn release_key(&self, provider_id: &str, key_id: &str) {
+        self.sessions.retain(|_, s| {
+            s.pin.provider_id != provider_id || s.pin.key_id.as_deref() != Some(key_id)
+        });
+    }
+
+    /// 删除已不存在的供应商的固定记录
+    pub fn retain_providers(&self, provider_ids: &[String]) {
+        self.sessions.retain(|_, s| provider_ids.contains(&s.pin.provider_id));
+    }
+
+    pub fn evict_expired(&self, now: u64, ttl_seconds: u64) {
+        self.sessions
+            .retain(|_, s| now <= s.last_used.saturating_add(ttl_seconds));
+    }
+
+    /// 当前跟踪的会话数
+    pub fn session_count(&self) -> usize {
+        self.sessions.len()
+    }
+
+    fn evict_oldest(&self) {
+        let oldest = self
+            .sessions
+            .iter()
+            .min_by_key(|e| e.last_used)
+            .map(|e| e.key().clone());
+        if let Some(key) = oldest {
+            self.sessions.remove(&key);
+        }
+    }
+}
+
+/// 计算会话指纹
+/// 优先使用客户端提供的会话标识（`metadata.user_id` / `user`），否则使用 system prompt + 首条 user 消息
+/// 同一会话的后续轮次只会在末尾追加消息，因此指纹保持不变
+pub fn session_fingerprint(api_type: &ApiType, body: &[u8]) -> Option<String> {
+    let v = serde_json::from_slice::<Value>(body).ok()?;
+
+    let client_id = v
+        .get("metadata")
+        .and_then(|m| m.get("user_id"))
+        .or_else(|| v.get("user"))
+        .and_then(|u| u.as_str())
+        .filter(|u| !u.is_empty());
+
+    let mut hasher = Sha256::new();
+    hasher.update(format!("{:?}", api_type).as_bytes());
+    hasher.update([0]);
+
+    if let Some(id) = client_id {
+        hasher.update(b"user:");
+        hasher.update(id.as_bytes());
+    } else {
+        let system = system_prompt(&v);
+        let first_user = first_user_message(&v);
+        if system.is_none() && first_user.is_none() {
+            return None;
+        }
+        hasher.update(b"conv:");
+        hasher.update(system.map(content_text).unwrap_or_default().as_bytes());
+        hasher.update([0]);
+        hasher.update(first_user.map(content_text).unwrap_or_default().as_bytes());
+    }
+
+    Some(format!("{:x}", hasher.finalize()))
+}
+
+/// system prompt：Anthropic `system`、Responses `instructions`，或 OpenAI 首条 system/developer 消息
+fn system_prompt(v: &Value) -> Option<&Value> {
+    if let Some(system) = v.get("system").or_else(|| v.get("instructions")) {
+        return Some(system);
+    }
+    conversation(v)?
+        .iter()
+        .find(|m| matches!(m.get("role").and_then(|r| r.as_str()), Some("system") | Some("developer")))
+        .and_then(|m| m.get("content"))
+}
+
+/// 首条 user 消息的内容（Responses 的字符串 input 视为首条消息）
+fn first_user_message(v: &Value) -> Option<&Value> {
+    if let Some(input) = v.get("input").filter(|i| i.is_string()) {
+        return Some(input);
+    }
+    conversation(v)?
+        .iter()
+        .find(|m| m.get("role").and_then(|r| r.as_str()) == Some("user"))
+        .and_then(|m| m.get("content"))
+}
+
+fn conversation(v: &Value) -> Option<&Vec<Value>> {
+    v.get("messages")
+        .or_else(|| v.get("input"))
+        .and_then(|m| m.as_array())
+}
+
+/// 提取内容中的文本部分
+/// 忽略 cache_control 等元数据：客户端会在不同轮次移动缓存断点，不能影响指纹
+fn content_text(content: &Value) -> String {
+    match content {
+        Value::String(s) => s.clone(),
+        Value::Array(blocks) => blocks
+            .iter()
+            .filter_map(|b| b.as_str().or_else(|| b.get("text").and_then(|t| t.as_str())))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        other => other.to_string(),
+    }
+}
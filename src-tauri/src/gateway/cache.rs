@@ -1,7 +1,24 @@
 use std::collections::HashMap;
-use std::sync::{Arc, RwLock};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use sha2::{Sha256, Digest};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use axum::http::HeaderMap;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use crate::gateway::stats::CacheStats;
+
+/// 磁盘索引落盘防抖：空闲该时长后保存修改
+const INDEX_FLUSH_DELAY: Duration = Duration::from_secs(1);
+/// 持续写入时索引最长的落盘间隔
+const INDEX_FLUSH_MAX_DELAY: Duration = Duration::from_secs(10);
 
 #[derive(Debug, Clone)]
 pub struct CacheEntry {
@@ -10,122 +27,994 @@ pub struct CacheEntry {
     pub headers: Vec<(String, String)>,
     pub created_at: u64,
     pub ttl_seconds: u64,
+    // 响应体是否为原始 SSE 流
+    pub is_stream: bool,
+    pub api_type: String,
+    pub path: String,
+    pub model: String,
+    pub hits: u64,
 }
 
 impl CacheEntry {
     pub fn is_expired(&self) -> bool {
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs();
-        now > self.created_at + self.ttl_seconds
+        now_secs() > self.created_at + self.ttl_seconds
+    }
+}
+
+/// 写入缓存时附带的元信息
+#[derive(Debug, Clone)]
+pub struct CacheMeta {
+    pub api_type: String,
+    pub path: String,
+    pub model: String,
+    pub ttl_seconds: u64,
+    pub is_stream: bool,
+}
+
+/// 缓存条目概要（用于管理界面展示）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheEntrySummary {
+    pub key: String,
+    pub api_type: String,
+    pub path: String,
+    pub model: String,
+    pub status: u16,
+    pub size_bytes: u64,
+    pub created_at: u64,
+    pub age_seconds: u64,
+    pub ttl_seconds: u64,
+    pub hits: u64,
+    pub is_stream: bool,
+    pub in_memory: bool,
+    pub on_disk: bool,
+}
+
+/// 缓存失效条件，各字段之间为“且”关系，至少需要指定一个
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CacheInvalidation {
+    #[serde(default)]
+    pub key: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub path_prefix: Option<String>,
+    #[serde(default)]
+    pub api_type: Option<String>,
+}
+
+impl CacheInvalidation {
+    pub fn is_empty(&self) -> bool {
+        self.key.is_none() && self.model.is_none() && self.path_prefix.is_none() && self.api_type.is_none()
     }
+
+    fn matches(&self, key: &str, api_type: &str, path: &str, model: &str) -> bool {
+        self.key.as_deref().is_none_or(|k| k == key)
+            && self.model.as_deref().is_none_or(|m| m == model)
+            && self.path_prefix.as_deref().is_none_or(|p| path.starts_with(p))
+            && self.api_type.as_deref().is_none_or(|t| t == api_type)
+    }
+}
+
+/// 缓存配置
+#[derive(Debug, Clone)]
+pub struct CacheSettings {
+    pub max_entries: usize,
+    pub max_memory_bytes: u64,
+    pub max_disk_bytes: u64,
+    pub compression: bool,
+    // 磁盘层目录；None 表示仅使用内存
+    pub disk_dir: Option<PathBuf>,
+}
+
+/// 内存层条目
+struct MemoryEntry {
+    entry: CacheEntry,
+    last_access: u64,
+}
+
+/// 磁盘层索引条目（响应体单独存放在 `<key>.bin`）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct DiskEntryMeta {
+    status: u16,
+    headers: Vec<(String, String)>,
+    created_at: u64,
+    ttl_seconds: u64,
+    #[serde(default)]
+    is_stream: bool,
+    #[serde(default)]
+    api_type: String,
+    #[serde(default)]
+    path: String,
+    #[serde(default)]
+    model: String,
+    #[serde(default)]
+    hits: u64,
+    size_bytes: u64,
+    compressed: bool,
+    last_access: u64,
+}
+
+impl DiskEntryMeta {
+    fn is_expired(&self) -> bool {
+        now_secs() > self.created_at + self.ttl_seconds
+    }
+}
+
+struct DiskTier {
+    dir: PathBuf,
+    index: HashMap<String, DiskEntryMeta>,
+    bytes: u64,
+    // 索引有未落盘的修改（由后台写入线程定期保存）
+    dirty: bool,
 }
 
+struct CacheInner {
+    memory: HashMap<String, MemoryEntry>,
+    memory_bytes: u64,
+    disk: Option<DiskTier>,
+    // 单调递增的访问序号，用于 LRU
+    tick: u64,
+    evictions: u64,
+}
+
+/// 交给后台写入线程的磁盘操作（按提交顺序执行）
+enum DiskJob {
+    Write { key: String, entry: CacheEntry, tick: u64 },
+    Remove(Vec<String>),
+    Clear,
+}
+
+/// 两级响应缓存：内存层 + 可选的磁盘层（`data/cache/`）
+/// 写入时同时写两层；内存层按 LRU 和字节上限淘汰，磁盘层按 LRU 和磁盘字节上限淘汰。
+/// 内存未命中时从磁盘读取并回填内存。
+/// 锁只保护内存中的数据与磁盘索引：压缩、文件读写与索引落盘在锁外进行
+/// （读取使用 spawn_blocking，写入、删除与索引保存由后台线程按顺序执行）。
+#[derive(Clone)]
 pub struct CacheManager {
-    cache: Arc<RwLock<HashMap<String, CacheEntry>>>,
-    max_entries: usize,
-    default_ttl: u64,
+    inner: Arc<Mutex<CacheInner>>,
+    settings: CacheSettings,
+    disk_jobs: Option<mpsc::Sender<DiskJob>>,
 }
 
 impl CacheManager {
-    pub fn new(max_entries: usize, default_ttl: u64) -> Self {
+    pub fn new(settings: CacheSettings) -> Self {
+        let disk = settings.disk_dir.as_ref().and_then(|dir| match DiskTier::open(dir.clone()) {
+            Ok(tier) => Some(tier),
+            Err(e) => {
+                eprintln!("Failed to open disk cache at {}: {}", dir.display(), e);
+                None
+            }
+        });
+        let tick = disk
+            .as_ref()
+            .and_then(|d| d.index.values().map(|m| m.last_access).max())
+            .unwrap_or(0);
+        let has_disk = disk.is_some();
+
+        let inner = Arc::new(Mutex::new(CacheInner {
+            memory: HashMap::new(),
+            memory_bytes: 0,
+            disk,
+            tick,
+            evictions: 0,
+        }));
+        let disk_jobs = has_disk.then(|| spawn_disk_writer(inner.clone(), settings.clone()));
+
         Self {
-            cache: Arc::new(RwLock::new(HashMap::new())),
-            max_entries,
-            default_ttl,
+            inner,
+            settings,
+            disk_jobs,
         }
     }
     
-    /// 生成缓存 Key (基于路径和请求体的 SHA256)
-    pub fn generate_key(path: &str, body: &[u8]) -> String {
+    /// 生成缓存 Key (基于 API 类型、路径和规范化请求体的 SHA256)
+    /// JSON 请求体会移除 ignored_fields 中的字段并按键排序后再计算，非 JSON 请求体按原始字节计算
+    pub fn generate_key(api_type: &str, path: &str, body: &[u8], ignored_fields: &[String]) -> String {
         let mut hasher = Sha256::new();
+        hasher.update(api_type.as_bytes());
+        hasher.update(b":");
         hasher.update(path.as_bytes());
-        hasher.update(body);
+        match serde_json::from_slice::<Value>(body) {
+            Ok(mut json) => {
+                for field in ignored_fields {
+                    remove_json_path(&mut json, field);
+                }
+                hasher.update(canonical_json(&json).as_bytes());
+            }
+            Err(_) => hasher.update(body),
+        }
         format!("{:x}", hasher.finalize())
     }
     
-    /// 获取缓存
-    pub fn get(&self, key: &str) -> Option<CacheEntry> {
-        let cache = self.cache.read().ok()?;
-        let entry = cache.get(key)?;
-        
-        if entry.is_expired() {
-            // 过期了，返回 None（下次写入时会覆盖）
-            None
-        } else {
-            Some(entry.clone())
+    /// 获取缓存（内存未命中时在阻塞线程中读取磁盘层）
+    pub async fn get(&self, key: &str) -> Option<CacheEntry> {
+        let (meta, path) = {
+            let mut inner = self.inner.lock().ok()?;
+            inner.tick += 1;
+            let tick = inner.tick;
+
+            if let Some(mem) = inner.memory.get_mut(key) {
+                if !mem.entry.is_expired() {
+                    mem.last_access = tick;
+                    mem.entry.hits += 1;
+                    let entry = mem.entry.clone();
+                    if let Some(disk) = inner.disk.as_mut() {
+                        disk.touch(key, tick);
+                    }
+                    return Some(entry);
+                }
+            }
+
+            let disk = inner.disk.as_mut()?;
+            let meta = disk.index.get(key)?.clone();
+            if meta.is_expired() {
+                disk.remove(key);
+                self.submit(DiskJob::Remove(vec![key.to_string()]));
+                return None;
+            }
+            (meta, disk.body_path(key))
+        };
+
+        let compressed = meta.compressed;
+        let body = tokio::task::spawn_blocking(move || read_body(&path, compressed))
+            .await
+            .ok()
+            .flatten();
+
+        let mut inner = self.inner.lock().ok()?;
+        inner.tick += 1;
+        let tick = inner.tick;
+        let disk = inner.disk.as_mut()?;
+        // 读取期间条目可能已被删除或覆盖
+        if disk.index.get(key).is_none_or(|m| m.created_at != meta.created_at) {
+            return None;
         }
+        let Some(body) = body else {
+            // 文件缺失或已损坏
+            disk.remove(key);
+            self.submit(DiskJob::Remove(vec![key.to_string()]));
+            return None;
+        };
+        disk.touch(key, tick);
+
+        let entry = CacheEntry {
+            response_body: body,
+            status: meta.status,
+            headers: meta.headers,
+            created_at: meta.created_at,
+            ttl_seconds: meta.ttl_seconds,
+            is_stream: meta.is_stream,
+            api_type: meta.api_type,
+            path: meta.path,
+            model: meta.model,
+            hits: meta.hits + 1,
+        };
+        inner.insert_memory(key.to_string(), entry.clone(), tick, &self.settings);
+        Some(entry)
     }
     
-    /// 设置缓存
-    pub fn set(&self, key: String, response_body: Vec<u8>, status: u16, headers: Vec<(String, String)>) {
-        let mut cache = match self.cache.write() {
+    /// 设置缓存：立即写入内存层，磁盘层由后台线程写入
+    pub fn set(
+        &self,
+        key: String,
+        response_body: Vec<u8>,
+        status: u16,
+        headers: Vec<(String, String)>,
+        meta: CacheMeta,
+    ) {
+        let mut inner = match self.inner.lock() {
             Ok(c) => c,
             Err(_) => return,
         };
-        
-        // 如果超过最大条目数，清理过期条目
-        if cache.len() >= self.max_entries {
-            self.evict_expired_internal(&mut cache);
-            
-            // 如果还是满了，删除最旧的
-            if cache.len() >= self.max_entries {
-                // 简单策略：删除第一个找到的
-                if let Some(k) = cache.keys().next().cloned() {
-                    cache.remove(&k);
-                }
-            }
-        }
-        
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs();
-        
-        cache.insert(key, CacheEntry {
+        inner.tick += 1;
+        let tick = inner.tick;
+
+        let entry = CacheEntry {
             response_body,
             status,
             headers,
-            created_at: now,
-            ttl_seconds: self.default_ttl,
-        });
+            created_at: now_secs(),
+            ttl_seconds: meta.ttl_seconds,
+            is_stream: meta.is_stream,
+            api_type: meta.api_type,
+            path: meta.path,
+            model: meta.model,
+            hits: 0,
+        };
+
+        if inner.disk.is_some() {
+            self.submit(DiskJob::Write { key: key.clone(), entry: entry.clone(), tick });
+        }
+        inner.insert_memory(key, entry, tick, &self.settings);
     }
     
     /// 清理过期条目
     pub fn evict_expired(&self) {
-        if let Ok(mut cache) = self.cache.write() {
-            self.evict_expired_internal(&mut cache);
+        let Ok(mut inner) = self.inner.lock() else {
+            return;
+        };
+        inner.evict_expired_memory();
+        if let Some(disk) = inner.disk.as_mut() {
+            let expired = disk.remove_expired();
+            if !expired.is_empty() {
+                self.submit(DiskJob::Remove(expired));
+            }
         }
     }
     
-    fn evict_expired_internal(&self, cache: &mut HashMap<String, CacheEntry>) {
-        cache.retain(|_, entry| !entry.is_expired());
-    }
-    
     /// 清空所有缓存
     pub fn clear(&self) {
-        if let Ok(mut cache) = self.cache.write() {
-            cache.clear();
+        if let Ok(mut inner) = self.inner.lock() {
+            inner.memory.clear();
+            inner.memory_bytes = 0;
+            if let Some(disk) = inner.disk.as_mut() {
+                disk.index.clear();
+                disk.bytes = 0;
+                disk.dirty = true;
+                self.submit(DiskJob::Clear);
+            }
         }
     }
     
-    /// 获取缓存统计
-    pub fn stats(&self) -> (usize, usize) {
-        let cache = match self.cache.read() {
-            Ok(c) => c,
-            Err(_) => return (0, self.max_entries),
+    /// 列出所有未过期的缓存条目（合并内存层与磁盘层），按最近创建排序
+    pub fn entries(&self) -> Vec<CacheEntrySummary> {
+        let Ok(inner) = self.inner.lock() else {
+            return Vec::new();
+        };
+        let now = now_secs();
+        let mut summaries: HashMap<String, CacheEntrySummary> = HashMap::new();
+
+        if let Some(disk) = inner.disk.as_ref() {
+            for (key, meta) in disk.index.iter().filter(|(_, m)| !m.is_expired()) {
+                summaries.insert(key.clone(), CacheEntrySummary {
+                    key: key.clone(),
+                    api_type: meta.api_type.clone(),
+                    path: meta.path.clone(),
+                    model: meta.model.clone(),
+                    status: meta.status,
+                    size_bytes: meta.size_bytes,
+                    created_at: meta.created_at,
+                    age_seconds: now.saturating_sub(meta.created_at),
+                    ttl_seconds: meta.ttl_seconds,
+                    hits: meta.hits,
+                    is_stream: meta.is_stream,
+                    in_memory: false,
+                    on_disk: true,
+                });
+            }
+        }
+
+        for (key, mem) in inner.memory.iter().filter(|(_, m)| !m.entry.is_expired()) {
+            let entry = &mem.entry;
+            let summary = summaries.entry(key.clone()).or_insert_with(|| CacheEntrySummary {
+                key: key.clone(),
+                api_type: entry.api_type.clone(),
+                path: entry.path.clone(),
+                model: entry.model.clone(),
+                status: entry.status,
+                size_bytes: 0,
+                created_at: entry.created_at,
+                age_seconds: now.saturating_sub(entry.created_at),
+                ttl_seconds: entry.ttl_seconds,
+                hits: 0,
+                is_stream: entry.is_stream,
+                in_memory: true,
+                on_disk: false,
+            });
+            summary.in_memory = true;
+            summary.size_bytes = entry.response_body.len() as u64;
+            summary.hits = summary.hits.max(entry.hits);
+        }
+
+        let mut list: Vec<CacheEntrySummary> = summaries.into_values().collect();
+        list.sort_by(|a, b| b.created_at.cmp(&a.created_at).then_with(|| a.key.cmp(&b.key)));
+        list
+    }
+
+    /// 删除匹配条件的缓存条目，返回删除数量
+    pub fn invalidate(&self, filter: &CacheInvalidation) -> usize {
+        let Ok(mut inner) = self.inner.lock() else {
+            return 0;
+        };
+
+        let mut keys: Vec<String> = inner
+            .memory
+            .iter()
+            .filter(|(k, m)| filter.matches(k, &m.entry.api_type, &m.entry.path, &m.entry.model))
+            .map(|(k, _)| k.clone())
+            .collect();
+        if let Some(disk) = inner.disk.as_ref() {
+            keys.extend(
+                disk.index
+                    .iter()
+                    .filter(|(k, m)| filter.matches(k, &m.api_type, &m.path, &m.model))
+                    .map(|(k, _)| k.clone()),
+            );
+        }
+        keys.sort();
+        keys.dedup();
+
+        for key in &keys {
+            if let Some(old) = inner.memory.remove(key) {
+                inner.memory_bytes -= old.entry.response_body.len() as u64;
+            }
+            if let Some(disk) = inner.disk.as_mut() {
+                disk.remove(key);
+            }
+        }
+        let removed = keys.len();
+        if inner.disk.is_some() && removed > 0 {
+            self.submit(DiskJob::Remove(keys));
+        }
+        removed
+    }
+
+    /// 获取缓存统计（命中率由调用方基于 GatewayStats 计数填充）
+    pub fn stats(&self) -> CacheStats {
+        let Ok(inner) = self.inner.lock() else {
+            return CacheStats::default();
+        };
+        CacheStats {
+            memory_entries: inner.memory.len() as u64,
+            memory_bytes: inner.memory_bytes,
+            disk_entries: inner.disk.as_ref().map(|d| d.index.len() as u64).unwrap_or(0),
+            disk_bytes: inner.disk.as_ref().map(|d| d.bytes).unwrap_or(0),
+            max_memory_bytes: self.settings.max_memory_bytes,
+            max_disk_bytes: if inner.disk.is_some() { self.settings.max_disk_bytes } else { 0 },
+            evictions: inner.evictions,
+            hit_ratio: 0.0,
+        }
+    }
+
+    /// 提交磁盘操作给后台写入线程
+    fn submit(&self, job: DiskJob) {
+        if let Some(jobs) = &self.disk_jobs {
+            let _ = jobs.send(job);
+        }
+    }
+}
+
+impl CacheInner {
+    /// 写入内存层，按条目数与字节上限淘汰最久未访问的条目
+    fn insert_memory(&mut self, key: String, entry: CacheEntry, tick: u64, settings: &CacheSettings) {
+        if let Some(old) = self.memory.remove(&key) {
+            self.memory_bytes -= old.entry.response_body.len() as u64;
+        }
+
+        let size = entry.response_body.len() as u64;
+        if size > settings.max_memory_bytes {
+            return;
+        }
+
+        if self.memory.len() >= settings.max_entries
+            || self.memory_bytes + size > settings.max_memory_bytes
+        {
+            self.evict_expired_memory();
+        }
+        while !self.memory.is_empty()
+            && (self.memory.len() >= settings.max_entries
+                || self.memory_bytes + size > settings.max_memory_bytes)
+        {
+            let Some(lru_key) = self
+                .memory
+                .iter()
+                .min_by_key(|(_, m)| m.last_access)
+                .map(|(k, _)| k.clone())
+            else {
+                break;
+            };
+            if let Some(old) = self.memory.remove(&lru_key) {
+                self.memory_bytes -= old.entry.response_body.len() as u64;
+            }
+            // 有磁盘层时条目仍可从磁盘读取，不计为淘汰
+            if self.disk.is_none() {
+                self.evictions += 1;
+            }
+        }
+
+        self.memory_bytes += size;
+        self.memory.insert(key, MemoryEntry { entry, last_access: tick });
+    }
+
+    fn evict_expired_memory(&mut self) {
+        let mut freed = 0;
+        self.memory.retain(|_, m| {
+            let keep = !m.entry.is_expired();
+            if !keep {
+                freed += m.entry.response_body.len() as u64;
+            }
+            keep
+        });
+        self.memory_bytes -= freed;
+    }
+}
+
+impl DiskTier {
+    /// 打开磁盘缓存目录并加载索引；丢弃过期条目与缺失文件，删除索引外的残留文件
+    fn open(dir: PathBuf) -> std::io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        let index: HashMap<String, DiskEntryMeta> = fs::read_to_string(dir.join("index.json"))
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default();
+
+        let mut tier = Self {
+            dir,
+            index,
+            bytes: 0,
+            dirty: false,
+        };
+
+        let dir = tier.dir.clone();
+        tier.index.retain(|key, meta| !meta.is_expired() && body_path(&dir, key).exists());
+        tier.bytes = tier.index.values().map(|m| m.size_bytes).sum();
+
+        if let Ok(read_dir) = fs::read_dir(&tier.dir) {
+            for file in read_dir.flatten() {
+                let path = file.path();
+                let stale = match path.extension().and_then(|e| e.to_str()) {
+                    // 写入中断留下的临时文件
+                    Some("tmp") => true,
+                    Some("bin") => !path
+                        .file_stem()
+                        .and_then(|s| s.to_str())
+                        .map(|stem| tier.index.contains_key(stem))
+                        .unwrap_or(false),
+                    _ => false,
+                };
+                if stale {
+                    let _ = fs::remove_file(&path);
+                }
+            }
+        }
+
+        save_index(&tier.dir, &tier.index);
+        Ok(tier)
+    }
+
+    fn body_path(&self, key: &str) -> PathBuf {
+        body_path(&self.dir, key)
+    }
+
+    /// 更新访问序号与命中次数；索引由后台线程延迟落盘
+    fn touch(&mut self, key: &str, tick: u64) {
+        if let Some(meta) = self.index.get_mut(key) {
+            meta.last_access = tick;
+            meta.hits += 1;
+            self.dirty = true;
+        }
+    }
+
+    /// 从索引中移除条目（文件由后台线程删除）
+    fn remove(&mut self, key: &str) {
+        if let Some(meta) = self.index.remove(key) {
+            self.bytes = self.bytes.saturating_sub(meta.size_bytes);
+            self.dirty = true;
+        }
+    }
+
+    /// 从索引中移除过期条目，返回被移除的 key
+    fn remove_expired(&mut self) -> Vec<String> {
+        let expired: Vec<String> = self
+            .index
+            .iter()
+            .filter(|(_, m)| m.is_expired())
+            .map(|(k, _)| k.clone())
+            .collect();
+        for key in &expired {
+            self.remove(key);
+        }
+        expired
+    }
+}
+
+/// 启动磁盘层后台写入线程：按顺序执行写入与删除，索引防抖落盘
+/// 所有发送端释放后保存一次索引并退出
+fn spawn_disk_writer(inner: Arc<Mutex<CacheInner>>, settings: CacheSettings) -> mpsc::Sender<DiskJob> {
+    let (sender, receiver) = mpsc::channel();
+    let Some(dir) = settings.disk_dir.clone() else {
+        return sender;
+    };
+
+    let spawned = thread::Builder::new()
+        .name("cache-disk-writer".to_string())
+        .spawn(move || {
+            let mut last_flush = Instant::now();
+            loop {
+                match receiver.recv_timeout(INDEX_FLUSH_DELAY) {
+                    Ok(job) => {
+                        run_disk_job(&inner, &settings, &dir, job);
+                        // 持续写入时也定期落盘
+                        if last_flush.elapsed() >= INDEX_FLUSH_MAX_DELAY {
+                            flush_index(&inner, &dir);
+                            last_flush = Instant::now();
+                        }
+                    }
+                    Err(RecvTimeoutError::Timeout) => {
+                        flush_index(&inner, &dir);
+                        last_flush = Instant::now();
+                    }
+                    Err(RecvTimeoutError::Disconnected) => {
+                        flush_index(&inner, &dir);
+                        break;
+                    }
+                }
+            }
+        });
+    if let Err(e) = spawned {
+        eprintln!("Failed to start disk cache writer: {}", e);
+    }
+    sender
+}
+
+fn run_disk_job(inner: &Mutex<CacheInner>, settings: &CacheSettings, dir: &Path, job: DiskJob) {
+    match job {
+        DiskJob::Write { key, entry, tick } => write_entry(inner, settings, dir, &key, &entry, tick),
+        DiskJob::Remove(keys) => {
+            // 提交删除后、执行前写入的同名条目也一并删除
+            if let Ok(mut inner) = inner.lock() {
+                if let Some(disk) = inner.disk.as_mut() {
+                    for key in &keys {
+                        disk.remove(key);
+                    }
+                }
+            }
+            for key in &keys {
+                let _ = fs::remove_file(body_path(dir, key));
+            }
+        }
+        DiskJob::Clear => {
+            if let Ok(mut inner) = inner.lock() {
+                if let Some(disk) = inner.disk.as_mut() {
+                    disk.index.clear();
+                    disk.bytes = 0;
+                    disk.dirty = true;
+                }
+            }
+            if let Ok(read_dir) = fs::read_dir(dir) {
+                for file in read_dir.flatten() {
+                    let path = file.path();
+                    if path.extension().and_then(|e| e.to_str()) == Some("bin") {
+                        let _ = fs::remove_file(&path);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// 压缩并写入条目文件，再更新索引并按磁盘字节上限淘汰（锁内只修改索引）
+fn write_entry(inner: &Mutex<CacheInner>, settings: &CacheSettings, dir: &Path, key: &str, entry: &CacheEntry, tick: u64) {
+    let (data, compressed) = if settings.compression {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::fast());
+        match encoder.write_all(&entry.response_body).and_then(|_| encoder.finish()) {
+            Ok(data) => (data, true),
+            Err(_) => (entry.response_body.clone(), false),
+        }
+    } else {
+        (entry.response_body.clone(), false)
+    };
+
+    let size = data.len() as u64;
+    let path = body_path(dir, key);
+    if size > settings.max_disk_bytes {
+        if let Ok(mut inner) = inner.lock() {
+            if let Some(disk) = inner.disk.as_mut() {
+                disk.remove(key);
+            }
+        }
+        let _ = fs::remove_file(&path);
+        return;
+    }
+
+    if let Err(e) = write_atomic(&path, &data) {
+        eprintln!("Failed to write disk cache entry: {}", e);
+        return;
+    }
+
+    let removed = {
+        let Ok(mut guard) = inner.lock() else {
+            return;
+        };
+        let inner = &mut *guard;
+        let Some(disk) = inner.disk.as_mut() else {
+            return;
+        };
+        // 覆盖同一 key 时文件已被替换，只更新索引
+        disk.remove(key);
+        let mut removed = disk.remove_expired();
+        let mut evicted = 0;
+        while disk.bytes + size > settings.max_disk_bytes {
+            let Some(lru_key) = disk
+                .index
+                .iter()
+                .min_by_key(|(_, m)| m.last_access)
+                .map(|(k, _)| k.clone())
+            else {
+                break;
+            };
+            disk.remove(&lru_key);
+            removed.push(lru_key);
+            evicted += 1;
+        }
+
+        disk.bytes += size;
+        disk.index.insert(
+            key.to_string(),
+            DiskEntryMeta {
+                status: entry.status,
+                headers: entry.headers.clone(),
+                created_at: entry.created_at,
+                ttl_seconds: entry.ttl_seconds,
+                is_stream: entry.is_stream,
+                api_type: entry.api_type.clone(),
+                path: entry.path.clone(),
+                model: entry.model.clone(),
+                hits: entry.hits,
+                size_bytes: size,
+                compressed,
+                last_access: tick,
+            },
+        );
+        disk.dirty = true;
+        inner.evictions += evicted;
+        removed
+    };
+
+    for key in removed {
+        let _ = fs::remove_file(body_path(dir, &key));
+    }
+}
+
+/// 索引有修改时保存（锁内只复制索引，序列化与写文件在锁外进行）
+fn flush_index(inner: &Mutex<CacheInner>, dir: &Path) {
+    let index = {
+        let Ok(mut inner) = inner.lock() else {
+            return;
+        };
+        let Some(disk) = inner.disk.as_mut().filter(|d| d.dirty) else {
+            return;
+        };
+        disk.dirty = false;
+        disk.index.clone()
+    };
+    save_index(dir, &index);
+}
+
+fn save_index(dir: &Path, index: &HashMap<String, DiskEntryMeta>) {
+    match serde_json::to_vec(index) {
+        Ok(json) => {
+            if let Err(e) = write_atomic(&dir.join("index.json"), &json) {
+                eprintln!("Failed to save disk cache index: {}", e);
+            }
+        }
+        Err(e) => eprintln!("Failed to serialize disk cache index: {}", e),
+    }
+}
+
+/// 读取条目文件；文件缺失或解压失败时返回 None
+fn read_body(path: &Path, compressed: bool) -> Option<Vec<u8>> {
+    let raw = fs::read(path).ok()?;
+    if !compressed {
+        return Some(raw);
+    }
+    let mut decoded = Vec::new();
+    GzDecoder::new(raw.as_slice()).read_to_end(&mut decoded).ok()?;
+    Some(decoded)
+}
+
+/// 先写临时文件再重命名，读取方不会看到写了一半的文件
+fn write_atomic(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, data)?;
+    fs::rename(&tmp, path)
+}
+
+fn body_path(dir: &Path, key: &str) -> PathBuf {
+    dir.join(format!("{}.bin", key))
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// 缓存准入规则
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheAdmission {
+    // 允许缓存 temperature 非 0（或未指定）的请求
+    pub allow_nonzero_temperature: bool,
+    // 允许缓存带工具定义的请求（工具结果可能依赖外部状态）
+    pub allow_tools: bool,
+    // 可缓存的最大响应体字节数
+    pub max_body_bytes: u64,
+}
+
+impl CacheAdmission {
+    /// 判断请求是否允许使用缓存
+    /// 未指定 temperature 时上游默认值通常为 1，视为非确定性请求
+    pub fn admits_request(&self, body: &[u8]) -> bool {
+        let Ok(json) = serde_json::from_slice::<Value>(body) else {
+            return false;
         };
-        (cache.len(), self.max_entries)
+
+        if !self.allow_nonzero_temperature {
+            let temperature = json.get("temperature").and_then(|t| t.as_f64());
+            if temperature != Some(0.0) {
+                return false;
+            }
+        }
+
+        if !self.allow_tools {
+            let has_tools = ["tools", "functions"].iter().any(|field| {
+                json.get(field)
+                    .and_then(|t| t.as_array())
+                    .is_some_and(|arr| !arr.is_empty())
+            });
+            if has_tools {
+                return false;
+            }
+        }
+
+        true
     }
+
+    /// 判断响应是否允许写入缓存：仅 2xx 且不超过大小上限
+    pub fn admits_response(&self, status: u16, body_len: usize) -> bool {
+        (200..300).contains(&status) && body_len as u64 <= self.max_body_bytes
+    }
+}
+
+/// 客户端请求级缓存控制
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheDirective {
+    pub read: bool,
+    pub write: bool,
 }
 
-impl Clone for CacheManager {
-    fn clone(&self) -> Self {
+impl CacheDirective {
+    /// 解析请求头：
+    /// - `x-vibehub-cache: bypass` / `Cache-Control: no-store`：不读也不写缓存
+    /// - `Cache-Control: no-cache`：跳过读取，但写入新结果
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let bypass = headers
+            .get("x-vibehub-cache")
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.trim().eq_ignore_ascii_case("bypass"))
+            .unwrap_or(false);
+        if bypass {
+            return Self { read: false, write: false };
+        }
+
+        let cache_control = headers
+            .get("cache-control")
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("")
+            .to_ascii_lowercase();
+        let directives: Vec<&str> = cache_control.split(',').map(|d| d.trim()).collect();
+
+        if directives.contains(&"no-store") {
+            return Self { read: false, write: false };
+        }
         Self {
-            cache: self.cache.clone(),
-            max_entries: self.max_entries,
-            default_ttl: self.default_ttl,
+            read: !directives.contains(&"no-cache"),
+            write: true,
+        }
+    }
+}
+
+/// 按点号路径删除 JSON 字段，例如 `metadata.user_id`
+fn remove_json_path(value: &mut Value, path: &str) {
+    let mut parts: Vec<&str> = path.split('.').filter(|p| !p.is_empty()).collect();
+    let Some(last) = parts.pop() else {
+        return;
+    };
+
+    let mut current = value;
+    for part in parts {
+        match current.get_mut(part) {
+            Some(next) => current = next,
+            None => return,
+        }
+    }
+    if let Some(obj) = current.as_object_mut() {
+        obj.remove(last);
+    }
+}
+
+/// 按键排序序列化 JSON，保证键顺序不同的等价请求得到相同结果
+fn canonical_json(value: &Value) -> String {
+    match value {
+        Value::Object(map) => {
+            let mut keys: Vec<&String> = map.keys().collect();
+            keys.sort();
+            let fields: Vec<String> = keys
+                .into_iter()
+                .map(|k| format!("{}:{}", Value::String(k.clone()), canonical_json(&map[k])))
+                .collect();
+            format!("{{{}}}", fields.join(","))
+        }
+        Value::Array(items) => {
+            let items: Vec<String> = items.iter().map(canonical_json).collect();
+            format!("[{}]", items.join(","))
         }
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strict() -> CacheAdmission {
+        CacheAdmission {
+            allow_nonzero_temperature: false,
+            allow_tools: false,
+            max_body_bytes: 1024,
+        }
+    }
+
+    fn admits(admission: CacheAdmission, body: &str) -> bool {
+        admission.admits_request(body.as_bytes())
+    }
+
+    #[test]
+    fn temperature_must_be_zero_unless_allowed() {
+        let admission = strict();
+        assert!(!admits(admission, r#"{"model":"m"}"#), "absent temperature defaults to nonzero upstream");
+        assert!(admits(admission, r#"{"model":"m","temperature":0}"#));
+        assert!(admits(admission, r#"{"model":"m","temperature":0.0}"#));
+        assert!(!admits(admission, r#"{"model":"m","temperature":0.7}"#));
+
+        let relaxed = CacheAdmission { allow_nonzero_temperature: true, ..strict() };
+        assert!(admits(relaxed, r#"{"model":"m"}"#));
+        assert!(admits(relaxed, r#"{"model":"m","temperature":0.7}"#));
+    }
+
+    #[test]
+    fn tools_and_functions_block_unless_allowed() {
+        let admission = strict();
+        for field in ["tools", "functions"] {
+            let with_tools = format!(r#"{{"temperature":0,"{}":[{{"name":"get_weather"}}]}}"#, field);
+            let empty = format!(r#"{{"temperature":0,"{}":[]}}"#, field);
+            assert!(!admits(admission, &with_tools), "{}", field);
+            assert!(admits(admission, &empty), "empty {} is not a tool request", field);
+        }
+
+        let relaxed = CacheAdmission { allow_tools: true, ..strict() };
+        assert!(admits(relaxed, r#"{"temperature":0,"tools":[{"name":"get_weather"}]}"#));
+    }
+
+    #[test]
+    fn invalid_json_is_never_admitted() {
+        let permissive = CacheAdmission {
+            allow_nonzero_temperature: true,
+            allow_tools: true,
+            max_body_bytes: 1024,
+        };
+        assert!(!admits(permissive, "not json"));
+        assert!(!admits(permissive, r#"{"temperature":0"#));
+        assert!(!admits(permissive, ""));
+    }
+
+    #[test]
+    fn response_requires_2xx_status() {
+        let admission = strict();
+        assert!(admission.admits_response(200, 10));
+        assert!(admission.admits_response(204, 10));
+        assert!(!admission.admits_response(199, 10));
+        assert!(!admission.admits_response(304, 10));
+        assert!(!admission.admits_response(429, 10));
+        assert!(!admission.admits_response(500, 10));
+    }
+
+    #[test]
+    fn response_body_limit_is_inclusive() {
+        let admission = strict();
+        assert!(admission.admits_response(200, 0));
+        assert!(admission.admits_response(200, 1024));
+        assert!(!admission.admits_response(200, 1025));
     }
 }
@@ -0,0 +1,233 @@
+// 上游 HTTP 客户端池：每种出站代理与 TLS 设置组合对应一个客户端（连接池互相隔离）
+
+use crate::gateway::config::{OutboundProxy, Provider, TlsSettings};
+use crate::gateway::resilience::FailureKind;
+use crate::gateway::secrets;
+use dashmap::DashMap;
+use reqwest::Client;
+use std::error::Error as StdError;
+use std::fs;
+use std::sync::RwLock;
+use std::time::Duration;
+
+/// 选中的上游客户端
+pub struct UpstreamClient {
+    pub client: Client,
+    // 是否经由出站代理（用于区分代理错误与上游连接错误）
+    pub via_proxy: bool,
+}
+
+/// 客户端的连接设置；proxy 为 None 表示未配置代理（沿用系统环境变量）
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct ClientOptions {
+    proxy: Option<OutboundProxy>,
+    tls: Option<TlsSettings>,
+}
+
+pub struct ClientPool {
+    global_proxy: RwLock<Option<OutboundProxy>>,
+    clients: DashMap<ClientOptions, Client>,
+}
+
+impl ClientPool {
+    pub fn new(global_proxy: Option<OutboundProxy>) -> Self {
+        Self {
+            global_proxy: RwLock::new(global_proxy),
+            clients: DashMap::new(),
+        }
+    }
+
+    /// 更新全局代理（保存配置时调用），丢弃已缓存的客户端（同时重新读取证书文件）
+    pub fn set_global_proxy(&self, proxy: Option<OutboundProxy>) {
+        *self.global_proxy.write().unwrap() = proxy;
+        self.clients.clear();
+    }
+
+    /// 获取供应商使用的客户端：供应商代理优先，其次全局代理
+    /// 构建失败时返回失败类别（代理或 TLS 配置错误）与说明
+    pub fn client_for(&self, provider: &Provider) -> Result<UpstreamClient, (FailureKind, String)> {
+        let options = ClientOptions {
+            proxy: match &provider.proxy {
+                Some(proxy) => Some(proxy.clone()),
+                None => self.global_proxy.read().unwrap().clone(),
+            },
+            tls: provider.tls.clone().filter(|t| !t.is_default()),
+        };
+        let via_proxy = options.proxy.as_ref().is_some_and(|p| !p.is_direct());
+
+        if let Some(client) = self.clients.get(&options) {
+            return Ok(UpstreamClient {
+                client: client.clone(),
+                via_proxy,
+            });
+        }
+
+        let client = build_client(&options)?;
+        self.clients.insert(options, client.clone());
+        Ok(UpstreamClient { client, via_proxy })
+    }
+}
+
+fn build_client(options: &ClientOptions) -> Result<Client, (FailureKind, String)> {
+    let mut builder = Client::builder()
+        .connect_timeout(Duration::from_secs(3))
+        .pool_idle_timeout(Duration::from_secs(90))
+        .pool_max_idle_per_host(16);
+
+    match &options.proxy {
+        Some(proxy) if proxy.is_direct() => builder = builder.no_proxy(),
+        Some(proxy) => builder = builder.proxy(build_proxy(proxy).map_err(|e| (FailureKind::Proxy, e))?),
+        None => {}
+    }
+    if let Some(tls) = &options.tls {
+        builder = apply_tls(builder, tls).map_err(|e| (FailureKind::Tls, e))?;
+    }
+
+    builder
+        .build()
+        .map_err(|e| (FailureKind::Other, format!("Failed to build HTTP client: {}", e)))
+}
+
+/// 追加 CA 证书、客户端证书，或关闭证书校验
+fn apply_tls(
+    mut builder: reqwest::ClientBuilder,
+    tls: &TlsSettings,
+) -> Result<reqwest::ClientBuilder, String> {
+    if let Some(path) = non_empty(&tls.ca_cert_path) {
+        let pem = read_pem(path, "CA certificate")?;
+        let certs = reqwest::Certificate::from_pem_bundle(&pem)
+            .map_err(|e| format!("Invalid CA certificate '{}': {}", path, e))?;
+        if certs.is_empty() {
+            return Err(format!("No certificates found in '{}'", path));
+        }
+        for cert in certs {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+
+    match (non_empty(&tls.client_cert_path), non_empty(&tls.client_key_path)) {
+        (Some(cert_path), Some(key_path)) => {
+            let cert = read_pem(cert_path, "client certificate")?;
+            let key = read_pem(key_path, "client key")?;
+            let identity = reqwest::Identity::from_pkcs8_pem(&cert, &key)
+                .map_err(|e| format!("Invalid client certificate or key (key must be PKCS#8 PEM): {}", e))?;
+            builder = builder.identity(identity);
+        }
+        (None, None) => {}
+        _ => return Err("Client certificate and client key must be configured together".to_string()),
+    }
+
+    if tls.danger_accept_invalid_certs {
+        builder = builder.danger_accept_invalid_certs(true);
+    }
+    Ok(builder)
+}
+
+fn non_empty(value: &Option<String>) -> Option<&str> {
+    value.as_deref().map(str::trim).filter(|v| !v.is_empty())
+}
+
+fn read_pem(path: &str, what: &str) -> Result<Vec<u8>, String> {
+    fs::read(path).map_err(|e| format!("Failed to read {} '{}': {}", what, path, e))
+}
+
+/// 构造 reqwest 代理；凭据写入代理 URL，HTTP 与 SOCKS5 代理通用
+fn build_proxy(proxy: &OutboundProxy) -> Result<reqwest::Proxy, String> {
+    let mut url = reqwest::Url::parse(proxy.url.trim())
+        .map_err(|e| format!("Invalid proxy URL '{}': {}", proxy.url, e))?;
+    if !matches!(url.scheme(), "http" | "https" | "socks5" | "socks5h") {
+        return Err(format!("Unsupported proxy scheme: {}", url.scheme()));
+    }
+
+    if let Some(username) = proxy.username.as_deref().filter(|u| !u.is_empty()) {
+        let password = proxy
+            .password
+            .as_deref()
+            .and_then(secrets::resolve)
+            .unwrap_or_default();
+        url.set_username(username)
+            .and_then(|_| url.set_password(Some(&password)))
+            .map_err(|_| format!("Invalid proxy URL '{}'", proxy.url))?;
+    }
+
+    let mut result = reqwest::Proxy::all(url.as_str()).map_err(|e| format!("Invalid proxy: {}", e))?;
+    if !proxy.no_proxy.is_empty() {
+        result = result.no_proxy(reqwest::NoProxy::from_string(&proxy.no_proxy.join(",")));
+    }
+    Ok(result)
+}
+
+/// 校验代理配置（保存配置前调用）
+pub fn validate_proxy(proxy: &OutboundProxy) -> Result<(), String> {
+    if proxy.is_direct() {
+        return Ok(());
+    }
+    build_proxy(proxy).map(|_| ())
+}
+
+/// 校验 TLS 配置：证书文件可读且格式正确（保存配置前调用）
+pub fn validate_tls(tls: &TlsSettings) -> Result<(), String> {
+    apply_tls(Client::builder(), tls).map(|_| ())
+}
+
+/// 上游请求发送失败时的失败类别与说明
+/// TLS 错误优先识别：证书问题在连接阶段出现，否则会被误报为代理或连接失败
+pub fn classify_send_error(error: &reqwest::Error, via_proxy: bool) -> (FailureKind, String) {
+    if error.is_timeout() {
+        (FailureKind::Timeout, format!("Upstream timeout: {}", error_chain(error)))
+    } else if let Some(detail) = tls_error_detail(error) {
+        (FailureKind::Tls, format!("TLS handshake failed: {}", detail))
+    } else if via_proxy && is_proxy_error(error) {
+        (FailureKind::Proxy, format!("Proxy connection failed: {}", error_chain(error)))
+    } else {
+        (FailureKind::Connect, format!("Connection failed: {}", error_chain(error)))
+    }
+}
+
+/// 在错误链中查找 TLS 相关的底层错误（证书校验失败、握手被拒绝等）
+fn tls_error_detail(error: &reqwest::Error) -> Option<String> {
+    let mut source: Option<&dyn StdError> = error.source();
+    while let Some(e) = source {
+        let text = e.to_string();
+        let lower = text.to_lowercase();
+        if ["certificate", "handshake", "ssl", "tls", "x509"]
+            .iter()
+            .any(|k| lower.contains(k))
+        {
+            return Some(text);
+        }
+        source = e.source();
+    }
+    None
+}
+
+/// 拼接完整错误链，reqwest 顶层错误通常只有 "error sending request"
+fn error_chain(error: &reqwest::Error) -> String {
+    let mut text = error.to_string();
+    let mut source: Option<&dyn StdError> = error.source();
+    while let Some(e) = source {
+        let part = e.to_string();
+        if !text.contains(&part) {
+            text.push_str(": ");
+            text.push_str(&part);
+        }
+        source = e.source();
+    }
+    text
+}
+
+/// 经由代理的请求失败时，判断是否为代理本身的问题（无法连接代理、隧道建立失败、SOCKS 握手失败）
+pub fn is_proxy_error(error: &reqwest::Error) -> bool {
+    if error.is_connect() {
+        return true;
+    }
+    let mut source: Option<&dyn StdError> = error.source();
+    while let Some(e) = source {
+        let text = e.to_string().to_lowercase();
+        if text.contains("proxy") || text.contains("tunnel") || text.contains("socks") {
+            return true;
+        }
+        source = e.source();
+    }
+    false
+}
@@ -2,9 +2,18 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
+use std::sync::Arc;
 use anyhow::{Context, Result};
+use crate::gateway::affinity::AffinitySettings;
+use crate::gateway::cache::CacheAdmission;
+use crate::gateway::keys::{KeySelection, ResolvedKey};
+use crate::gateway::plugins::PluginConfig;
+use crate::gateway::secrets::{self, SecretStore};
+use crate::gateway::security::{PiiPattern, ScanSettings, SecurityPolicy};
+use crate::gateway::selection::SelectionStrategy;
+use crate::gateway::transforms::TransformRule;
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash, Default)]
 pub enum ApiType {
     #[default]
     Anthropic,      // /v1/messages - Claude Code
@@ -12,12 +21,28 @@ pub enum ApiType {
     OpenAIChat,     // /v1/chat/completions - Cline, Continue, etc.
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct Provider {
     pub id: String,
     pub name: String,
+    // 供应商类型（决定默认 URL 结构与鉴权方式）
+    #[serde(default)]
+    pub kind: ProviderKind,
     pub base_url: String,
+    // 上游 URL 模板，占位符：{base} {path} {endpoint} {model} {deployment}
+    // 例：{base}/openai/deployments/{deployment}{endpoint}；为空时使用 base_url + path
+    #[serde(default)]
+    pub url_template: Option<String>,
+    // 密钥可写明文（保存时加密）或引用：env:VAR_NAME / file:/path
     pub api_key: String,
+    // 额外的 API Key，与 api_key 一起轮换使用；每个 Key 独立冷却
+    #[serde(default)]
+    pub api_keys: Vec<String>,
+    #[serde(default)]
+    pub key_selection: KeySelection,
+    // 解析后的 API Key（加载或保存配置时解析），不写入配置文件
+    #[serde(skip)]
+    pub(crate) resolved_keys: Arc<Vec<ResolvedKey>>,
     #[serde(default)]
     pub model_mapping: HashMap<String, String>,
     pub enabled: bool,
@@ -39,6 +64,197 @@ pub struct Provider {
     // Claude Code 代理模式：将 Anthropic 请求转换为 OpenAI 格式
     #[serde(default)]
     pub claude_code_proxy: bool,
+    // 转换模式下将 thinking 映射为 reasoning_effort（仅推理模型接受，其他模型会返回 400）
+    #[serde(default)]
+    pub send_reasoning_effort: bool,
+    // 转换模式下转发 top_k（OpenAI 不支持该参数，仅在上游接受时开启）
+    #[serde(default)]
+    pub forward_top_k: bool,
+
+    // 鉴权方式（默认按协议自动选择）
+    #[serde(default)]
+    pub auth_style: AuthStyle,
+    // 覆盖 Anthropic 原生请求的 anthropic-version（默认 2023-06-01）
+    #[serde(default)]
+    pub anthropic_version: Option<String>,
+    // 附加到上游请求的固定请求头（同名时覆盖）
+    #[serde(default)]
+    pub extra_headers: HashMap<String, String>,
+    // 不转发给上游的客户端请求头（不区分大小写）
+    #[serde(default)]
+    pub remove_headers: Vec<String>,
+    // 附加到上游 URL 的查询参数（同名时覆盖客户端参数）
+    #[serde(default)]
+    pub query_params: HashMap<String, String>,
+
+    // 出站代理（覆盖全局代理；url 填 "direct" 表示直连）
+    #[serde(default)]
+    pub proxy: Option<OutboundProxy>,
+
+    // 自定义 TLS（内网 CA、mTLS 客户端证书、本地 mock 跳过校验）
+    #[serde(default)]
+    pub tls: Option<TlsSettings>,
+}
+
+/// 供应商 TLS 设置
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq, Eq, Hash)]
+pub struct TlsSettings {
+    // 额外信任的 CA 证书（PEM 文件，可包含多个证书），与系统根证书同时生效
+    #[serde(default)]
+    pub ca_cert_path: Option<String>,
+    // mTLS 客户端证书与私钥（PEM 文件，私钥需为 PKCS#8 格式）
+    #[serde(default)]
+    pub client_cert_path: Option<String>,
+    #[serde(default)]
+    pub client_key_path: Option<String>,
+    // 危险：不校验证书与主机名，仅用于本地 mock 服务
+    #[serde(default)]
+    pub danger_accept_invalid_certs: bool,
+}
+
+impl TlsSettings {
+    /// 未设置任何选项时与默认 TLS 行为一致
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// 出站代理设置
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq, Eq, Hash)]
+pub struct OutboundProxy {
+    // http:// https:// socks5:// socks5h://，或 "direct"
+    pub url: String,
+    #[serde(default)]
+    pub username: Option<String>,
+    // 保存时加密，也可使用 env:/file: 引用
+    #[serde(default)]
+    pub password: Option<String>,
+    // 不走代理的主机，规则同 NO_PROXY（如 localhost、.internal、10.0.0.0/8）
+    #[serde(default)]
+    pub no_proxy: Vec<String>,
+}
+
+impl OutboundProxy {
+    /// 直连（不使用代理，也忽略系统代理环境变量）
+    pub fn is_direct(&self) -> bool {
+        let url = self.url.trim();
+        url.is_empty() || url.eq_ignore_ascii_case("direct")
+    }
+}
+
+/// 供应商类型
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ProviderKind {
+    /// OpenAI / Anthropic 兼容接口（base_url + path）
+    #[default]
+    Generic,
+    /// Azure OpenAI：按部署路由，`api-key` 鉴权，需要 api-version
+    Azure,
+}
+
+/// Azure OpenAI 默认 api-version（可通过 query_params 覆盖）
+const DEFAULT_AZURE_API_VERSION: &str = "2024-10-21";
+
+impl ProviderKind {
+    /// 未配置 url_template 时的默认模板
+    /// endpoint 为去掉 `/v1` 前缀的路径；Azure 的部署级接口需要模型（部署名）
+    pub fn default_url_template(&self, endpoint: &str, has_model: bool) -> Option<&'static str> {
+        match self {
+            ProviderKind::Generic => None,
+            ProviderKind::Azure => {
+                let deployment_scoped = ["/chat/completions", "/completions", "/embeddings", "/audio/", "/images/"]
+                    .iter()
+                    .any(|p| endpoint.starts_with(p));
+                if deployment_scoped && has_model {
+                    Some("{base}/openai/deployments/{deployment}{endpoint}")
+                } else {
+                    Some("{base}/openai{endpoint}")
+                }
+            }
+        }
+    }
+}
+
+/// 上游鉴权方式
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthStyle {
+    /// Azure 使用 api-key，Anthropic 原生协议使用 x-api-key，其余使用 Bearer
+    #[default]
+    Auto,
+    /// `Authorization: Bearer <key>`
+    Bearer,
+    /// `x-api-key: <key>`
+    XApiKey,
+    /// `api-key: <key>`（Azure OpenAI）
+    ApiKey,
+    /// 查询参数 `?key=<key>`
+    QueryKey,
+    /// 不发送密钥（本地模型等）
+    #[serde(rename = "none")]
+    NoAuth,
+}
+
+impl Provider {
+    /// 所有可用的 API Key（由 resolve_keys 在加载或保存配置时解析）
+    pub fn keys(&self) -> &[ResolvedKey] {
+        &self.resolved_keys
+    }
+
+    /// 解析 API Key：api_key 在前，解析 env:/file: 引用，去除空值与重复
+    pub fn resolve_keys(&mut self) {
+        let mut keys: Vec<ResolvedKey> = Vec::new();
+        for key in std::iter::once(&self.api_key).chain(self.api_keys.iter()) {
+            if let Some(key) = secrets::resolve(key) {
+                if !keys.iter().any(|k| k.secret == key) {
+                    keys.push(ResolvedKey::new(key));
+                }
+            }
+        }
+        self.resolved_keys = Arc::new(keys);
+    }
+
+    /// 应用模型映射后的上游模型名
+    pub fn mapped_model<'a>(&'a self, model: &'a str) -> &'a str {
+        self.model_mapping.get(model).map(|m| m.as_str()).unwrap_or(model)
+    }
+
+    /// 附加到上游 URL 的查询参数（含供应商类型的默认参数），按名称排序
+    pub fn upstream_query_params(&self) -> Vec<(String, String)> {
+        let mut params: Vec<(String, String)> = self
+            .query_params
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        if self.kind == ProviderKind::Azure && !self.query_params.contains_key("api-version") {
+            params.push(("api-version".to_string(), DEFAULT_AZURE_API_VERSION.to_string()));
+        }
+        params.sort();
+        params
+    }
+
+    /// 需要加密保存的字段：API Key 与代理密码
+    fn secrets(&self) -> impl Iterator<Item = &String> {
+        std::iter::once(&self.api_key)
+            .chain(self.api_keys.iter())
+            .chain(self.proxy.iter().filter_map(|p| p.password.as_ref()))
+    }
+
+    fn secrets_mut(&mut self) -> impl Iterator<Item = &mut String> {
+        std::iter::once(&mut self.api_key)
+            .chain(self.api_keys.iter_mut())
+            .chain(self.proxy.iter_mut().filter_map(|p| p.password.as_mut()))
+    }
+}
+
+/// 按 API 类型覆盖的缓存策略（未设置的字段沿用全局配置）
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct CacheOverride {
+    #[serde(default)]
+    pub enabled: Option<bool>,
+    #[serde(default)]
+    pub ttl_seconds: Option<u64>,
 }
 
 fn default_api_types() -> Vec<ApiType> {
@@ -59,6 +275,38 @@ pub struct GatewayConfig {
     #[serde(default = "default_chat_port")]
     pub chat_port: u16,
     
+    // 各端口监听地址（默认仅本机；0.0.0.0 等非回环地址对局域网开放），修改后重启生效
+    #[serde(default = "default_bind_address")]
+    pub anthropic_bind_address: String,
+    #[serde(default = "default_bind_address")]
+    pub responses_bind_address: String,
+    #[serde(default = "default_bind_address")]
+    pub chat_bind_address: String,
+
+    // 统一端口：单个端口按路径分发三种 API（默认关闭，独立端口仍然可用），修改后重启生效
+    #[serde(default)]
+    pub unified_enabled: bool,
+    #[serde(default = "default_unified_port")]
+    pub unified_port: u16,
+    #[serde(default = "default_bind_address")]
+    pub unified_bind_address: String,
+
+    // HTTPS：配置证书后所有端口均使用 TLS，修改后重启生效
+    #[serde(default)]
+    pub server_tls: Option<ServerTls>,
+
+    // 访问控制（IP 或 CIDR）：denylist 优先；allowlist 非空时只允许列表内的远程地址，本机始终允许
+    #[serde(default)]
+    pub allowed_ips: Vec<String>,
+    #[serde(default)]
+    pub denied_ips: Vec<String>,
+    // 客户端 Key：远程客户端必须通过 x-api-key 或 Authorization: Bearer 提供，本机客户端不要求
+    #[serde(default)]
+    pub client_keys: Vec<ClientKey>,
+    // 对外开放时允许的 CORS 来源（仅监听本机时不限制）
+    #[serde(default)]
+    pub cors_allowed_origins: Vec<String>,
+
     // 三个独立开关
     #[serde(default = "default_true")]
     pub anthropic_enabled: bool,
@@ -76,6 +324,22 @@ pub struct GatewayConfig {
     pub providers: Vec<Provider>,
     #[serde(default = "default_true")]
     pub fallback_enabled: bool,
+
+    // 按 API 类型配置供应商选择策略（默认 weighted）
+    #[serde(default)]
+    pub selection_strategies: HashMap<ApiType, SelectionStrategy>,
+
+    // 全局出站代理（供应商可单独覆盖）
+    #[serde(default)]
+    pub outbound_proxy: Option<OutboundProxy>,
+
+    // 会话亲和：同一会话固定到首次成功的供应商，熔断时解除
+    #[serde(default = "default_true")]
+    pub session_affinity_enabled: bool,
+    #[serde(default = "default_session_affinity_ttl")]
+    pub session_affinity_ttl_seconds: u64,
+    #[serde(default = "default_session_affinity_max_sessions")]
+    pub session_affinity_max_sessions: usize,
     
     // 缓存配置
     #[serde(default)]
@@ -84,19 +348,112 @@ pub struct GatewayConfig {
     pub cache_ttl_seconds: u64,
     #[serde(default = "default_cache_max_entries")]
     pub cache_max_entries: usize,
+    #[serde(default = "default_cache_max_memory_bytes")]
+    pub cache_max_memory_bytes: u64,
+    // 磁盘缓存 (data/cache/)，重启后仍可命中
+    #[serde(default = "default_true")]
+    pub cache_persist_enabled: bool,
+    #[serde(default = "default_cache_max_disk_bytes")]
+    pub cache_max_disk_bytes: u64,
+    #[serde(default = "default_true")]
+    pub cache_compression: bool,
+    // 按 API 类型覆盖缓存开关与 TTL（全局 cache_enabled 为总开关）
+    #[serde(default)]
+    pub cache_overrides: HashMap<ApiType, CacheOverride>,
+    // 缓存准入规则：默认只缓存 temperature == 0 且不含工具定义的请求
+    #[serde(default)]
+    pub cache_allow_nonzero_temperature: bool,
+    #[serde(default)]
+    pub cache_allow_tools: bool,
+    #[serde(default = "default_cache_max_body_bytes")]
+    pub cache_max_body_bytes: u64,
+    // 生成缓存 Key 时忽略的请求字段（点号分隔的 JSON 路径）
+    #[serde(default = "default_cache_ignored_fields")]
+    pub cache_ignored_fields: Vec<String>,
     
+    // 请求体大小上限（字节），超出时直接返回 413，不转发上游
+    #[serde(default = "default_max_request_body_bytes")]
+    pub max_request_body_bytes: u64,
+    // 按 API 类型覆盖请求体大小上限
+    #[serde(default)]
+    pub request_body_limits: HashMap<ApiType, u64>,
+    // 转发前校验请求体（model、messages、max_tokens 等），不合法的请求不计入供应商健康状态
+    #[serde(default = "default_true")]
+    pub request_validation_enabled: bool,
+
+    // 请求改写规则（按顺序执行，可限定 API 类型与供应商）
+    #[serde(default)]
+    pub transform_rules: Vec<TransformRule>,
+
+    // 出站请求敏感信息检测（内置密钥规则 + 自定义 PII 正则）
+    #[serde(default = "default_true")]
+    pub secret_scan_enabled: bool,
+    // 命中后的处理：warn 放行并记录 / redact 脱敏后转发 / block 拒绝请求
+    #[serde(default)]
+    pub secret_scan_policy: SecurityPolicy,
+    // 检测高熵字符串（未知格式的 Token；锁文件哈希、base64 等容易误报，默认关闭）
+    #[serde(default)]
+    pub secret_scan_entropy: bool,
+    #[serde(default)]
+    pub pii_patterns: Vec<PiiPattern>,
+
+    // 脚本插件（Rhai），按顺序在供应商选择前与收到响应后调用
+    #[serde(default)]
+    pub plugins: Vec<PluginConfig>,
+
     // 熔断配置
     #[serde(default = "default_cooldown")]
     pub circuit_breaker_cooldown_seconds: u64,
+
+    // 加载配置时产生的提示（如迁移说明），不写入配置文件
+    #[serde(skip)]
+    pub notices: Vec<String>,
+}
+
+/// 网关 HTTPS 证书
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ServerTls {
+    // PEM 证书链与 PKCS#8 私钥文件
+    pub cert_path: String,
+    pub key_path: String,
+}
+
+/// 局域网客户端使用的访问 Key
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ClientKey {
+    // 用途说明（如使用者姓名）
+    pub name: String,
+    // 保存时加密，读取时脱敏
+    pub key: String,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
 }
 
+fn default_bind_address() -> String { "127.0.0.1".to_string() }
+// 加入监听地址配置之前的版本固定监听的地址
+const LEGACY_BIND_ADDRESS: &str = "0.0.0.0";
 fn default_anthropic_port() -> u16 { 12345 }
 fn default_responses_port() -> u16 { 12346 }
 fn default_chat_port() -> u16 { 12347 }
+fn default_unified_port() -> u16 { 12340 }
 fn default_true() -> bool { true }
 fn default_cache_ttl() -> u64 { 600 } // 10 分钟
 fn default_cache_max_entries() -> usize { 1000 }
+fn default_cache_max_memory_bytes() -> u64 { 64 * 1024 * 1024 } // 64 MB
+fn default_cache_max_disk_bytes() -> u64 { 512 * 1024 * 1024 } // 512 MB
+fn default_cache_max_body_bytes() -> u64 { 4 * 1024 * 1024 } // 4 MB
 fn default_cooldown() -> u64 { 60 }
+fn default_max_request_body_bytes() -> u64 { 32 * 1024 * 1024 } // 32 MB（可容纳音频上传）
+fn default_session_affinity_ttl() -> u64 { 3600 } // 1 小时
+fn default_session_affinity_max_sessions() -> usize { 10000 }
+fn default_cache_ignored_fields() -> Vec<String> {
+    vec![
+        "stream".to_string(),
+        "stream_options".to_string(),
+        "metadata.user_id".to_string(),
+        "user".to_string(),
+    ]
+}
 
 impl Default for GatewayConfig {
     fn default() -> Self {
@@ -104,6 +461,17 @@ impl Default for GatewayConfig {
             anthropic_port: 12345,
             responses_port: 12346,
             chat_port: 12347,
+            anthropic_bind_address: default_bind_address(),
+            responses_bind_address: default_bind_address(),
+            chat_bind_address: default_bind_address(),
+            unified_enabled: false,
+            unified_port: default_unified_port(),
+            unified_bind_address: default_bind_address(),
+            server_tls: None,
+            allowed_ips: vec![],
+            denied_ips: vec![],
+            client_keys: vec![],
+            cors_allowed_origins: vec![],
             anthropic_enabled: true,
             responses_enabled: true,
             chat_enabled: true,
@@ -111,10 +479,34 @@ impl Default for GatewayConfig {
             enabled: true,
             providers: vec![],
             fallback_enabled: true,
+            selection_strategies: HashMap::new(),
+            outbound_proxy: None,
+            session_affinity_enabled: true,
+            session_affinity_ttl_seconds: default_session_affinity_ttl(),
+            session_affinity_max_sessions: default_session_affinity_max_sessions(),
             cache_enabled: true,
             cache_ttl_seconds: 600,
             cache_max_entries: 1000,
+            cache_max_memory_bytes: default_cache_max_memory_bytes(),
+            cache_persist_enabled: true,
+            cache_max_disk_bytes: default_cache_max_disk_bytes(),
+            cache_compression: true,
+            cache_overrides: HashMap::new(),
+            cache_allow_nonzero_temperature: false,
+            cache_allow_tools: false,
+            cache_max_body_bytes: default_cache_max_body_bytes(),
+            cache_ignored_fields: default_cache_ignored_fields(),
+            max_request_body_bytes: default_max_request_body_bytes(),
+            request_body_limits: HashMap::new(),
+            request_validation_enabled: true,
+            transform_rules: vec![],
+            secret_scan_enabled: true,
+            secret_scan_policy: SecurityPolicy::default(),
+            secret_scan_entropy: false,
+            pii_patterns: vec![],
+            plugins: vec![],
             circuit_breaker_cooldown_seconds: 60,
+            notices: vec![],
         }
     }
 }
@@ -125,16 +517,67 @@ impl GatewayConfig {
             return Ok(Self::default());
         }
         let content = fs::read_to_string(&path).context("Failed to read gateway config")?;
-        let mut config: GatewayConfig = serde_json::from_str(&content).context("Failed to parse gateway config")?;
-        
+        let raw: serde_json::Value = serde_json::from_str(&content).context("Failed to parse gateway config")?;
+        let mut config: GatewayConfig = serde_json::from_value(raw.clone()).context("Failed to parse gateway config")?;
+
+        // 解密密钥；解密失败的密钥保留原密文（不会被使用），此时不自动保存配置，避免覆盖原密文
+        let has_plaintext = config.secrets_mut().any(|k| secrets::needs_encryption(k));
+        let has_encrypted = config.secrets_mut().any(|k| secrets::is_encrypted(k));
+        let mut decrypt_failures = 0;
+        if has_encrypted {
+            let store = SecretStore::open(secrets_dir(path.as_ref()))?;
+            for key in config.secrets_mut() {
+                match store.decrypt(key) {
+                    Ok(plain) => *key = plain,
+                    Err(e) => {
+                        eprintln!("Failed to decrypt gateway secret: {}", e);
+                        decrypt_failures += 1;
+                    }
+                }
+            }
+        }
+        if decrypt_failures > 0 {
+            config.notices.push(format!(
+                "{} gateway secret(s) could not be decrypted and are disabled; \
+                 re-enter them to replace the stored ciphertext.",
+                decrypt_failures
+            ));
+        }
+
+        // 旧配置中的明文密钥立即加密保存
+        let mut migrated = has_plaintext;
         // 自动迁移：如果旧的 port 字段有值，迁移到新字段
         if config.port != 0 {
             config.anthropic_port = config.port;
             config.port = 0;
-            // 保存迁移后的配置
-            let _ = config.save(&path);
+            migrated = true;
             println!("Migrated gateway config: port {} -> anthropic_port", config.anthropic_port);
         }
+
+        // 自动迁移：旧版本固定监听 0.0.0.0，没有监听地址字段的配置保持原有监听地址
+        if raw.get("anthropic_bind_address").is_none() {
+            for bind_address in [
+                &mut config.anthropic_bind_address,
+                &mut config.responses_bind_address,
+                &mut config.chat_bind_address,
+            ] {
+                *bind_address = LEGACY_BIND_ADDRESS.to_string();
+            }
+            migrated = true;
+            config.notices.push(format!(
+                "Existing ports keep listening on {}. Remote clients now need a client key; \
+                 set the bind address to 127.0.0.1 to accept connections from this machine only.",
+                LEGACY_BIND_ADDRESS
+            ));
+        }
+
+        if migrated && decrypt_failures == 0 {
+            // 保存迁移后的配置
+            match config.save(&path) {
+                Ok(()) => println!("Saved migrated gateway config"),
+                Err(e) => eprintln!("Failed to save migrated gateway config: {}", e),
+            }
+        }
         
         // 自动迁移：为没有 api_types 的供应商添加默认值
         for provider in &mut config.providers {
@@ -152,17 +595,154 @@ impl GatewayConfig {
             }
         }
         
+        config.resolve_keys();
         Ok(config)
     }
 
+    /// 解析所有供应商的 API Key（加载配置与保存新配置后调用）
+    pub fn resolve_keys(&mut self) {
+        for provider in &mut self.providers {
+            provider.resolve_keys();
+        }
+    }
+
+    /// 保存配置；明文密钥使用主密钥加密后写入
     pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
-        let content = serde_json::to_string_pretty(self).context("Failed to serialize gateway config")?;
+        let mut sealed = self.clone();
+        if sealed.secrets_mut().any(|k| secrets::needs_encryption(k)) {
+            let store = SecretStore::open(secrets_dir(path.as_ref()))?;
+            for key in sealed.secrets_mut() {
+                *key = store.seal(key)?;
+            }
+        }
+        let content = serde_json::to_string_pretty(&sealed).context("Failed to serialize gateway config")?;
         if let Some(parent) = path.as_ref().parent() {
             fs::create_dir_all(parent).context("Failed to create config directory")?;
         }
         fs::write(path, content).context("Failed to write gateway config")
     }
     
+    /// 返回给前端的配置：密钥脱敏
+    pub fn masked(&self) -> GatewayConfig {
+        let mut masked = self.clone();
+        for key in masked.secrets_mut() {
+            *key = secrets::mask(key);
+        }
+        masked
+    }
+
+    /// 前端提交的配置中，未修改的密钥仍是脱敏值，按脱敏值中的标识还原为当前密钥
+    pub fn restore_masked_keys(&mut self, current: &GatewayConfig) -> Result<()> {
+        if let Some(password) = self.outbound_proxy.as_mut().and_then(|p| p.password.as_mut()) {
+            if secrets::is_masked(password) {
+                let existing = current.outbound_proxy.iter().filter_map(|p| p.password.as_ref());
+                *password = secrets::unmask(password, existing)
+                    .context("Failed to restore proxy password")?
+                    .clone();
+            }
+        }
+
+        for client_key in &mut self.client_keys {
+            if secrets::is_masked(&client_key.key) {
+                let existing = current.client_keys.iter().map(|k| &k.key);
+                client_key.key = secrets::unmask(&client_key.key, existing)
+                    .with_context(|| format!("Failed to restore client key {}", client_key.name))?
+                    .clone();
+            }
+        }
+
+        for provider in &mut self.providers {
+            let existing: Vec<&String> = current
+                .providers
+                .iter()
+                .filter(|p| p.id == provider.id)
+                .flat_map(|p| p.secrets())
+                .collect();
+            let name = provider.name.clone();
+            for key in provider.secrets_mut() {
+                if !secrets::is_masked(key) {
+                    continue;
+                }
+                *key = secrets::unmask(key, existing.iter().copied())
+                    .with_context(|| format!("Failed to restore secret for provider {}", name))?
+                    .clone();
+            }
+        }
+        Ok(())
+    }
+
+    /// 所有需要加密保存的字段（全局代理密码、客户端 Key 与各供应商的密钥）
+    fn secrets_mut(&mut self) -> impl Iterator<Item = &mut String> {
+        self.outbound_proxy
+            .iter_mut()
+            .filter_map(|p| p.password.as_mut())
+            .chain(self.client_keys.iter_mut().map(|k| &mut k.key))
+            .chain(self.providers.iter_mut().flat_map(|p| p.secrets_mut()))
+    }
+
+    /// 指定 API 类型端口的监听地址
+    pub fn bind_address(&self, api_type: &ApiType) -> &str {
+        match api_type {
+            ApiType::Anthropic => &self.anthropic_bind_address,
+            ApiType::OpenAIResponses => &self.responses_bind_address,
+            ApiType::OpenAIChat => &self.chat_bind_address,
+        }
+    }
+
+    /// 获取指定 API 类型的缓存策略：(是否启用, TTL 秒)
+    pub fn cache_policy(&self, api_type: &ApiType) -> (bool, u64) {
+        let overrides = self.cache_overrides.get(api_type);
+        let enabled = self.cache_enabled
+            && overrides.and_then(|o| o.enabled).unwrap_or(true);
+        let ttl = overrides
+            .and_then(|o| o.ttl_seconds)
+            .unwrap_or(self.cache_ttl_seconds);
+        (enabled, ttl)
+    }
+
+    /// 获取指定 API 类型的供应商选择策略
+    pub fn selection_strategy(&self, api_type: &ApiType) -> SelectionStrategy {
+        self.selection_strategies.get(api_type).copied().unwrap_or_default()
+    }
+
+    /// 会话亲和设置
+    pub fn affinity_settings(&self) -> AffinitySettings {
+        AffinitySettings {
+            enabled: self.session_affinity_enabled,
+            ttl_seconds: self.session_affinity_ttl_seconds.max(1),
+            max_sessions: self.session_affinity_max_sessions,
+        }
+    }
+
+    /// 指定 API 类型的请求体大小上限（字节）
+    pub fn request_body_limit(&self, api_type: &ApiType) -> usize {
+        let limit = self
+            .request_body_limits
+            .get(api_type)
+            .copied()
+            .unwrap_or(self.max_request_body_bytes);
+        usize::try_from(limit.max(1)).unwrap_or(usize::MAX)
+    }
+
+    /// 敏感信息检测设置
+    pub fn scan_settings(&self) -> ScanSettings {
+        ScanSettings {
+            enabled: self.secret_scan_enabled,
+            policy: self.secret_scan_policy,
+            entropy: self.secret_scan_entropy,
+            pii_patterns: self.pii_patterns.clone(),
+        }
+    }
+
+    /// 缓存准入规则
+    pub fn cache_admission(&self) -> CacheAdmission {
+        CacheAdmission {
+            allow_nonzero_temperature: self.cache_allow_nonzero_temperature,
+            allow_tools: self.cache_allow_tools,
+            max_body_bytes: self.cache_max_body_bytes,
+        }
+    }
+
     /// 获取支持指定 API 类型的供应商列表
     pub fn get_providers_for_api_type(&self, api_type: &ApiType) -> Vec<&Provider> {
         self.providers
@@ -171,3 +751,8 @@ impl GatewayConfig {
             .collect()
     }
 }
+
+/// 主密钥与配置文件位于同一目录
+fn secrets_dir(config_path: &Path) -> &Path {
+    config_path.parent().unwrap_or_else(|| Path::new("."))
+}
@@ -2,11 +2,12 @@
 // 参考: https://github.com/CassiopeiaCode/b4u2cc
 
 use serde_json::{json, Value};
-use std::collections::HashMap;
+use crate::gateway::config::{ApiType, Provider};
 
 /// 将 Anthropic Messages API 请求转换为 OpenAI Chat Completions 格式
-/// model_mapping: 模型名称映射表，将请求中的模型名映射到目标模型名
-pub fn anthropic_to_openai(body: &[u8], model_mapping: &HashMap<String, String>) -> Result<Vec<u8>, String> {
+/// 使用供应商的模型映射表与转换选项（是否发送 reasoning_effort、top_k）
+pub fn anthropic_to_openai(body: &[u8], provider: &Provider) -> Result<Vec<u8>, String> {
+    let model_mapping = &provider.model_mapping;
     let anthropic_req: Value = serde_json::from_slice(body)
         .map_err(|e| format!("Failed to parse Anthropic request: {}", e))?;
     
@@ -114,106 +115,347 @@ pub fn anthropic_to_openai(body: &[u8], model_mapping: &HashMap<String, String>)
         .and_then(|m| m.as_u64())
         .unwrap_or(4096);
     
-    let temperature = anthropic_req.get("temperature")
-        .and_then(|t| t.as_f64())
-        .unwrap_or(1.0);
-    
     let stream = anthropic_req.get("stream")
         .and_then(|s| s.as_bool())
         .unwrap_or(false);
     
-    let openai_req = json!({
+    let mut openai_req = json!({
         "model": model,
         "messages": openai_messages,
         "max_tokens": max_tokens,
-        "temperature": temperature,
         "stream": stream
     });
+    // 流式响应在最后一个 chunk 中返回用量（客户端只看到转换后的事件）
+    if stream {
+        openai_req["stream_options"] = json!({"include_usage": true});
+    }
+
+    // 采样参数：仅在客户端显式指定时传递，交由上游使用默认值
+    // top_k 不属于 OpenAI 接口，仅在供应商声明支持时转发
+    let top_k = provider.forward_top_k.then_some("top_k");
+    for key in ["temperature", "top_p"].into_iter().chain(top_k) {
+        if let Some(value) = anthropic_req.get(key).filter(|v| v.is_number()) {
+            openai_req[key] = value.clone();
+        }
+    }
+
+    let stop_sequences = stop_sequences_from_request(&anthropic_req);
+    if !stop_sequences.is_empty() {
+        openai_req["stop"] = json!(stop_sequences);
+    }
+
+    if let Some(user_id) = anthropic_req
+        .get("metadata")
+        .and_then(|m| m.get("user_id"))
+        .and_then(|u| u.as_str())
+    {
+        openai_req["user"] = json!(user_id);
+    }
+
+    // 扩展思考：将 thinking.budget_tokens 映射为 reasoning_effort（需供应商显式开启）
+    if provider.send_reasoning_effort {
+        if let Some(effort) = anthropic_req.get("thinking").and_then(thinking_to_reasoning_effort) {
+            openai_req["reasoning_effort"] = json!(effort);
+        }
+    }
     
     serde_json::to_vec(&openai_req)
         .map_err(|e| format!("Failed to serialize OpenAI request: {}", e))
 }
 
-/// 将 OpenAI SSE 事件转换为 Anthropic SSE 格式
-/// 输入：OpenAI 的 `data: {...}` 格式
-/// 输出：Anthropic 的 `event: xxx\ndata: {...}` 格式
-pub fn openai_sse_to_anthropic(openai_line: &str, message_id: &str, model: &str, is_first: bool) -> Vec<String> {
-    let mut events = Vec::new();
-    
-    // 跳过空行和非数据行
-    let data = if openai_line.starts_with("data: ") {
-        &openai_line[6..]
-    } else {
-        return events;
-    };
-    
-    // 处理 [DONE]
-    if data.trim() == "[DONE]" {
-        events.push(format!("event: message_stop\ndata: {{}}"));
-        return events;
+/// 提取 Anthropic 请求中的 stop_sequences（用于响应中回填匹配的停止序列）
+pub fn extract_stop_sequences(body: &[u8]) -> Vec<String> {
+    serde_json::from_slice::<Value>(body)
+        .map(|v| stop_sequences_from_request(&v))
+        .unwrap_or_default()
+}
+
+fn stop_sequences_from_request(request: &Value) -> Vec<String> {
+    request
+        .get("stop_sequences")
+        .and_then(|s| s.as_array())
+        .map(|arr| {
+            arr.iter()
+                .filter_map(|s| s.as_str().map(|s| s.to_string()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// 将 OpenAI finish_reason 映射为 Anthropic stop_reason 与 stop_sequence
+/// matched_stop: 上游回报的命中停止序列（vLLM `stop_reason` / SGLang `matched_stop`），
+/// 仅当其属于请求的 stop_sequences 时报告为 `stop_sequence`
+pub fn map_finish_reason(
+    finish_reason: &str,
+    matched_stop: Option<&str>,
+    stop_sequences: &[String],
+) -> (&'static str, Option<String>) {
+    match finish_reason {
+        "length" => ("max_tokens", None),
+        "tool_calls" | "function_call" => ("tool_use", None),
+        "content_filter" => ("refusal", None),
+        "stop" => match matched_stop.filter(|m| stop_sequences.iter().any(|s| s == m)) {
+            Some(sequence) => ("stop_sequence", Some(sequence.to_string())),
+            None => ("end_turn", None),
+        },
+        _ => ("end_turn", None),
     }
-    
-    // 解析 OpenAI 响应
-    let openai_resp: Value = match serde_json::from_str(data) {
-        Ok(v) => v,
-        Err(_) => return events,
-    };
-    
-    // 如果是第一个事件，发送 message_start
-    if is_first {
-        events.push(format!(r#"event: message_start
-data: {{"type":"message_start","message":{{"id":"{}","type":"message","role":"assistant","content":[],"model":"{}","stop_reason":null,"stop_sequence":null,"usage":{{"input_tokens":0,"output_tokens":0}}}}}}"#, 
-            message_id, model));
-        
-        // 发送 content_block_start
-        events.push(format!(r#"event: content_block_start
-data: {{"type":"content_block_start","index":0,"content_block":{{"type":"text","text":""}}}}"#));
+}
+
+/// 从 OpenAI choice 中取出 finish_reason 并映射
+fn stop_reason_from_choice(choice: &Value, stop_sequences: &[String]) -> Option<(&'static str, Option<String>)> {
+    let finish_reason = choice.get("finish_reason").and_then(|f| f.as_str())?;
+    let matched_stop = choice
+        .get("stop_reason")
+        .or_else(|| choice.get("matched_stop"))
+        .and_then(|m| m.as_str());
+    Some(map_finish_reason(finish_reason, matched_stop, stop_sequences))
+}
+
+/// 将 Anthropic `thinking` 配置映射为 OpenAI `reasoning_effort`
+/// `{"type":"enabled","budget_tokens":N}`：N < 4096 为 low，N < 16384 为 medium，其余为 high
+fn thinking_to_reasoning_effort(thinking: &Value) -> Option<&'static str> {
+    if thinking.get("type").and_then(|t| t.as_str()) != Some("enabled") {
+        return None;
     }
-    
-    // 提取 delta content
-    if let Some(choices) = openai_resp.get("choices").and_then(|c| c.as_array()) {
-        if let Some(choice) = choices.first() {
-            // 检查是否完成
-            if let Some(finish_reason) = choice.get("finish_reason").and_then(|f| f.as_str()) {
-                if finish_reason == "stop" || finish_reason == "end_turn" || finish_reason == "length" {
-                    events.push(format!(r#"event: content_block_stop
-data: {{"type":"content_block_stop","index":0}}"#));
-                    
-                    events.push(format!(r#"event: message_delta
-data: {{"type":"message_delta","delta":{{"stop_reason":"end_turn","stop_sequence":null}},"usage":{{"output_tokens":0}}}}"#));
-                    
-                    events.push(format!(r#"event: message_stop
-data: {{"type":"message_stop"}}"#));
-                    return events;
-                }
+    let budget = thinking.get("budget_tokens").and_then(|b| b.as_u64()).unwrap_or(0);
+    Some(match budget {
+        0..=4095 => "low",
+        4096..=16383 => "medium",
+        _ => "high",
+    })
+}
+
+/// 流式内容块类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BlockKind {
+    Thinking,
+    Text,
+}
+
+/// 将 OpenAI SSE 流转换为 Anthropic SSE 格式的有状态转换器
+/// 输入：OpenAI 的 `data: {...}` 行
+/// 输出：Anthropic 的 `event: xxx\ndata: {...}` 事件
+/// 推理内容 (`reasoning_content` / `reasoning`) 转换为 `thinking` 内容块，正文转换为 `text` 内容块，
+/// 内容块索引按出现顺序递增
+/// 收到 finish_reason 时只记录停止原因，等 [DONE] / 流结束时再输出收尾事件，
+/// 以便带上其后 `include_usage` chunk 中的用量
+pub struct SseConverter {
+    message_id: String,
+    model: String,
+    started: bool,
+    finished: bool,
+    next_index: usize,
+    open_block: Option<(BlockKind, usize)>,
+    stop_sequences: Vec<String>,
+    stop_reason: &'static str,
+    stop_sequence: Option<String>,
+    stop_received: bool,
+    input_tokens: u64,
+    output_tokens: u64,
+}
+
+impl SseConverter {
+    pub fn new(message_id: &str, model: &str, stop_sequences: Vec<String>) -> Self {
+        Self {
+            message_id: message_id.to_string(),
+            model: model.to_string(),
+            started: false,
+            finished: false,
+            next_index: 0,
+            open_block: None,
+            stop_sequences,
+            stop_reason: "end_turn",
+            stop_sequence: None,
+            stop_received: false,
+            input_tokens: 0,
+            output_tokens: 0,
+        }
+    }
+
+    /// 转换一行 OpenAI SSE 数据
+    pub fn convert_line(&mut self, openai_line: &str) -> Vec<String> {
+        let mut events = Vec::new();
+        if self.finished {
+            return events;
+        }
+
+        // 跳过空行和非数据行
+        let Some(data) = openai_line.strip_prefix("data:") else {
+            return events;
+        };
+        let data = data.trim();
+
+        // 处理 [DONE]
+        if data == "[DONE]" {
+            return self.finish();
+        }
+
+        // 解析 OpenAI 响应
+        let openai_resp: Value = match serde_json::from_str(data) {
+            Ok(v) => v,
+            Err(_) => return events,
+        };
+
+        self.ensure_started(&mut events);
+
+        // 用量：include_usage 时在 finish_reason 之后单独发送（choices 为空）
+        if let Some(usage) = openai_resp.get("usage").filter(|u| u.is_object()) {
+            if let Some(input) = usage.get("prompt_tokens").and_then(|t| t.as_u64()) {
+                self.input_tokens = input;
             }
-            
-            // 提取文本 delta
-            if let Some(delta) = choice.get("delta") {
-                if let Some(content) = delta.get("content").and_then(|c| c.as_str()) {
-                    if !content.is_empty() {
-                        let escaped = serde_json::to_string(content).unwrap_or_default();
-                        // 移除外层引号
-                        let escaped = &escaped[1..escaped.len()-1];
-                        events.push(format!(r#"event: content_block_delta
-data: {{"type":"content_block_delta","index":0,"delta":{{"type":"text_delta","text":"{}"}}}}"#, escaped));
-                    }
-                }
+            if let Some(output) = usage.get("completion_tokens").and_then(|t| t.as_u64()) {
+                self.output_tokens = output;
             }
         }
+
+        let Some(choice) = openai_resp
+            .get("choices")
+            .and_then(|c| c.as_array())
+            .and_then(|c| c.first())
+        else {
+            return events;
+        };
+
+        if let Some(delta) = choice.get("delta") {
+            // 推理内容：不同上游字段名不同
+            let reasoning = delta
+                .get("reasoning_content")
+                .or_else(|| delta.get("reasoning"))
+                .and_then(|r| r.as_str())
+                .unwrap_or("");
+            if !reasoning.is_empty() {
+                let index = self.ensure_block(BlockKind::Thinking, &mut events);
+                events.push(sse_event("content_block_delta", json!({
+                    "type": "content_block_delta",
+                    "index": index,
+                    "delta": {"type": "thinking_delta", "thinking": reasoning}
+                })));
+            }
+
+            let content = delta.get("content").and_then(|c| c.as_str()).unwrap_or("");
+            if !content.is_empty() {
+                let index = self.ensure_block(BlockKind::Text, &mut events);
+                events.push(sse_event("content_block_delta", json!({
+                    "type": "content_block_delta",
+                    "index": index,
+                    "delta": {"type": "text_delta", "text": content}
+                })));
+            }
+        }
+
+        // 记录停止原因，收尾事件在 [DONE] / 流结束时输出
+        if let Some((stop_reason, stop_sequence)) = stop_reason_from_choice(choice, &self.stop_sequences) {
+            self.stop_reason = stop_reason;
+            self.stop_sequence = stop_sequence;
+            self.stop_received = true;
+        }
+
+        events
+    }
+
+    /// 上游是否已发送结束标记（finish_reason 或 [DONE]）
+    pub fn completed(&self) -> bool {
+        self.finished || self.stop_received
+    }
+
+    /// 结束流：关闭当前内容块并输出 message_delta / message_stop（已结束时返回空）
+    pub fn finish(&mut self) -> Vec<String> {
+        let mut events = Vec::new();
+        if self.finished {
+            return events;
+        }
+        self.ensure_started(&mut events);
+
+        // 保证至少有一个内容块
+        if self.next_index == 0 {
+            self.ensure_block(BlockKind::Text, &mut events);
+        }
+        self.close_block(&mut events);
+
+        events.push(sse_event("message_delta", json!({
+            "type": "message_delta",
+            "delta": {"stop_reason": self.stop_reason, "stop_sequence": self.stop_sequence},
+            "usage": {"input_tokens": self.input_tokens, "output_tokens": self.output_tokens}
+        })));
+        events.push(sse_event("message_stop", json!({"type": "message_stop"})));
+        self.finished = true;
+        events
+    }
+
+    fn ensure_started(&mut self, events: &mut Vec<String>) {
+        if self.started {
+            return;
+        }
+        self.started = true;
+        events.push(sse_event("message_start", json!({
+            "type": "message_start",
+            "message": {
+                "id": self.message_id,
+                "type": "message",
+                "role": "assistant",
+                "content": [],
+                "model": self.model,
+                "stop_reason": null,
+                "stop_sequence": null,
+                "usage": {"input_tokens": 0, "output_tokens": 0}
+            }
+        })));
+    }
+
+    /// 确保当前打开的是指定类型的内容块，返回其索引
+    fn ensure_block(&mut self, kind: BlockKind, events: &mut Vec<String>) -> usize {
+        if let Some((open_kind, index)) = self.open_block {
+            if open_kind == kind {
+                return index;
+            }
+        }
+        self.close_block(events);
+
+        let index = self.next_index;
+        self.next_index += 1;
+        let content_block = match kind {
+            BlockKind::Thinking => json!({"type": "thinking", "thinking": "", "signature": ""}),
+            BlockKind::Text => json!({"type": "text", "text": ""}),
+        };
+        events.push(sse_event("content_block_start", json!({
+            "type": "content_block_start",
+            "index": index,
+            "content_block": content_block
+        })));
+        self.open_block = Some((kind, index));
+        index
+    }
+
+    fn close_block(&mut self, events: &mut Vec<String>) {
+        if let Some((_, index)) = self.open_block.take() {
+            events.push(sse_event("content_block_stop", json!({
+                "type": "content_block_stop",
+                "index": index
+            })));
+        }
     }
-    
-    events
+}
+
+fn sse_event(event: &str, data: Value) -> String {
+    format!("event: {}\ndata: {}", event, data)
 }
 
 /// 将完整的 OpenAI 非流式响应转换为 Anthropic 格式
-pub fn openai_response_to_anthropic(openai_body: &[u8], model: &str) -> Result<Vec<u8>, String> {
+pub fn openai_response_to_anthropic(
+    openai_body: &[u8],
+    model: &str,
+    stop_sequences: &[String],
+) -> Result<Vec<u8>, String> {
     let openai_resp: Value = serde_json::from_slice(openai_body)
         .map_err(|e| format!("Failed to parse OpenAI response: {}", e))?;
     
     let message_id = format!("msg_{}", uuid::Uuid::new_v4().to_string().replace("-", "")[..24].to_string());
     
     let mut content_text = String::new();
+    let mut reasoning_text = String::new();
+    let mut stop_reason = "end_turn";
+    let mut stop_sequence = None;
     let mut output_tokens = 0u64;
     let mut input_tokens = 0u64;
     
@@ -226,27 +468,46 @@ pub fn openai_response_to_anthropic(openai_body: &[u8], model: &str) -> Result<V
     // 提取 content
     if let Some(choices) = openai_resp.get("choices").and_then(|c| c.as_array()) {
         if let Some(choice) = choices.first() {
+            if let Some((reason, sequence)) = stop_reason_from_choice(choice, stop_sequences) {
+                stop_reason = reason;
+                stop_sequence = sequence;
+            }
             if let Some(message) = choice.get("message") {
                 if let Some(content) = message.get("content").and_then(|c| c.as_str()) {
                     content_text = content.to_string();
                 }
+                if let Some(reasoning) = message
+                    .get("reasoning_content")
+                    .or_else(|| message.get("reasoning"))
+                    .and_then(|r| r.as_str())
+                {
+                    reasoning_text = reasoning.to_string();
+                }
             }
         }
     }
     
+    let mut content = Vec::new();
+    if !reasoning_text.is_empty() {
+        content.push(json!({
+            "type": "thinking",
+            "thinking": reasoning_text,
+            "signature": ""
+        }));
+    }
+    content.push(json!({
+        "type": "text",
+        "text": content_text
+    }));
+
     let anthropic_resp = json!({
         "id": message_id,
         "type": "message",
         "role": "assistant",
-        "content": [
-            {
-                "type": "text",
-                "text": content_text
-            }
-        ],
+        "content": content,
         "model": model,
-        "stop_reason": "end_turn",
-        "stop_sequence": null,
+        "stop_reason": stop_reason,
+        "stop_sequence": stop_sequence,
         "usage": {
             "input_tokens": input_tokens,
             "output_tokens": output_tokens
@@ -256,3 +517,268 @@ pub fn openai_response_to_anthropic(openai_body: &[u8], model: &str) -> Result<V
     serde_json::to_vec(&anthropic_resp)
         .map_err(|e| format!("Failed to serialize Anthropic response: {}", e))
 }
+
+/// 将缓存的非流式响应重放为 SSE 事件（流式客户端命中非流式缓存时使用）
+/// 按端口协议生成对应的事件序列；响应体无法识别时返回 None
+pub fn replay_as_sse(api_type: &ApiType, body: &[u8]) -> Option<Vec<String>> {
+    let resp: Value = serde_json::from_slice(body).ok()?;
+    match api_type {
+        ApiType::Anthropic => replay_anthropic_message(&resp),
+        ApiType::OpenAIChat => replay_chat_completion(&resp),
+        ApiType::OpenAIResponses => replay_responses(&resp),
+    }
+}
+
+fn replay_anthropic_message(message: &Value) -> Option<Vec<String>> {
+    if message.get("type").and_then(|t| t.as_str()) != Some("message") {
+        return None;
+    }
+    let mut events = Vec::new();
+
+    let mut start_message = message.clone();
+    start_message["content"] = json!([]);
+    start_message["stop_reason"] = Value::Null;
+    start_message["stop_sequence"] = Value::Null;
+    events.push(sse_event("message_start", json!({
+        "type": "message_start",
+        "message": start_message
+    })));
+
+    let blocks = message.get("content").and_then(|c| c.as_array()).cloned().unwrap_or_default();
+    for (index, block) in blocks.iter().enumerate() {
+        let block_type = block.get("type").and_then(|t| t.as_str()).unwrap_or("");
+        let (start_block, deltas) = match block_type {
+            "text" => (
+                json!({"type": "text", "text": ""}),
+                vec![json!({"type": "text_delta", "text": block.get("text").cloned().unwrap_or(json!(""))})],
+            ),
+            "thinking" => {
+                let mut deltas = vec![json!({
+                    "type": "thinking_delta",
+                    "thinking": block.get("thinking").cloned().unwrap_or(json!(""))
+                })];
+                if let Some(signature) = block.get("signature").and_then(|s| s.as_str()).filter(|s| !s.is_empty()) {
+                    deltas.push(json!({"type": "signature_delta", "signature": signature}));
+                }
+                (json!({"type": "thinking", "thinking": "", "signature": ""}), deltas)
+            }
+            "tool_use" => {
+                let mut start_block = block.clone();
+                start_block["input"] = json!({});
+                let input = block.get("input").cloned().unwrap_or(json!({}));
+                (
+                    start_block,
+                    vec![json!({"type": "input_json_delta", "partial_json": input.to_string()})],
+                )
+            }
+            _ => (block.clone(), Vec::new()),
+        };
+
+        events.push(sse_event("content_block_start", json!({
+            "type": "content_block_start",
+            "index": index,
+            "content_block": start_block
+        })));
+        for delta in deltas {
+            events.push(sse_event("content_block_delta", json!({
+                "type": "content_block_delta",
+                "index": index,
+                "delta": delta
+            })));
+        }
+        events.push(sse_event("content_block_stop", json!({
+            "type": "content_block_stop",
+            "index": index
+        })));
+    }
+
+    let output_tokens = message
+        .get("usage")
+        .and_then(|u| u.get("output_tokens"))
+        .cloned()
+        .unwrap_or(json!(0));
+    events.push(sse_event("message_delta", json!({
+        "type": "message_delta",
+        "delta": {
+            "stop_reason": message.get("stop_reason").cloned().unwrap_or(json!("end_turn")),
+            "stop_sequence": message.get("stop_sequence").cloned().unwrap_or(Value::Null)
+        },
+        "usage": {"output_tokens": output_tokens}
+    })));
+    events.push(sse_event("message_stop", json!({"type": "message_stop"})));
+    Some(events)
+}
+
+fn replay_chat_completion(completion: &Value) -> Option<Vec<String>> {
+    let choices = completion.get("choices").and_then(|c| c.as_array())?;
+    let mut events = Vec::new();
+
+    let chunk = |choices: Value, usage: Option<&Value>| {
+        let mut chunk = json!({
+            "id": completion.get("id").cloned().unwrap_or(json!("")),
+            "object": "chat.completion.chunk",
+            "created": completion.get("created").cloned().unwrap_or(json!(0)),
+            "model": completion.get("model").cloned().unwrap_or(json!("")),
+            "choices": choices
+        });
+        if let Some(usage) = usage {
+            chunk["usage"] = usage.clone();
+        }
+        format!("data: {}", chunk)
+    };
+
+    for (i, choice) in choices.iter().enumerate() {
+        let index = choice.get("index").cloned().unwrap_or(json!(i));
+        let message = choice.get("message").cloned().unwrap_or(json!({}));
+
+        let mut delta = json!({"role": "assistant"});
+        for key in ["content", "reasoning_content", "refusal"] {
+            if let Some(value) = message.get(key).filter(|v| !v.is_null()) {
+                delta[key] = value.clone();
+            }
+        }
+        if let Some(tool_calls) = message.get("tool_calls").and_then(|t| t.as_array()) {
+            let indexed: Vec<Value> = tool_calls
+                .iter()
+                .enumerate()
+                .map(|(ti, call)| {
+                    let mut call = call.clone();
+                    call["index"] = json!(ti);
+                    call
+                })
+                .collect();
+            delta["tool_calls"] = json!(indexed);
+        }
+
+        events.push(chunk(json!([{"index": index, "delta": delta, "finish_reason": null}]), None));
+        events.push(chunk(
+            json!([{"index": index, "delta": {}, "finish_reason": choice.get("finish_reason").cloned().unwrap_or(json!("stop"))}]),
+            None,
+        ));
+    }
+
+    if let Some(usage) = completion.get("usage") {
+        events.push(chunk(json!([]), Some(usage)));
+    }
+    events.push("data: [DONE]".to_string());
+    Some(events)
+}
+
+fn replay_responses(response: &Value) -> Option<Vec<String>> {
+    if response.get("object").and_then(|o| o.as_str()) != Some("response") {
+        return None;
+    }
+    let mut events = Vec::new();
+    let mut sequence = 0u64;
+    let mut push = |events: &mut Vec<String>, event_type: &str, mut data: Value| {
+        data["type"] = json!(event_type);
+        data["sequence_number"] = json!(sequence);
+        sequence += 1;
+        events.push(sse_event(event_type, data));
+    };
+
+    let mut in_progress = response.clone();
+    in_progress["status"] = json!("in_progress");
+    in_progress["output"] = json!([]);
+    push(&mut events, "response.created", json!({"response": in_progress}));
+
+    let output = response.get("output").and_then(|o| o.as_array()).cloned().unwrap_or_default();
+    for (output_index, item) in output.iter().enumerate() {
+        let item_id = item.get("id").cloned().unwrap_or(json!(""));
+        let mut added = item.clone();
+        if item.get("type").and_then(|t| t.as_str()) == Some("message") {
+            added["content"] = json!([]);
+            added["status"] = json!("in_progress");
+        }
+        push(&mut events, "response.output_item.added", json!({
+            "output_index": output_index,
+            "item": added
+        }));
+
+        let parts = item.get("content").and_then(|c| c.as_array()).cloned().unwrap_or_default();
+        for (content_index, part) in parts.iter().enumerate() {
+            let text = part.get("text").and_then(|t| t.as_str());
+            let is_text = part.get("type").and_then(|t| t.as_str()) == Some("output_text");
+            if item.get("type").and_then(|t| t.as_str()) != Some("message") || !is_text {
+                continue;
+            }
+            let text = text.unwrap_or("");
+            push(&mut events, "response.content_part.added", json!({
+                "item_id": item_id,
+                "output_index": output_index,
+                "content_index": content_index,
+                "part": {"type": "output_text", "text": "", "annotations": []}
+            }));
+            push(&mut events, "response.output_text.delta", json!({
+                "item_id": item_id,
+                "output_index": output_index,
+                "content_index": content_index,
+                "delta": text
+            }));
+            push(&mut events, "response.output_text.done", json!({
+                "item_id": item_id,
+                "output_index": output_index,
+                "content_index": content_index,
+                "text": text
+            }));
+            push(&mut events, "response.content_part.done", json!({
+                "item_id": item_id,
+                "output_index": output_index,
+                "content_index": content_index,
+                "part": part
+            }));
+        }
+
+        push(&mut events, "response.output_item.done", json!({
+            "output_index": output_index,
+            "item": item
+        }));
+    }
+
+    push(&mut events, "response.completed", json!({"response": response}));
+    Some(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event_data(events: &[String], event: &str) -> Vec<Value> {
+        events
+            .iter()
+            .filter_map(|e| e.strip_prefix(&format!("event: {}\ndata: ", event)))
+            .filter_map(|data| serde_json::from_str(data).ok())
+            .collect()
+    }
+
+    #[test]
+    fn usage_chunk_after_finish_reason_is_reported() {
+        let mut converter = SseConverter::new("msg_1", "claude", vec![]);
+        let mut events = Vec::new();
+        for line in [
+            r#"data: {"choices":[{"index":0,"delta":{"role":"assistant","content":"Hi"}}]}"#,
+            r#"data: {"choices":[{"index":0,"delta":{},"finish_reason":"length"}]}"#,
+            r#"data: {"choices":[],"usage":{"prompt_tokens":12,"completion_tokens":34}}"#,
+            "data: [DONE]",
+        ] {
+            events.extend(converter.convert_line(line));
+        }
+        assert!(converter.completed());
+        assert!(converter.finish().is_empty());
+
+        let deltas = event_data(&events, "message_delta");
+        assert_eq!(deltas.len(), 1);
+        assert_eq!(deltas[0]["delta"]["stop_reason"], "max_tokens");
+        assert_eq!(deltas[0]["usage"]["input_tokens"], 12);
+        assert_eq!(deltas[0]["usage"]["output_tokens"], 34);
+        assert_eq!(event_data(&events, "message_stop").len(), 1);
+        assert_eq!(event_data(&events, "content_block_delta")[0]["delta"]["text"], "Hi");
+    }
+
+    #[test]
+    fn stream_without_stop_is_not_completed() {
+        let mut converter = SseConverter::new("msg_1", "claude", vec![]);
+        converter.convert_line(r#"data: {"choices":[{"index":0,"delta":{"content":"Hi"}}]}"#);
+        assert!(!converter.completed());
+    }
+}
@@ -0,0 +1,266 @@
+// 端点族：按请求路径区分 chat / embeddings / completions / audio / images 等，
+// 分别处理模型提取、输入 token 估算、上游用量解析、计费与缓存准入
+
+use crate::gateway::cache::CacheAdmission;
+use crate::gateway::tokens;
+use serde_json::Value;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EndpointFamily {
+    /// Anthropic `/v1/messages`
+    Messages,
+    /// OpenAI `/v1/responses`
+    Responses,
+    /// OpenAI `/v1/chat/completions`
+    ChatCompletions,
+    /// OpenAI 旧版 `/v1/completions`
+    Completions,
+    /// `/v1/embeddings`
+    Embeddings,
+    /// `/v1/audio/*`：转写、翻译（multipart 上传）与语音合成
+    Audio,
+    /// `/v1/images/*`：生成、编辑（multipart 上传）与变体
+    Images,
+    Other,
+}
+
+/// 上游返回的用量
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Usage {
+    pub input_tokens: u32,
+    pub output_tokens: u32,
+}
+
+impl EndpointFamily {
+    pub fn from_path(path: &str) -> Self {
+        let path = path.trim_end_matches('/');
+        let under = |prefix: &str| path == prefix || path.starts_with(&format!("{}/", prefix));
+        if under("/v1/messages") {
+            Self::Messages
+        } else if under("/v1/responses") {
+            Self::Responses
+        } else if path == "/v1/chat/completions" {
+            Self::ChatCompletions
+        } else if path == "/v1/completions" {
+            Self::Completions
+        } else if path == "/v1/embeddings" {
+            Self::Embeddings
+        } else if under("/v1/audio") {
+            Self::Audio
+        } else if under("/v1/images") {
+            Self::Images
+        } else {
+            Self::Other
+        }
+    }
+
+    /// 是否允许使用响应缓存
+    /// embeddings 结果是确定性的（没有 temperature），直接允许；音频与图片不缓存（上传体大、生成结果随机）
+    pub fn admits_cache(self, admission: &CacheAdmission, body: &[u8]) -> bool {
+        match self {
+            Self::Embeddings => serde_json::from_slice::<Value>(body).is_ok(),
+            Self::Audio | Self::Images => false,
+            _ => admission.admits_request(body),
+        }
+    }
+
+    /// 估算输入 token 数（上游未返回 usage 时使用）
+    pub fn estimate_input_tokens(self, body: &[u8]) -> u32 {
+        let json = serde_json::from_slice::<Value>(body).ok();
+        match self {
+            Self::Messages | Self::Responses | Self::ChatCompletions => chat_input_tokens(json.as_ref(), body),
+            Self::Completions => json.as_ref().map_or(0, |v| text_input_tokens(v.get("prompt"))),
+            Self::Embeddings => json.as_ref().map_or(0, |v| text_input_tokens(v.get("input"))),
+            // 语音合成按输入文本计；转写与翻译上传的是音频，无法估算
+            Self::Audio => json.as_ref().map_or(0, |v| text_input_tokens(v.get("input"))),
+            Self::Images => json.as_ref().map_or(0, |v| text_input_tokens(v.get("prompt"))),
+            Self::Other => 0,
+        }
+    }
+
+    /// 计算费用：embeddings 只有输入；其他端点按输入、输出分别计价
+    pub fn cost(self, usage: Usage, input_price_per_1k: f64, output_price_per_1k: f64) -> f64 {
+        let output_tokens = match self {
+            Self::Embeddings => 0,
+            _ => usage.output_tokens,
+        };
+        (usage.input_tokens as f64 / 1000.0 * input_price_per_1k)
+            + (output_tokens as f64 / 1000.0 * output_price_per_1k)
+    }
+}
+
+/// 提取请求的模型名：JSON 请求读取 `model` 字段，multipart 请求读取 `model` 表单字段
+pub fn extract_model(body: &[u8], content_type: Option<&str>) -> Option<String> {
+    if let Some(boundary) = content_type.and_then(multipart_boundary) {
+        return multipart_field(body, &boundary, "model");
+    }
+    let v = serde_json::from_slice::<Value>(body).ok()?;
+    v.get("model").and_then(|m| m.as_str()).map(|s| s.to_string())
+}
+
+/// 解析非流式响应中的 usage
+/// 兼容 OpenAI（prompt_tokens / completion_tokens）、Anthropic 与 Responses（input_tokens / output_tokens）
+pub fn parse_usage(body: &[u8]) -> Option<Usage> {
+    let v = serde_json::from_slice::<Value>(body).ok()?;
+    let (input_tokens, output_tokens) = usage_fields(v.get("usage")?);
+    Some(Usage {
+        input_tokens: input_tokens?,
+        output_tokens: output_tokens.unwrap_or(0),
+    })
+}
+
+/// 从流式响应中逐行解析 usage（SSE data 行）：
+/// - OpenAI Chat / Completions：开启 stream_options.include_usage 时最后一个 chunk 的 usage
+/// - OpenAI Responses：response.completed 事件中的 response.usage
+/// - Anthropic：message_start 中 message.usage 的输入用量，message_delta 中 usage 的累计输出用量
+#[derive(Debug, Default)]
+pub struct StreamUsage {
+    // 未读完的半行
+    pending: Vec<u8>,
+    input_tokens: Option<u32>,
+    output_tokens: Option<u32>,
+}
+
+impl StreamUsage {
+    pub fn feed(&mut self, chunk: &[u8]) {
+        self.pending.extend_from_slice(chunk);
+        while let Some(pos) = self.pending.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=pos).collect();
+            self.parse_line(&line);
+        }
+    }
+
+    /// 流结束后的用量；没有解析到输入用量时返回 None
+    pub fn finish(mut self) -> Option<Usage> {
+        let rest = std::mem::take(&mut self.pending);
+        self.parse_line(&rest);
+        Some(Usage {
+            input_tokens: self.input_tokens?,
+            output_tokens: self.output_tokens.unwrap_or(0),
+        })
+    }
+
+    fn parse_line(&mut self, line: &[u8]) {
+        let Some(data) = std::str::from_utf8(line)
+            .ok()
+            .and_then(|l| l.trim().strip_prefix("data:"))
+        else {
+            return;
+        };
+        let Ok(v) = serde_json::from_str::<Value>(data.trim()) else {
+            return;
+        };
+        let usage = [v.get("usage"), v.get("message").and_then(|m| m.get("usage")), v.get("response").and_then(|r| r.get("usage"))]
+            .into_iter()
+            .flatten()
+            .find(|u| u.is_object());
+        let Some(usage) = usage else {
+            return;
+        };
+        let (input_tokens, output_tokens) = usage_fields(usage);
+        self.input_tokens = input_tokens.or(self.input_tokens);
+        self.output_tokens = output_tokens.or(self.output_tokens);
+    }
+}
+
+/// usage 对象中的 (输入, 输出) token 数
+fn usage_fields(usage: &Value) -> (Option<u32>, Option<u32>) {
+    let field = |names: &[&str]| {
+        names
+            .iter()
+            .find_map(|n| usage.get(*n).and_then(|t| t.as_u64()))
+            .map(|t| t.min(u32::MAX as u64) as u32)
+    };
+
+    // Anthropic 的缓存读写 token 不计入 input_tokens
+    let input_tokens = field(&["prompt_tokens", "input_tokens"]).map(|tokens| {
+        tokens
+            .saturating_add(field(&["cache_creation_input_tokens"]).unwrap_or(0))
+            .saturating_add(field(&["cache_read_input_tokens"]).unwrap_or(0))
+    });
+    let output_tokens = field(&["completion_tokens", "output_tokens"]);
+    (input_tokens, output_tokens)
+}
+
+/// chat 类请求：按 messages 文本长度粗略估算
+fn chat_input_tokens(json: Option<&Value>, body: &[u8]) -> u32 {
+    if let Some(messages) = json.and_then(|j| j.get("messages")).and_then(|m| m.as_array()) {
+        let mut char_count = 0;
+        for msg in messages {
+            if let Some(content) = msg.get("content") {
+                if let Some(s) = content.as_str() {
+                    char_count += s.len();
+                } else if let Some(arr) = content.as_array() {
+                    for part in arr {
+                        if let Some(text) = part.get("text").and_then(|t| t.as_str()) {
+                            char_count += text.len();
+                        }
+                    }
+                }
+            }
+        }
+        return (char_count as f64 / 4.0) as u32;
+    }
+    (body.len() as f64 / 4.0) as u32
+}
+
+/// 文本输入：字符串、字符串数组，或已分词的 token 数组（embeddings / completions 支持）
+fn text_input_tokens(input: Option<&Value>) -> u32 {
+    match input {
+        Some(Value::String(s)) => tokens::count_text_tokens(s),
+        Some(Value::Array(items)) => items
+            .iter()
+            .map(|item| match item {
+                Value::String(s) => tokens::count_text_tokens(s),
+                Value::Number(_) => 1,
+                Value::Array(ids) => ids.len() as u32,
+                _ => 0,
+            })
+            .sum(),
+        _ => 0,
+    }
+}
+
+fn multipart_boundary(content_type: &str) -> Option<String> {
+    let (mime, params) = content_type.split_once(';')?;
+    if !mime.trim().eq_ignore_ascii_case("multipart/form-data") {
+        return None;
+    }
+    params
+        .split(';')
+        .filter_map(|p| p.trim().split_once('='))
+        .find(|(k, _)| k.trim().eq_ignore_ascii_case("boundary"))
+        .map(|(_, v)| v.trim().trim_matches('"').to_string())
+}
+
+/// 读取 multipart 中的文本字段（只扫描各部分的头部，不解析文件内容）
+fn multipart_field(body: &[u8], boundary: &str, name: &str) -> Option<String> {
+    let delimiter = format!("--{}", boundary);
+    let disposition = format!("name=\"{}\"", name);
+    let mut parts = split_bytes(body, delimiter.as_bytes()).into_iter().skip(1);
+    parts.find_map(|part| {
+        let header_end = find_bytes(part, b"\r\n\r\n")?;
+        let headers = String::from_utf8_lossy(&part[..header_end]);
+        if !headers.contains(&disposition) {
+            return None;
+        }
+        let value = &part[header_end + 4..];
+        let value = value.strip_suffix(b"\r\n").unwrap_or(value);
+        let value = String::from_utf8_lossy(value).trim().to_string();
+        (!value.is_empty()).then_some(value)
+    })
+}
+
+fn find_bytes(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+fn split_bytes<'a>(mut body: &'a [u8], delimiter: &[u8]) -> Vec<&'a [u8]> {
+    let mut parts = Vec::new();
+    while let Some(pos) = find_bytes(body, delimiter) {
+        parts.push(&body[..pos]);
+        body = &body[pos + delimiter.len()..];
+    }
+    parts.push(body);
+    parts
+}
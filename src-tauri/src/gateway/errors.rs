@@ -0,0 +1,159 @@
+// 网关自身产生的错误：按客户端使用的协议返回 Anthropic 或 OpenAI 格式，便于 SDK 正确解析
+// 错误对象额外包含网关错误码（gateway_code），所有供应商失败时附带每个供应商的尝试结果（attempts）
+
+use crate::gateway::config::ApiType;
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+/// 错误类别
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// 请求格式或参数错误（400）
+    InvalidRequest,
+    /// 客户端 Key 缺失或无效（401）
+    Unauthorized,
+    /// 来源地址被访问控制拒绝（403）
+    Forbidden,
+    /// 路径或模型不存在（404）
+    NotFound,
+    /// 请求体超过大小上限（413）
+    RequestTooLarge,
+    /// 请求包含敏感信息，被安全策略拒绝（400）
+    SensitiveData,
+    /// 被插件拒绝（403）
+    PluginRejected,
+    /// 上游响应格式转换失败（502）
+    ConversionFailed,
+    /// 上游无法连接（连接、代理或 TLS 失败，502）
+    UpstreamUnavailable,
+    /// 所有候选供应商均失败（502）
+    AllProvidersFailed,
+    /// 该端口已关闭（503）
+    GatewayDisabled,
+    /// 没有启用的供应商（503）
+    NoProviders,
+    /// 上游超时（504）
+    UpstreamTimeout,
+}
+
+impl ErrorKind {
+    pub fn status(self) -> StatusCode {
+        match self {
+            Self::InvalidRequest | Self::SensitiveData => StatusCode::BAD_REQUEST,
+            Self::Unauthorized => StatusCode::UNAUTHORIZED,
+            Self::Forbidden | Self::PluginRejected => StatusCode::FORBIDDEN,
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::RequestTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::ConversionFailed | Self::UpstreamUnavailable | Self::AllProvidersFailed => StatusCode::BAD_GATEWAY,
+            Self::GatewayDisabled | Self::NoProviders => StatusCode::SERVICE_UNAVAILABLE,
+            Self::UpstreamTimeout => StatusCode::GATEWAY_TIMEOUT,
+        }
+    }
+
+    /// 网关错误码，两种格式通用
+    pub fn code(self) -> &'static str {
+        match self {
+            Self::InvalidRequest => "invalid_request",
+            Self::Unauthorized => "client_key_invalid",
+            Self::Forbidden => "access_denied",
+            Self::NotFound => "not_found",
+            Self::RequestTooLarge => "request_too_large",
+            Self::SensitiveData => "sensitive_data_blocked",
+            Self::PluginRejected => "plugin_rejected",
+            Self::ConversionFailed => "conversion_failed",
+            Self::UpstreamUnavailable => "upstream_unavailable",
+            Self::AllProvidersFailed => "all_providers_failed",
+            Self::GatewayDisabled => "gateway_disabled",
+            Self::NoProviders => "no_active_providers",
+            Self::UpstreamTimeout => "upstream_timeout",
+        }
+    }
+
+    /// Anthropic `error.type`
+    fn anthropic_type(self) -> &'static str {
+        match self {
+            Self::InvalidRequest | Self::SensitiveData => "invalid_request_error",
+            Self::Unauthorized => "authentication_error",
+            Self::Forbidden | Self::PluginRejected => "permission_error",
+            Self::NotFound => "not_found_error",
+            Self::RequestTooLarge => "request_too_large",
+            _ => "api_error",
+        }
+    }
+
+    /// OpenAI `error.type`
+    fn openai_type(self) -> &'static str {
+        match self {
+            Self::InvalidRequest | Self::NotFound | Self::RequestTooLarge | Self::SensitiveData => {
+                "invalid_request_error"
+            }
+            Self::Unauthorized => "authentication_error",
+            Self::Forbidden | Self::PluginRejected => "permission_error",
+            _ => "server_error",
+        }
+    }
+}
+
+/// 单个供应商的尝试结果
+#[derive(Debug, Clone, Serialize)]
+pub struct AttemptSummary {
+    pub provider: String,
+    // 上游 HTTP 状态码；未发出请求或连接阶段失败时为空
+    pub status: Option<u16>,
+    // 失败类别：timeout / connect / proxy / tls / rate_limit / auth / not_found / upstream_5xx / other，
+    // 以及未尝试的原因 circuit_open / busy / keys_cooling_down，和 conversion（响应转换失败）
+    pub failure_kind: String,
+    pub message: Option<String>,
+}
+
+/// 构造 API 格式的错误响应；param 为出错的请求字段（仅 OpenAI 格式包含）
+pub fn error_response(api_type: &ApiType, kind: ErrorKind, message: &str, param: Option<&str>) -> Response {
+    build(api_type, kind, message, param, &[])
+}
+
+/// 附带供应商尝试结果的错误响应
+pub fn error_response_with_attempts(
+    api_type: &ApiType,
+    kind: ErrorKind,
+    message: &str,
+    attempts: &[AttemptSummary],
+) -> Response {
+    build(api_type, kind, message, None, attempts)
+}
+
+fn build(
+    api_type: &ApiType,
+    kind: ErrorKind,
+    message: &str,
+    param: Option<&str>,
+    attempts: &[AttemptSummary],
+) -> Response {
+    let mut error = match api_type {
+        ApiType::Anthropic => json!({
+            "type": kind.anthropic_type(),
+            "message": message,
+            "gateway_code": kind.code(),
+        }),
+        ApiType::OpenAIResponses | ApiType::OpenAIChat => json!({
+            "message": message,
+            "type": kind.openai_type(),
+            "param": param,
+            "code": kind.code(),
+            "gateway_code": kind.code(),
+        }),
+    };
+    if !attempts.is_empty() {
+        error["attempts"] = serde_json::to_value(attempts).unwrap_or(Value::Null);
+    }
+
+    let body = match api_type {
+        ApiType::Anthropic => json!({ "type": "error", "error": error }),
+        ApiType::OpenAIResponses | ApiType::OpenAIChat => json!({ "error": error }),
+    };
+    (kind.status(), Json(body)).into_response()
+}
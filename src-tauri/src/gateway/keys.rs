@@ -0,0 +1,210 @@
+// 多 Key 轮换：同一供应商可配置多个 API Key，每个 Key 独立熔断与限流状态
+
+use crate::gateway::config::Provider;
+use crate::gateway::resilience::{Circuit, FailureKind};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum KeySelection {
+    /// 按配置顺序轮换（默认）
+    #[default]
+    RoundRobin,
+    /// 本次运行中使用次数最少的 Key 优先
+    LeastUsed,
+}
+
+/// 本次请求选中的 Key
+#[derive(Debug, Clone)]
+pub struct SelectedKey {
+    // 稳定标识（Key 的哈希前缀），用于统计与会话亲和
+    pub id: String,
+    // 脱敏后的 Key，用于 UI 展示
+    pub label: String,
+    pub secret: String,
+}
+
+/// 解析后的 Key 与其标识（加载配置时计算，请求时不再读取引用与计算哈希）
+#[derive(Debug, Clone)]
+pub struct ResolvedKey {
+    pub id: String,
+    pub secret: String,
+}
+
+impl ResolvedKey {
+    pub fn new(secret: String) -> Self {
+        Self {
+            id: key_id(&secret),
+            secret,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct KeyState {
+    circuit: Circuit,
+    requests: u64,
+}
+
+pub struct KeyPool {
+    // "{provider_id}:{key_id}" -> 状态
+    keys: DashMap<String, KeyState>,
+    // provider_id -> 轮换游标
+    cursors: DashMap<String, u64>,
+}
+
+impl Default for KeyPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl KeyPool {
+    pub fn new() -> Self {
+        Self {
+            keys: DashMap::new(),
+            cursors: DashMap::new(),
+        }
+    }
+
+    /// 为一次请求选择 Key；供应商未配置 Key 时返回 None
+    /// preferred: 会话亲和固定的 Key，可用时优先使用
+    /// force: 所有 Key 都在冷却时仍选出最早恢复的 Key（供应商兜底尝试）
+    pub fn select(
+        &self,
+        provider: &Provider,
+        now: u64,
+        preferred: Option<&str>,
+        force: bool,
+    ) -> Option<SelectedKey> {
+        let keys = provider.keys();
+        if keys.is_empty() {
+            return None;
+        }
+
+        let ids: Vec<&str> = keys.iter().map(|k| k.id.as_str()).collect();
+        let state_of = |id: &str| {
+            self.keys
+                .get(&slot(&provider.id, id))
+                .map(|s| s.clone())
+                .unwrap_or_default()
+        };
+        let available: Vec<usize> = (0..keys.len())
+            .filter(|&i| !state_of(ids[i]).circuit.is_open(now))
+            .collect();
+
+        let chosen = if let Some(i) = preferred
+            .and_then(|p| ids.iter().position(|id| *id == p))
+            .filter(|i| available.contains(i))
+        {
+            i
+        } else if available.is_empty() {
+            if !force {
+                return None;
+            }
+            (0..keys.len()).min_by_key(|&i| state_of(ids[i]).circuit.open_until)?
+        } else {
+            match provider.key_selection {
+                KeySelection::RoundRobin => {
+                    let cursor = {
+                        let mut c = self.cursors.entry(provider.id.clone()).or_insert(0);
+                        let value = *c;
+                        *c = c.wrapping_add(1);
+                        value
+                    };
+                    let start = (cursor % keys.len() as u64) as usize;
+                    (0..keys.len())
+                        .map(|offset| (start + offset) % keys.len())
+                        .find(|i| available.contains(i))?
+                }
+                KeySelection::LeastUsed => *available
+                    .iter()
+                    .min_by_key(|&&i| state_of(ids[i]).requests)?,
+            }
+        };
+
+        self.keys
+            .entry(slot(&provider.id, ids[chosen]))
+            .or_default()
+            .requests += 1;
+
+        let key = &keys[chosen];
+        Some(SelectedKey {
+            id: key.id.clone(),
+            label: mask_key(&key.secret),
+            secret: key.secret.clone(),
+        })
+    }
+
+    /// 不计入使用次数地取一个可用 Key（用于 count_tokens 等辅助请求）
+    pub fn peek(&self, provider: &Provider, now: u64) -> Option<String> {
+        provider
+            .keys()
+            .iter()
+            .find(|k| {
+                self.keys
+                    .get(&slot(&provider.id, &k.id))
+                    .is_none_or(|s| !s.circuit.is_open(now))
+            })
+            .map(|k| k.secret.clone())
+    }
+
+    /// 供应商是否还有未冷却的 Key（未配置 Key 的供应商视为可用）
+    pub fn has_available(&self, provider: &Provider, now: u64) -> bool {
+        let keys = provider.keys();
+        keys.is_empty() || self.peek(provider, now).is_some()
+    }
+
+    pub fn on_success(&self, provider_id: &str, key_id: &str, latency_ms: u64) {
+        self.keys
+            .entry(slot(provider_id, key_id))
+            .or_default()
+            .circuit
+            .on_success(latency_ms);
+    }
+
+    /// 冷却单个 Key，返回冷却结束时间
+    pub fn on_failure(
+        &self,
+        provider_id: &str,
+        key_id: &str,
+        now: u64,
+        base_cooldown_seconds: u64,
+        kind: FailureKind,
+        retry_after_seconds: Option<u64>,
+    ) -> u64 {
+        self.keys
+            .entry(slot(provider_id, key_id))
+            .or_default()
+            .circuit
+            .on_failure(now, base_cooldown_seconds, kind, retry_after_seconds, &(provider_id, key_id, now))
+    }
+}
+
+/// 只影响单个 Key 的失败：限流与鉴权失败（额度耗尽、Key 失效）
+pub fn is_key_failure(kind: FailureKind) -> bool {
+    matches!(kind, FailureKind::RateLimit | FailureKind::Auth)
+}
+
+/// Key 的稳定标识（不可逆），用于统计与日志
+pub fn key_id(key: &str) -> String {
+    let digest = Sha256::digest(key.as_bytes());
+    format!("{:x}", digest)[..12].to_string()
+}
+
+/// 脱敏显示：保留前 3 位与后 4 位
+pub fn mask_key(key: &str) -> String {
+    let chars: Vec<char> = key.chars().collect();
+    if chars.len() <= 10 {
+        return "****".to_string();
+    }
+    let head: String = chars[..3].iter().collect();
+    let tail: String = chars[chars.len() - 4..].iter().collect();
+    format!("{}…{}", head, tail)
+}
+
+fn slot(provider_id: &str, key_id: &str) -> String {
+    format!("{}:{}", provider_id, key_id)
+}
@@ -4,32 +4,100 @@ pub mod stats;
 pub mod cache;
 pub mod converter;
 pub mod resilience;
+pub mod models;
+pub mod tokens;
+pub mod selection;
+pub mod affinity;
+pub mod keys;
+pub mod secrets;
+pub mod clients;
+pub mod access;
+pub mod server;
+pub mod endpoints;
+pub mod errors;
+pub mod validation;
+pub mod transforms;
+pub mod security;
+pub mod plugins;
 
 use tauri::{AppHandle, Manager, Runtime, State};
 use std::sync::Arc;
 use std::path::PathBuf;
 use tokio::sync::RwLock;
-use self::config::GatewayConfig;
+use self::config::{ApiType, GatewayConfig};
 use self::stats::{StatsManager, GatewayStats};
+use self::models::{ModelCatalog, ProviderModels, ProviderTestResult};
+use self::cache::{CacheEntrySummary, CacheInvalidation, CacheManager, CacheSettings};
+use self::affinity::SessionAffinity;
+use self::clients::ClientPool;
+use self::plugins::PluginHost;
+use self::keys::KeyPool;
+use self::transforms::{TransformPreview, TransformRule};
 
 pub struct GatewayState(pub Arc<RwLock<GatewayConfig>>);
 pub struct GatewayConfigPath(pub PathBuf);
 pub struct GatewayStatsState(pub Arc<StatsManager>);
+pub struct GatewayModelsState(pub Arc<ModelCatalog>);
+pub struct GatewayCacheState(pub Arc<CacheManager>);
+pub struct GatewayAffinityState(pub Arc<SessionAffinity>);
+pub struct GatewayClientsState(pub Arc<ClientPool>);
+pub struct GatewayPluginsState(pub Arc<PluginHost>);
+pub struct GatewayKeysState(pub Arc<KeyPool>);
+// 配置加载失败的原因；此时不启动网关，也不允许保存配置（避免用默认配置覆盖原文件）
+pub struct GatewayLoadError(pub Option<String>);
 
 #[tauri::command]
 pub async fn get_gateway_config(state: State<'_, GatewayState>) -> Result<GatewayConfig, String> {
     let config = state.0.read().await;
-    Ok(config.clone())
+    Ok(config.masked())
 }
 
 #[tauri::command]
 pub async fn save_gateway_config(
     state: State<'_, GatewayState>,
     path_state: State<'_, GatewayConfigPath>,
-    config: GatewayConfig
+    affinity_state: State<'_, GatewayAffinityState>,
+    clients_state: State<'_, GatewayClientsState>,
+    plugins_state: State<'_, GatewayPluginsState>,
+    load_error: State<'_, GatewayLoadError>,
+    mut config: GatewayConfig
 ) -> Result<(), String> {
+    if let Some(error) = &load_error.0 {
+        return Err(format!("{}; fix the config file and restart before saving", error));
+    }
+    // 校验出站代理配置
+    for proxy in config.outbound_proxy.iter().chain(config.providers.iter().filter_map(|p| p.proxy.as_ref())) {
+        clients::validate_proxy(proxy)?;
+    }
+    // 校验网关 HTTPS 证书与供应商 TLS 证书文件
+    if let Some(tls) = &config.server_tls {
+        server::load_tls_acceptor(tls)?;
+    }
+    for provider in &config.providers {
+        if let Some(tls) = &provider.tls {
+            clients::validate_tls(tls).map_err(|e| format!("Provider {}: {}", provider.name, e))?;
+        }
+    }
+    // 校验请求改写规则（正则、请求头名称等）与自定义 PII 正则
+    transforms::validate_rules(&config.transform_rules)?;
+    security::validate_patterns(&config.pii_patterns)?;
+    // 校验插件脚本（可读取、可编译）
+    plugins::validate(&config.plugins)?;
+
     let mut current_config = state.0.write().await;
+    config.restore_masked_keys(&current_config).map_err(|e| e.to_string())?;
+    config.resolve_keys();
+    // 校验监听地址与访问控制（需要还原后的客户端 Key）
+    access::validate(&config, &current_config)?;
+    // 重新编译插件（请求处理时直接使用编译结果）
+    plugins_state.0.reload(&config.plugins);
     *current_config = config.clone();
+    clients_state.0.set_global_proxy(config.outbound_proxy.clone());
+
+    // 已删除的供应商不再保留会话固定
+    affinity_state.0.retain_providers(
+        &config.providers.iter().map(|p| p.id.clone()).collect::<Vec<_>>(),
+    );
     
     // Save to disk
     config.save(&path_state.0).map_err(|e| e.to_string())?;
@@ -37,8 +105,130 @@ pub async fn save_gateway_config(
 }
 
 #[tauri::command]
-pub async fn get_gateway_stats(state: State<'_, GatewayStatsState>) -> Result<GatewayStats, String> {
-    Ok(state.0.get_stats())
+pub async fn get_gateway_stats(
+    state: State<'_, GatewayStatsState>,
+    cache_state: State<'_, GatewayCacheState>,
+    affinity_state: State<'_, GatewayAffinityState>,
+) -> Result<GatewayStats, String> {
+    let mut stats = state.0.get_stats();
+    stats.cache = cache_state.0.stats();
+    stats.pinned_sessions = affinity_state.0.session_count();
+    let lookups = stats.cache_hits + stats.cache_misses;
+    if lookups > 0 {
+        stats.cache.hit_ratio = stats.cache_hits as f64 / lookups as f64;
+    }
+    Ok(stats)
+}
+
+/// 网关提示：加载配置时的迁移说明，以及对外监听但缺少客户端 Key 等需要处理的问题
+#[tauri::command]
+pub async fn get_gateway_notices(
+    state: State<'_, GatewayState>,
+    load_error: State<'_, GatewayLoadError>,
+) -> Result<Vec<String>, String> {
+    let config = state.0.read().await;
+    let mut notices: Vec<String> = load_error.0.iter().cloned().collect();
+    notices.extend(config.notices.iter().cloned());
+    notices.extend(access::exposure_notice(&config));
+    Ok(notices)
+}
+
+/// 获取供应商上游模型列表及映射校验结果
+/// provider_id 为空时返回所有供应商；refresh 为 true 时强制重新拉取
+#[tauri::command]
+pub async fn get_provider_models(
+    state: State<'_, GatewayState>,
+    models_state: State<'_, GatewayModelsState>,
+    provider_id: Option<String>,
+    refresh: Option<bool>,
+) -> Result<Vec<ProviderModels>, String> {
+    let providers = {
+        let config = state.0.read().await;
+        models_state.0.retain_providers(
+            &config.providers.iter().map(|p| p.id.clone()).collect::<Vec<_>>(),
+        );
+        config
+            .providers
+            .iter()
+            .filter(|p| provider_id.as_deref().is_none_or(|id| p.id == id))
+            .cloned()
+            .collect::<Vec<_>>()
+    };
+
+    if let Some(id) = &provider_id {
+        if providers.is_empty() {
+            return Err(format!("Provider not found: {}", id));
+        }
+    }
+
+    let refresh = refresh.unwrap_or(false);
+    let reports = futures::future::join_all(
+        providers.iter().map(|p| models_state.0.report(p, refresh)),
+    )
+    .await;
+    Ok(reports)
+}
+
+/// 测试供应商连通性（使用已保存的配置），TLS / 代理 / 连接失败会分别报告
+#[tauri::command]
+pub async fn test_provider(
+    state: State<'_, GatewayState>,
+    models_state: State<'_, GatewayModelsState>,
+    provider_id: String,
+) -> Result<ProviderTestResult, String> {
+    let provider = {
+        let config = state.0.read().await;
+        config
+            .providers
+            .iter()
+            .find(|p| p.id == provider_id)
+            .cloned()
+            .ok_or_else(|| format!("Provider not found: {}", provider_id))?
+    };
+    Ok(models_state.0.test(&provider).await)
+}
+
+/// 预览请求改写规则（dry-run，不发送请求）
+/// rules 为空时使用当前配置中的规则，便于保存前试用未保存的规则
+#[tauri::command]
+pub async fn preview_transform(
+    state: State<'_, GatewayState>,
+    api_type: ApiType,
+    provider_id: Option<String>,
+    body: String,
+    rules: Option<Vec<TransformRule>>,
+) -> Result<TransformPreview, String> {
+    let rules = match rules {
+        Some(rules) => rules,
+        None => state.0.read().await.transform_rules.clone(),
+    };
+    transforms::validate_rules(&rules)?;
+    Ok(transforms::preview(&rules, &api_type, provider_id.as_deref(), &body))
+}
+
+/// 列出响应缓存条目
+#[tauri::command]
+pub async fn list_cache_entries(cache_state: State<'_, GatewayCacheState>) -> Result<Vec<CacheEntrySummary>, String> {
+    Ok(cache_state.0.entries())
+}
+
+/// 按 key / 模型 / 路径前缀 / API 类型删除缓存条目，返回删除数量
+#[tauri::command]
+pub async fn invalidate_cache(
+    cache_state: State<'_, GatewayCacheState>,
+    filter: CacheInvalidation,
+) -> Result<usize, String> {
+    if filter.is_empty() {
+        return Err("At least one invalidation filter is required".to_string());
+    }
+    Ok(cache_state.0.invalidate(&filter))
+}
+
+/// 清空所有响应缓存
+#[tauri::command]
+pub async fn clear_cache(cache_state: State<'_, GatewayCacheState>) -> Result<(), String> {
+    cache_state.0.clear();
+    Ok(())
 }
 
 pub fn init<R: Runtime>(app: &AppHandle<R>) {
@@ -49,20 +239,59 @@ pub fn init<R: Runtime>(app: &AppHandle<R>) {
     std::fs::create_dir_all(&data_dir).expect("Failed to create data dir");
     let config_path = data_dir.join("gateway_config.json");
 
-    // Load config
-    let config = GatewayConfig::load(&config_path).unwrap_or_default();
+    // Load config；加载失败时报告错误，不使用默认配置启动
+    let (config, load_error) = match GatewayConfig::load(&config_path) {
+        Ok(config) => (config, None),
+        Err(e) => {
+            let error = format!("Failed to load gateway config {}: {:#}", config_path.display(), e);
+            eprintln!("[Gateway] {}; gateway not started", error);
+            (GatewayConfig::default(), Some(error))
+        }
+    };
+    for notice in config.notices.iter().chain(access::exposure_notice(&config).iter()) {
+        eprintln!("[Gateway] {}", notice);
+    }
+
+    // Init cache (内存 + data/cache/ 磁盘层)
+    let cache_manager = Arc::new(CacheManager::new(CacheSettings {
+        max_entries: config.cache_max_entries,
+        max_memory_bytes: config.cache_max_memory_bytes,
+        max_disk_bytes: config.cache_max_disk_bytes,
+        compression: config.cache_compression,
+        disk_dir: config.cache_persist_enabled.then(|| data_dir.join("cache")),
+    }));
+    // 上游 HTTP 客户端（按出站代理设置分池）
+    let clients = Arc::new(ClientPool::new(config.outbound_proxy.clone()));
+    let plugin_host = Arc::new(PluginHost::new());
+    plugin_host.reload(&config.plugins);
     let config_state = Arc::new(RwLock::new(config));
     
     // Init stats
     let stats_manager = Arc::new(StatsManager::new(data_dir));
 
+    // 上游 Key 的冷却状态（转发请求与模型发现共用）
+    let key_pool = Arc::new(KeyPool::new());
+    let model_catalog = Arc::new(ModelCatalog::new(clients.clone(), key_pool.clone()));
+    let affinity = Arc::new(SessionAffinity::new());
+
     app.manage(GatewayState(config_state.clone()));
     app.manage(GatewayConfigPath(config_path));
     app.manage(GatewayStatsState(stats_manager.clone()));
+    app.manage(GatewayModelsState(model_catalog.clone()));
+    app.manage(GatewayCacheState(cache_manager.clone()));
+    app.manage(GatewayAffinityState(affinity.clone()));
+    app.manage(GatewayClientsState(clients.clone()));
+    app.manage(GatewayPluginsState(plugin_host.clone()));
+    app.manage(GatewayKeysState(key_pool));
+    let failed = load_error.is_some();
+    app.manage(GatewayLoadError(load_error));
+    if failed {
+        return;
+    }
 
     let app_handle = app.clone();
     tauri::async_runtime::spawn(async move {
         // 启动三个独立的网关服务器
-        proxy::start_servers(config_state, stats_manager, app_handle).await;
+        proxy::start_servers(config_state, stats_manager, cache_manager, model_catalog, affinity, clients, app_handle).await;
     });
 }
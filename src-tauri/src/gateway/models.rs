@@ -0,0 +1,424 @@
+// 上游模型发现：拉取各供应商的 /v1/models，缓存结果并校验模型映射
+
+use crate::gateway::config::{ApiType, Provider};
+use crate::gateway::proxy::{apply_provider_auth, apply_provider_headers, failure_kind_from_status, provider_url};
+use dashmap::DashMap;
+use crate::gateway::clients::{self, ClientPool, UpstreamClient};
+use crate::gateway::keys::KeyPool;
+use crate::gateway::resilience::FailureKind;
+use crate::gateway::stats::failure_kind_to_string;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// 模型列表缓存有效期（秒）
+const MODELS_TTL_SECONDS: u64 = 3600;
+/// 拉取失败后的重试间隔（秒），避免短暂故障让模型列表一小时内都不刷新
+const MODELS_RETRY_SECONDS: u64 = 60;
+const FETCH_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Claude Code 等客户端常用的 Claude 模型名，用于生成映射建议
+const COMMON_CLAUDE_MODELS: &[&str] = &[
+    "claude-opus-4-1-20250805",
+    "claude-opus-4-20250514",
+    "claude-sonnet-4-5-20250929",
+    "claude-sonnet-4-20250514",
+    "claude-3-7-sonnet-20250219",
+    "claude-3-5-sonnet-20241022",
+    "claude-haiku-4-5-20251001",
+    "claude-3-5-haiku-20241022",
+];
+
+/// 单个供应商的模型列表缓存
+#[derive(Debug, Clone, Default)]
+pub struct CachedModels {
+    pub models: Vec<String>,
+    pub fetched_at: u64,
+    pub error: Option<String>,
+    // 上次拉取失败时的下次重试时间
+    pub retry_at: Option<u64>,
+}
+
+/// 指向不存在模型的映射
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MappingIssue {
+    pub alias: String,
+    pub target: String,
+}
+
+/// 返回给前端的供应商模型报告
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderModels {
+    pub provider_id: String,
+    pub provider_name: String,
+    pub models: Vec<String>,
+    pub fetched_at: u64,
+    pub error: Option<String>,
+    pub invalid_mappings: Vec<MappingIssue>,
+    pub suggested_mappings: BTreeMap<String, String>,
+}
+
+/// 供应商连通性测试结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderTestResult {
+    pub provider_id: String,
+    pub ok: bool,
+    // 上游 HTTP 状态码；连接阶段失败时为空
+    pub status: Option<u16>,
+    pub latency_ms: u64,
+    // 失败类别：tls / proxy / connect / timeout / auth / not_found / upstream_5xx / other
+    pub error_kind: Option<String>,
+    pub error: Option<String>,
+    pub model_count: Option<usize>,
+}
+
+pub struct ModelCatalog {
+    entries: DashMap<String, CachedModels>,
+    clients: Arc<ClientPool>,
+    keys: Arc<KeyPool>,
+}
+
+impl ModelCatalog {
+    pub fn new(clients: Arc<ClientPool>, keys: Arc<KeyPool>) -> Self {
+        Self {
+            entries: DashMap::new(),
+            clients,
+            keys,
+        }
+    }
+
+    /// 拉取模型列表使用的 Key：跳过冷却中的 Key；全部冷却时仍使用第一个
+    fn api_key(&self, provider: &Provider) -> String {
+        self.keys
+            .peek(provider, now_secs())
+            .or_else(|| provider.keys().first().map(|k| k.secret.clone()))
+            .unwrap_or_default()
+    }
+
+    /// 获取缓存的模型列表（不触发网络请求）
+    pub fn cached(&self, provider_id: &str) -> Option<CachedModels> {
+        self.entries.get(provider_id).map(|e| e.clone())
+    }
+
+    /// 缓存是否缺失或已过期
+    pub fn is_stale(&self, provider_id: &str) -> bool {
+        match self.entries.get(provider_id) {
+            Some(entry) => match entry.retry_at {
+                Some(retry_at) => now_secs() >= retry_at,
+                None => now_secs() > entry.fetched_at + MODELS_TTL_SECONDS,
+            },
+            None => true,
+        }
+    }
+
+    /// 拉取供应商模型列表并写入缓存；失败时保留上次成功的列表与拉取时间，稍后重试
+    pub async fn refresh(&self, provider: &Provider) -> CachedModels {
+        let fetched = match self.clients.client_for(provider) {
+            Ok(upstream) => fetch_models(&upstream, provider, &self.api_key(provider)).await.map_err(|(_, e)| e),
+            Err((_, e)) => Err(e),
+        };
+        let now = now_secs();
+
+        let entry = match fetched {
+            Ok(models) => CachedModels {
+                models,
+                fetched_at: now,
+                error: None,
+                retry_at: None,
+            },
+            Err(e) => {
+                let previous = self.cached(&provider.id).unwrap_or_default();
+                CachedModels {
+                    models: previous.models,
+                    fetched_at: previous.fetched_at,
+                    error: Some(e),
+                    retry_at: Some(now + MODELS_RETRY_SECONDS),
+                }
+            }
+        };
+
+        self.entries.insert(provider.id.clone(), entry.clone());
+        entry
+    }
+
+    /// 生成供应商报告；refresh 为 true 或缓存过期时重新拉取
+    pub async fn report(&self, provider: &Provider, refresh: bool) -> ProviderModels {
+        let cached = if refresh || self.is_stale(&provider.id) {
+            self.refresh(provider).await
+        } else {
+            self.cached(&provider.id).unwrap_or_default()
+        };
+        build_report(provider, cached)
+    }
+
+    /// 测试供应商连通性：请求 /v1/models，区分 TLS、代理、连接、鉴权等失败原因
+    /// 成功时顺带更新模型列表缓存
+    pub async fn test(&self, provider: &Provider) -> ProviderTestResult {
+        let start = Instant::now();
+        let result = match self.clients.client_for(provider) {
+            Ok(upstream) => fetch_models(&upstream, provider, &self.api_key(provider)).await,
+            Err(e) => Err(e),
+        };
+        let latency_ms = start.elapsed().as_millis() as u64;
+
+        match result {
+            Ok(models) => {
+                let model_count = models.len();
+                self.entries.insert(
+                    provider.id.clone(),
+                    CachedModels {
+                        models,
+                        fetched_at: now_secs(),
+                        error: None,
+                        retry_at: None,
+                    },
+                );
+                ProviderTestResult {
+                    provider_id: provider.id.clone(),
+                    ok: true,
+                    status: Some(200),
+                    latency_ms,
+                    error_kind: None,
+                    error: None,
+                    model_count: Some(model_count),
+                }
+            }
+            Err((kind, error)) => ProviderTestResult {
+                provider_id: provider.id.clone(),
+                ok: false,
+                status: http_status(&error),
+                latency_ms,
+                error_kind: Some(failure_kind_to_string(kind)),
+                error: Some(error),
+                model_count: None,
+            },
+        }
+    }
+
+    /// 删除已不存在的供应商缓存
+    pub fn retain_providers(&self, provider_ids: &[String]) {
+        self.entries.retain(|id, _| provider_ids.contains(id));
+    }
+}
+
+fn build_report(provider: &Provider, cached: CachedModels) -> ProviderModels {
+    // 拉取失败且没有历史列表时无法判断映射是否有效
+    let invalid_mappings = if cached.models.is_empty() {
+        Vec::new()
+    } else {
+        find_invalid_mappings(&provider.model_mapping, &cached.models)
+    };
+    let suggested_mappings = suggest_mappings(&provider.model_mapping, &cached.models);
+
+    ProviderModels {
+        provider_id: provider.id.clone(),
+        provider_name: provider.name.clone(),
+        models: cached.models,
+        fetched_at: cached.fetched_at,
+        error: cached.error,
+        invalid_mappings,
+        suggested_mappings,
+    }
+}
+
+/// 找出映射目标不在供应商模型列表中的条目
+pub fn find_invalid_mappings(mapping: &HashMap<String, String>, models: &[String]) -> Vec<MappingIssue> {
+    let mut issues: Vec<MappingIssue> = mapping
+        .iter()
+        .filter(|(_, target)| !models.iter().any(|m| m == *target))
+        .map(|(alias, target)| MappingIssue {
+            alias: alias.clone(),
+            target: target.clone(),
+        })
+        .collect();
+    issues.sort_by(|a, b| a.alias.cmp(&b.alias));
+    issues
+}
+
+/// 为常见 Claude 模型名生成映射建议
+/// 仅在供应商不直接提供该模型且尚未配置映射时给出，按 opus/sonnet/haiku 系列匹配
+pub fn suggest_mappings(mapping: &HashMap<String, String>, models: &[String]) -> BTreeMap<String, String> {
+    let mut suggestions = BTreeMap::new();
+    if models.is_empty() {
+        return suggestions;
+    }
+
+    for &claude_model in COMMON_CLAUDE_MODELS {
+        if mapping.contains_key(claude_model) || models.iter().any(|m| m == claude_model) {
+            continue;
+        }
+        let Some(family) = claude_family(claude_model) else {
+            continue;
+        };
+
+        // 同系列中优先选择 claude 命名的模型，其次按名称倒序（通常较新版本排在后面）
+        let best = models
+            .iter()
+            .filter(|m| m.to_lowercase().contains(family))
+            .max_by(|a, b| {
+                let a_claude = a.to_lowercase().contains("claude");
+                let b_claude = b.to_lowercase().contains("claude");
+                a_claude.cmp(&b_claude).then_with(|| a.cmp(b))
+            });
+
+        if let Some(target) = best {
+            suggestions.insert(claude_model.to_string(), target.clone());
+        }
+    }
+
+    suggestions
+}
+
+fn claude_family(model: &str) -> Option<&'static str> {
+    ["opus", "sonnet", "haiku"]
+        .into_iter()
+        .find(|family| model.contains(family))
+}
+
+/// 供应商是否以 Anthropic 原生协议提供服务
+pub fn is_anthropic_native(provider: &Provider) -> bool {
+    provider.api_types.contains(&ApiType::Anthropic) && !provider.claude_code_proxy
+}
+
+async fn fetch_models(
+    upstream: &UpstreamClient,
+    provider: &Provider,
+    api_key: &str,
+) -> Result<Vec<String>, (FailureKind, String)> {
+    let url = provider_url(provider, "/v1/models", None, None);
+
+    let mut req = upstream.client.get(&url);
+    req = apply_provider_auth(req, provider, api_key, is_anthropic_native(provider));
+    req = apply_provider_headers(req, provider);
+
+    let resp = tokio::time::timeout(FETCH_TIMEOUT, req.send())
+        .await
+        .map_err(|_| (FailureKind::Timeout, "Request timed out".to_string()))?
+        .map_err(|e| clients::classify_send_error(&e, upstream.via_proxy))?;
+
+    let status = resp.status();
+    let body = resp
+        .bytes()
+        .await
+        .map_err(|e| (FailureKind::Connect, format!("Failed to read response: {}", e)))?;
+
+    if !status.is_success() {
+        let text = String::from_utf8_lossy(&body);
+        let snippet: String = text.chars().take(200).collect();
+        return Err((failure_kind_from_status(status), format!("HTTP {} - {}", status, snippet)));
+    }
+
+    parse_models_response(&body).map_err(|e| (FailureKind::Other, e))
+}
+
+/// 从 "HTTP 401 Unauthorized - ..." 形式的错误中取出状态码
+fn http_status(error: &str) -> Option<u16> {
+    error.strip_prefix("HTTP ")?.get(..3)?.parse().ok()
+}
+
+/// 解析 OpenAI (`{"object":"list","data":[{"id":..}]}`) 与 Anthropic (`{"data":[{"id":..,"type":"model"}]}`) 两种格式
+pub fn parse_models_response(body: &[u8]) -> Result<Vec<String>, String> {
+    let value: serde_json::Value = serde_json::from_slice(body)
+        .map_err(|e| format!("Failed to parse models response: {}", e))?;
+
+    let data = value
+        .get("data")
+        .or_else(|| value.get("models"))
+        .and_then(|d| d.as_array())
+        .ok_or("Models response has no 'data' array")?;
+
+    let mut models: Vec<String> = data
+        .iter()
+        .filter_map(|item| {
+            item.get("id")
+                .or_else(|| item.get("name"))
+                .and_then(|id| id.as_str())
+                .map(|s| s.to_string())
+        })
+        .collect();
+    models.sort();
+    models.dedup();
+    Ok(models)
+}
+
+/// 汇总某个 API 类型可用的模型：上游模型 + 模型映射中的别名
+/// 返回 模型名 -> 首个提供该模型的供应商名
+pub async fn collect_served_models(
+    catalog: &ModelCatalog,
+    providers: &[Provider],
+) -> BTreeMap<String, String> {
+    let reports = futures::future::join_all(providers.iter().map(|p| catalog.report(p, false))).await;
+
+    let mut served = BTreeMap::new();
+    for (provider, report) in providers.iter().zip(reports) {
+        for alias in provider.model_mapping.keys() {
+            served.entry(alias.clone()).or_insert_with(|| provider.name.clone());
+        }
+        for model in report.models {
+            served.entry(model).or_insert_with(|| provider.name.clone());
+        }
+    }
+    served
+}
+
+/// 按端口协议构造 `GET /v1/models` 响应体
+pub fn models_list_body(api_type: &ApiType, served: &BTreeMap<String, String>) -> serde_json::Value {
+    match api_type {
+        ApiType::Anthropic => {
+            let data: Vec<serde_json::Value> = served
+                .keys()
+                .map(|id| anthropic_model_object(id))
+                .collect();
+            serde_json::json!({
+                "data": data,
+                "has_more": false,
+                "first_id": served.keys().next(),
+                "last_id": served.keys().next_back(),
+            })
+        }
+        ApiType::OpenAIResponses | ApiType::OpenAIChat => {
+            let data: Vec<serde_json::Value> = served
+                .iter()
+                .map(|(id, owner)| openai_model_object(id, owner))
+                .collect();
+            serde_json::json!({
+                "object": "list",
+                "data": data,
+            })
+        }
+    }
+}
+
+/// 按端口协议构造 `GET /v1/models/{id}` 响应体
+pub fn model_object_body(api_type: &ApiType, id: &str, owner: &str) -> serde_json::Value {
+    match api_type {
+        ApiType::Anthropic => anthropic_model_object(id),
+        ApiType::OpenAIResponses | ApiType::OpenAIChat => openai_model_object(id, owner),
+    }
+}
+
+fn anthropic_model_object(id: &str) -> serde_json::Value {
+    serde_json::json!({
+        "type": "model",
+        "id": id,
+        "display_name": id,
+        "created_at": "1970-01-01T00:00:00Z",
+    })
+}
+
+fn openai_model_object(id: &str, owner: &str) -> serde_json::Value {
+    serde_json::json!({
+        "id": id,
+        "object": "model",
+        "created": 0,
+        "owned_by": owner,
+    })
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
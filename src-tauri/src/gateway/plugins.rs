@@ -0,0 +1,366 @@
+// 脚本插件（Rhai）：在供应商选择前与收到响应后调用，实现团队自定义的路由与改写逻辑
+// 脚本运行在沙箱中：不能访问文件与网络，限制运算次数、调用深度与数据大小；脚本出错时跳过该插件并记录日志
+//
+// 脚本可定义两个函数（均可选）：
+//   fn on_request(ctx)  -> () 不做处理，或返回 #{ provider: "id" } / #{ providers: ["id", ...] } /
+//                          #{ body: 新请求体 } / #{ reject: "原因" }（可组合）
+//   fn on_response(ctx) -> () 不做处理，或返回 #{ body: 新响应体 }（仅非流式 JSON 响应可改写）
+//                          流式响应在转发结束后调用，只读：ctx.stream 为 true，ctx.body 为各 SSE 事件 data 组成的数组
+//
+// 脚本在加载或保存配置时读取并编译（修改 script_path 指向的文件后需重新保存配置）；
+// 钩子可能运行较久，调用方应放在阻塞线程池中执行
+
+use crate::gateway::config::{ApiType, Provider};
+use rhai::{CallFnOptions, Dynamic, Engine, Scope, AST};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::sync::{Arc, RwLock};
+
+/// 单次调用的运算次数上限（超出即中止，防止死循环拖住请求）
+const MAX_OPERATIONS: u64 = 200_000;
+const MAX_CALL_LEVELS: usize = 32;
+const MAX_STRING_SIZE: usize = 4 * 1024 * 1024;
+const MAX_COLLECTION_SIZE: usize = 100_000;
+
+const ON_REQUEST: &str = "on_request";
+const ON_RESPONSE: &str = "on_response";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PluginConfig {
+    pub name: String,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    // 生效的 API 类型，为空表示全部
+    #[serde(default)]
+    pub api_types: Vec<ApiType>,
+    // 内联脚本；为空时读取 script_path
+    #[serde(default)]
+    pub script: String,
+    #[serde(default)]
+    pub script_path: Option<String>,
+}
+
+fn default_true() -> bool { true }
+
+impl PluginConfig {
+    fn applies_to(&self, api_type: &ApiType) -> bool {
+        self.enabled && (self.api_types.is_empty() || self.api_types.contains(api_type))
+    }
+
+    fn source(&self) -> Result<String, String> {
+        if !self.script.trim().is_empty() {
+            return Ok(self.script.clone());
+        }
+        match self.script_path.as_deref().map(str::trim).filter(|p| !p.is_empty()) {
+            Some(path) => fs::read_to_string(path).map_err(|e| format!("Failed to read script '{}': {}", path, e)),
+            None => Err("script or script_path is required".to_string()),
+        }
+    }
+}
+
+/// 客户端信息
+#[derive(Debug, Clone, Serialize)]
+pub struct ClientInfo {
+    pub ip: String,
+    pub local: bool,
+    pub user_agent: String,
+    // 请求头（不含鉴权相关的请求头）
+    pub headers: HashMap<String, String>,
+}
+
+/// 候选供应商信息
+#[derive(Debug, Clone, Serialize)]
+pub struct ProviderInfo {
+    pub id: String,
+    pub name: String,
+    pub weight: u32,
+    // 熔断冷却中时为 false
+    pub available: bool,
+    pub claude_code_proxy: bool,
+    pub model_mapping: HashMap<String, String>,
+}
+
+impl ProviderInfo {
+    pub fn new(provider: &Provider, available: bool) -> Self {
+        Self {
+            id: provider.id.clone(),
+            name: provider.name.clone(),
+            weight: provider.weight,
+            available,
+            claude_code_proxy: provider.claude_code_proxy,
+            model_mapping: provider.model_mapping.clone(),
+        }
+    }
+}
+
+/// on_request 的参数
+#[derive(Debug, Clone, Serialize)]
+pub struct RequestContext {
+    pub request_id: String,
+    pub api_type: String,
+    pub method: String,
+    pub path: String,
+    pub model: Option<String>,
+    // 解析后的请求体，非 JSON 请求为 ()
+    pub body: Value,
+    pub client: ClientInfo,
+    pub providers: Vec<ProviderInfo>,
+}
+
+/// on_response 的参数
+#[derive(Debug, Clone, Serialize)]
+pub struct ResponseContext {
+    pub request_id: String,
+    pub api_type: String,
+    pub path: String,
+    pub model: String,
+    pub provider_id: String,
+    pub provider_name: String,
+    pub status: u16,
+    pub latency_ms: u64,
+    // 流式响应（只读，返回的 body 被忽略）
+    pub stream: bool,
+    pub body: Value,
+}
+
+/// 插件对请求的处理结果
+#[derive(Debug, Clone, Default)]
+pub struct RequestDecision {
+    // 按顺序尝试的供应商 ID（不在列表中的供应商不会被尝试）
+    pub providers: Option<Vec<String>>,
+    // 改写后的请求体
+    pub body: Option<Vec<u8>>,
+    // 拒绝原因：(插件名称, 说明)
+    pub reject: Option<(String, String)>,
+}
+
+/// 已编译的插件
+struct LoadedPlugin {
+    config: PluginConfig,
+    ast: AST,
+    on_request: bool,
+    on_response: bool,
+}
+
+/// 插件宿主：共享沙箱引擎，持有加载或保存配置时编译好的插件（请求时不读取文件、不编译）
+pub struct PluginHost {
+    engine: Engine,
+    loaded: RwLock<Arc<Vec<LoadedPlugin>>>,
+}
+
+impl Default for PluginHost {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PluginHost {
+    pub fn new() -> Self {
+        Self {
+            engine: sandboxed_engine(),
+            loaded: RwLock::new(Arc::new(Vec::new())),
+        }
+    }
+
+    /// 加载或保存配置时调用：读取并编译全部启用的插件，失败的插件记录日志后跳过
+    pub fn reload(&self, plugins: &[PluginConfig]) {
+        let mut loaded = Vec::new();
+        for plugin in plugins.iter().filter(|p| p.enabled) {
+            let ast = plugin
+                .source()
+                .and_then(|source| self.engine.compile(&source).map_err(|e| format!("failed to compile: {}", e)));
+            match ast {
+                Ok(ast) => loaded.push(LoadedPlugin {
+                    on_request: defines(&ast, ON_REQUEST),
+                    on_response: defines(&ast, ON_RESPONSE),
+                    config: plugin.clone(),
+                    ast,
+                }),
+                Err(e) => eprintln!("Plugin {}: {}", plugin.name, e),
+            }
+        }
+        *self.loaded.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(loaded);
+    }
+
+    fn snapshot(&self) -> Arc<Vec<LoadedPlugin>> {
+        self.loaded.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// 是否有插件定义了 on_request（没有时跳过构建插件上下文）
+    pub fn has_request_hook(&self, api_type: &ApiType) -> bool {
+        self.snapshot().iter().any(|p| p.on_request && p.config.applies_to(api_type))
+    }
+
+    /// 是否有插件定义了 on_response（流式响应据此决定是否收集响应体）
+    pub fn has_response_hook(&self, api_type: &ApiType) -> bool {
+        self.snapshot().iter().any(|p| p.on_response && p.config.applies_to(api_type))
+    }
+
+    /// 供应商选择前调用；多个插件按配置顺序执行，后面的插件看到前面插件改写后的请求体
+    pub fn on_request(&self, api_type: &ApiType, mut ctx: RequestContext) -> RequestDecision {
+        let mut decision = RequestDecision::default();
+        let plugins = self.snapshot();
+        for plugin in plugins.iter().filter(|p| p.on_request && p.config.applies_to(api_type)) {
+            let Some(result) = self.call(plugin, ON_REQUEST, &ctx) else {
+                continue;
+            };
+
+            if let Some(reason) = result.get("reject").filter(|r| !r.is_null()) {
+                let message = reason
+                    .as_str()
+                    .map(str::to_string)
+                    .unwrap_or_else(|| reason.to_string());
+                decision.reject = Some((plugin.config.name.clone(), message));
+                return decision;
+            }
+            if let Some(body) = result.get("body").filter(|b| !b.is_null()) {
+                ctx.body = body.clone();
+                decision.body = serde_json::to_vec(body).ok();
+            }
+            let providers = match (result.get("providers"), result.get("provider")) {
+                (Some(Value::Array(ids)), _) => Some(ids.iter().filter_map(|id| id.as_str()).map(str::to_string).collect()),
+                (_, Some(Value::String(id))) => Some(vec![id.clone()]),
+                _ => None,
+            };
+            if providers.is_some() {
+                decision.providers = providers;
+            }
+        }
+        decision
+    }
+
+    /// 非流式响应返回客户端前调用，返回改写后的响应体
+    pub fn on_response(&self, api_type: &ApiType, mut ctx: ResponseContext, body: &[u8]) -> Option<Vec<u8>> {
+        let plugins = self.snapshot();
+        let mut hooked = plugins.iter().filter(|p| p.on_response && p.config.applies_to(api_type)).peekable();
+        hooked.peek()?;
+        ctx.body = serde_json::from_slice::<Value>(body).ok()?;
+
+        let mut rewritten = None;
+        for plugin in hooked {
+            let Some(result) = self.call(plugin, ON_RESPONSE, &ctx) else {
+                continue;
+            };
+            if let Some(body) = result.get("body").filter(|b| !b.is_null()) {
+                ctx.body = body.clone();
+                rewritten = serde_json::to_vec(body).ok();
+            }
+        }
+        rewritten
+    }
+
+    /// 流式响应转发结束后调用（只读）：响应已发送给客户端，返回的 body 被忽略
+    pub fn on_stream_response(&self, api_type: &ApiType, mut ctx: ResponseContext, body: &[u8]) {
+        let plugins = self.snapshot();
+        let mut hooked = plugins.iter().filter(|p| p.on_response && p.config.applies_to(api_type)).peekable();
+        if hooked.peek().is_none() {
+            return;
+        }
+        ctx.stream = true;
+        ctx.body = Value::Array(sse_data(body));
+
+        for plugin in hooked {
+            let Some(result) = self.call(plugin, ON_RESPONSE, &ctx) else {
+                continue;
+            };
+            if result.get("body").is_some_and(|b| !b.is_null()) {
+                eprintln!("Plugin {} {}: body rewrite is ignored for streamed responses", plugin.config.name, ON_RESPONSE);
+            }
+        }
+    }
+
+    /// 调用插件函数；返回 () 或执行出错时返回空
+    fn call(&self, plugin: &LoadedPlugin, hook: &str, ctx: &impl Serialize) -> Option<Value> {
+        let name = &plugin.config.name;
+        let result = rhai::serde::to_dynamic(ctx).and_then(|arg| {
+            // 只调用函数，不执行脚本顶层语句
+            let options = CallFnOptions::new().eval_ast(false);
+            self.engine
+                .call_fn_with_options::<Dynamic>(options, &mut Scope::new(), &plugin.ast, hook, (arg,))
+        });
+        let value = match result {
+            Ok(value) if value.is_unit() => return None,
+            Ok(value) => rhai::serde::from_dynamic::<Value>(&value),
+            Err(e) => Err(e),
+        };
+        match value {
+            Ok(Value::Object(map)) => Some(Value::Object(map)),
+            Ok(other) => {
+                eprintln!("Plugin {} {}: expected a map or (), got {}", name, hook, other);
+                None
+            }
+            Err(e) => {
+                eprintln!("Plugin {} {} failed: {}", name, hook, e);
+                None
+            }
+        }
+    }
+}
+
+/// 校验插件配置（保存配置前调用）：名称唯一、脚本可读取且能编译、至少定义一个钩子函数
+pub fn validate(plugins: &[PluginConfig]) -> Result<(), String> {
+    let engine = sandboxed_engine();
+    let mut names = HashSet::new();
+    for plugin in plugins {
+        let name = plugin.name.trim();
+        if name.is_empty() {
+            return Err("Plugin name must not be empty".to_string());
+        }
+        if !names.insert(name) {
+            return Err(format!("Duplicate plugin name: {}", name));
+        }
+        let context = |e: String| format!("Plugin '{}': {}", name, e);
+        let source = plugin.source().map_err(context)?;
+        let ast = engine.compile(&source).map_err(|e| context(e.to_string()))?;
+        if !defines(&ast, ON_REQUEST) && !defines(&ast, ON_RESPONSE) {
+            return Err(context(format!("script must define {}(ctx) or {}(ctx)", ON_REQUEST, ON_RESPONSE)));
+        }
+    }
+    Ok(())
+}
+
+/// 请求头中排除鉴权信息后交给插件
+pub fn visible_headers(headers: &axum::http::HeaderMap) -> HashMap<String, String> {
+    headers
+        .iter()
+        .filter(|(name, _)| {
+            !matches!(
+                name.as_str(),
+                "authorization" | "x-api-key" | "api-key" | "cookie" | "proxy-authorization"
+            )
+        })
+        .filter_map(|(name, value)| value.to_str().ok().map(|v| (name.to_string(), v.to_string())))
+        .collect()
+}
+
+/// SSE 响应体中各事件的 data（JSON），跳过 [DONE] 与无法解析的行
+fn sse_data(body: &[u8]) -> Vec<Value> {
+    String::from_utf8_lossy(body)
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("data:"))
+        .map(str::trim)
+        .filter(|data| *data != "[DONE]")
+        .filter_map(|data| serde_json::from_str(data).ok())
+        .collect()
+}
+
+fn defines(ast: &AST, name: &str) -> bool {
+    ast.iter_functions().any(|f| f.name == name && f.params.len() == 1)
+}
+
+fn sandboxed_engine() -> Engine {
+    let mut engine = Engine::new();
+    engine
+        .set_max_operations(MAX_OPERATIONS)
+        .set_max_call_levels(MAX_CALL_LEVELS)
+        .set_max_string_size(MAX_STRING_SIZE)
+        .set_max_array_size(MAX_COLLECTION_SIZE)
+        .set_max_map_size(MAX_COLLECTION_SIZE)
+        // 禁止 import，脚本无法加载文件模块
+        .set_max_modules(0);
+    engine.on_print(|text| eprintln!("[Plugin] {}", text));
+    engine.on_debug(|text, _, pos| eprintln!("[Plugin] {:?} {}", pos, text));
+    engine
+}
@@ -1,37 +1,64 @@
 use axum::{
+    extract::ConnectInfo,
     body::Body,
-    extract::{State, Request},
+    extract::{Path, State, Request},
+    middleware,
     response::{IntoResponse, Response},
-    routing::any,
+    routing::{any, get, post},
     Router,
     http::{StatusCode, HeaderValue},
 };
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
 use std::sync::Arc;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
-use std::hash::{Hash, Hasher};
-use std::collections::hash_map::DefaultHasher;
+use std::hash::Hash;
 use tokio::sync::RwLock;
-use crate::gateway::config::{GatewayConfig, ApiType};
+use crate::gateway::config::{GatewayConfig, ApiType, AuthStyle, Provider, ProviderKind};
 use crate::gateway::stats::{StatsManager, RequestLog};
-use crate::gateway::cache::CacheManager;
+use crate::gateway::cache::{CacheAdmission, CacheDirective, CacheManager, CacheMeta};
 use crate::gateway::converter;
+use crate::gateway::tokens;
+use crate::gateway::endpoints::{self, EndpointFamily, StreamUsage, Usage};
+use crate::gateway::errors::{self, AttemptSummary, ErrorKind};
+use crate::gateway::stats::failure_kind_to_string;
+use crate::gateway::validation;
+use crate::gateway::transforms;
+use crate::gateway::security::{self, SecurityEvent};
+use crate::gateway::plugins::{visible_headers, ClientInfo, PluginHost, ProviderInfo, RequestContext, ResponseContext};
+use crate::gateway::models;
+use crate::gateway::{GatewayKeysState, GatewayPluginsState};
+use crate::gateway::models::ModelCatalog;
 use crate::gateway::resilience::{Circuit, FailureKind};
-use tower_http::cors::CorsLayer;
-use reqwest::Client;
-use tauri::{AppHandle, Emitter, Runtime};
+use crate::gateway::selection::{self, SelectionContext};
+use crate::gateway::affinity::{self, SessionAffinity, SessionPin};
+use crate::gateway::keys::{self, KeyPool};
+use crate::gateway::{access, server};
+use crate::gateway::clients::{self, ClientPool};
+use tauri::{AppHandle, Emitter, Manager, Runtime};
 use dashmap::DashMap;
 use tokio::sync::{Semaphore, OwnedSemaphorePermit};
 use tokio::time::timeout;
 
+/// 未配置覆盖时使用的 anthropic-version
+const DEFAULT_ANTHROPIC_VERSION: &str = "2023-06-01";
+/// 流式响应交给 on_response 插件时最多收集的字节数（超出时跳过插件）
+const MAX_PLUGIN_STREAM_BYTES: usize = 4 * 1024 * 1024;
+
 pub struct ProxyState<R: Runtime> {
     pub config: Arc<RwLock<GatewayConfig>>,
     pub stats: Arc<StatsManager>,
     pub cache: Arc<CacheManager>,
     pub app: AppHandle<R>,
     pub circuits: Arc<DashMap<String, Circuit>>,
+    pub keys: Arc<KeyPool>,
     pub inflight_limits: Arc<DashMap<String, Arc<Semaphore>>>,
-    pub http_client: Client,
+    pub clients: Arc<ClientPool>,
+    pub models: Arc<ModelCatalog>,
+    pub round_robin: Arc<AtomicU64>,
+    pub affinity: Arc<SessionAffinity>,
+    pub plugins: Arc<PluginHost>,
     pub api_type: ApiType,
 }
 
@@ -43,8 +70,13 @@ impl<R: Runtime> Clone for ProxyState<R> {
             cache: self.cache.clone(),
             app: self.app.clone(),
             circuits: self.circuits.clone(),
+            keys: self.keys.clone(),
             inflight_limits: self.inflight_limits.clone(),
-            http_client: self.http_client.clone(),
+            clients: self.clients.clone(),
+            models: self.models.clone(),
+            round_robin: self.round_robin.clone(),
+            affinity: self.affinity.clone(),
+            plugins: self.plugins.clone(),
             api_type: self.api_type.clone(),
         }
     }
@@ -57,51 +89,74 @@ struct ProviderStatusEvent {
     api_type: String,
 }
 
-/// 启动三个独立的网关服务器
+/// 启动三个独立的网关服务器（以及可选的统一端口服务器）
 pub async fn start_servers<R: Runtime>(
     config: Arc<RwLock<GatewayConfig>>,
     stats: Arc<StatsManager>,
+    cache: Arc<CacheManager>,
+    models: Arc<ModelCatalog>,
+    affinity: Arc<SessionAffinity>,
+    clients: Arc<ClientPool>,
     app: AppHandle<R>,
 ) {
     let cfg = config.read().await;
     
-    let cache = Arc::new(CacheManager::new(
-        cfg.cache_max_entries,
-        cfg.cache_ttl_seconds,
-    ));
     let circuits = Arc::new(DashMap::new());
+    let key_pool = app.state::<GatewayKeysState>().0.clone();
     let inflight_limits: Arc<DashMap<String, Arc<Semaphore>>> = Arc::new(DashMap::new());
+    // 插件在 init 中编译，保存配置时重新编译
+    let plugin_host = app.state::<GatewayPluginsState>().0.clone();
 
-    let http_client = Client::builder()
-        .connect_timeout(Duration::from_secs(3))
-        .pool_idle_timeout(Duration::from_secs(90))
-        .pool_max_idle_per_host(16)
-        .build()
-        .unwrap_or_else(|_| Client::new());
-    
     let anthropic_port = cfg.anthropic_port;
     let responses_port = cfg.responses_port;
     let chat_port = cfg.chat_port;
+    let unified_port = cfg.unified_port;
     
     let anthropic_enabled = cfg.anthropic_enabled;
     let responses_enabled = cfg.responses_enabled;
     let chat_enabled = cfg.chat_enabled;
+    let unified_enabled = cfg.unified_enabled;
     
     drop(cfg);
+
+    // 定期清理过期缓存条目（含磁盘层）与过期的会话固定
+    let cache_janitor = cache.clone();
+    let affinity_janitor = affinity.clone();
+    let janitor_config = config.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(300));
+        loop {
+            interval.tick().await;
+            cache_janitor.evict_expired();
+            let affinity_ttl = janitor_config.read().await.affinity_settings().ttl_seconds;
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+            affinity_janitor.evict_expired(now, affinity_ttl);
+        }
+    });
+
+    // 每种 API 类型一个状态，独立端口与统一端口共用（轮询计数、统计归属一致）
+    let make_state = |api_type: ApiType| ProxyState {
+        config: config.clone(),
+        stats: stats.clone(),
+        cache: cache.clone(),
+        app: app.clone(),
+        circuits: circuits.clone(),
+        keys: key_pool.clone(),
+        inflight_limits: inflight_limits.clone(),
+        clients: clients.clone(),
+        models: models.clone(),
+        round_robin: Arc::new(AtomicU64::new(0)),
+        affinity: affinity.clone(),
+        plugins: plugin_host.clone(),
+        api_type,
+    };
+    let anthropic_state = make_state(ApiType::Anthropic);
+    let responses_state = make_state(ApiType::OpenAIResponses);
+    let chat_state = make_state(ApiType::OpenAIChat);
     
     // 启动 Anthropic 网关 (Claude Code)
     if anthropic_enabled {
-        let state = ProxyState {
-            config: config.clone(),
-            stats: stats.clone(),
-            cache: cache.clone(),
-            app: app.clone(),
-            circuits: circuits.clone(),
-            inflight_limits: inflight_limits.clone(),
-            http_client: http_client.clone(),
-            api_type: ApiType::Anthropic,
-        };
-        
+        let state = anthropic_state.clone();
         tokio::spawn(async move {
             start_single_server(anthropic_port, state, "Anthropic").await;
         });
@@ -109,17 +164,7 @@ pub async fn start_servers<R: Runtime>(
     
     // 启动 OpenAI Responses 网关 (CodeX)
     if responses_enabled {
-        let state = ProxyState {
-            config: config.clone(),
-            stats: stats.clone(),
-            cache: cache.clone(),
-            app: app.clone(),
-            circuits: circuits.clone(),
-            inflight_limits: inflight_limits.clone(),
-            http_client: http_client.clone(),
-            api_type: ApiType::OpenAIResponses,
-        };
-        
+        let state = responses_state.clone();
         tokio::spawn(async move {
             start_single_server(responses_port, state, "OpenAI Responses").await;
         });
@@ -127,46 +172,293 @@ pub async fn start_servers<R: Runtime>(
     
     // 启动 OpenAI Chat 网关 (Cline/Continue)
     if chat_enabled {
-        let state = ProxyState {
-            config: config.clone(),
-            stats: stats.clone(),
-            cache: cache.clone(),
-            app: app.clone(),
-            circuits: circuits.clone(),
-            inflight_limits: inflight_limits.clone(),
-            http_client: http_client.clone(),
-            api_type: ApiType::OpenAIChat,
-        };
-        
+        let state = chat_state.clone();
         tokio::spawn(async move {
             start_single_server(chat_port, state, "OpenAI Chat").await;
         });
     }
+
+    // 统一端口：按路径分发到三种 API 类型
+    if unified_enabled {
+        tokio::spawn(async move {
+            start_unified_server(unified_port, anthropic_state, responses_state, chat_state).await;
+        });
+    }
 }
 
 async fn start_single_server<R: Runtime>(port: u16, state: ProxyState<R>, name: &str) {
+    let bind_address = state.config.read().await.bind_address(&state.api_type).to_string();
     let app_router = Router::new()
+        .route("/v1/models", get(handle_list_models::<R>))
+        .route("/v1/models/:model_id", get(handle_get_model::<R>))
+        .route("/v1/messages/count_tokens", post(handle_count_tokens::<R>))
         .route("/*path", any(handle_request::<R>))
-        .layer(CorsLayer::permissive())
-        .with_state(state);
+        .with_state(state.clone());
+
+    serve_gateway(app_router, state, &bind_address, port, name).await;
+}
+
+/// 统一端口：`/v1/messages*` -> Anthropic，`/v1/responses*` -> Responses，
+/// `/v1/chat/completions`、`/v1/completions`、`/v1/embeddings`、`/v1/audio/*`、`/v1/images/*` -> Chat；各请求仍按自身 ApiType 选择供应商与记录统计
+async fn start_unified_server<R: Runtime>(
+    port: u16,
+    anthropic: ProxyState<R>,
+    responses: ProxyState<R>,
+    chat: ProxyState<R>,
+) {
+    let bind_address = anthropic.config.read().await.unified_bind_address.clone();
+
+    let anthropic_routes = Router::new()
+        .route("/v1/messages", any(handle_request::<R>))
+        .route("/v1/messages/count_tokens", post(handle_count_tokens::<R>))
+        .route("/v1/messages/*rest", any(handle_request::<R>))
+        .with_state(anthropic.clone());
+    let responses_routes = Router::new()
+        .route("/v1/responses", any(handle_request::<R>))
+        .route("/v1/responses/*rest", any(handle_request::<R>))
+        .with_state(responses);
+    let chat_routes = Router::new()
+        .route("/v1/chat/completions", any(handle_request::<R>))
+        .route("/v1/completions", any(handle_request::<R>))
+        .route("/v1/embeddings", any(handle_request::<R>))
+        .route("/v1/audio/*rest", any(handle_request::<R>))
+        .route("/v1/images/*rest", any(handle_request::<R>))
+        .with_state(chat.clone());
+
+    // 模型列表没有路径可区分：带 anthropic-version 头的请求按 Anthropic 返回，其余按 OpenAI 格式
+    let models_anthropic = anthropic.clone();
+    let models_chat = chat.clone();
+    let model_anthropic = anthropic.clone();
+    let model_chat = chat;
+    let models_routes = Router::new()
+        .route(
+            "/v1/models",
+            get(move |headers: axum::http::HeaderMap| {
+                let state = if headers.contains_key("anthropic-version") { models_anthropic } else { models_chat };
+                handle_list_models(State(state))
+            }),
+        )
+        .route(
+            "/v1/models/:model_id",
+            get(move |headers: axum::http::HeaderMap, model_id: Path<String>| {
+                let state = if headers.contains_key("anthropic-version") { model_anthropic } else { model_chat };
+                handle_get_model(State(state), model_id)
+            }),
+        );
+
+    let app_router = Router::new()
+        .merge(anthropic_routes)
+        .merge(responses_routes)
+        .merge(chat_routes)
+        .merge(models_routes)
+        .fallback(|req: Request<Body>| async move {
+            let api_type = if req.headers().contains_key("anthropic-version") {
+                ApiType::Anthropic
+            } else {
+                ApiType::OpenAIChat
+            };
+            let message = format!(
+                "Unsupported path on unified gateway port: {} (expected /v1/messages, /v1/responses, /v1/chat/completions, /v1/completions, /v1/embeddings, /v1/audio/* or /v1/images/*)",
+                req.uri().path()
+            );
+            errors::error_response(&api_type, ErrorKind::NotFound, &message, None)
+        });
+
+    serve_gateway(app_router, anthropic, &bind_address, port, "Unified").await;
+}
+
+/// 附加访问控制与 CORS，绑定地址并提供服务（配置证书时使用 HTTPS）
+async fn serve_gateway<R: Runtime>(
+    app_router: Router,
+    state: ProxyState<R>,
+    bind_address: &str,
+    port: u16,
+    name: &str,
+) {
+    let (server_tls, cors) = {
+        let config = state.config.read().await;
+        (config.server_tls.clone(), access::cors_layer(&config, bind_address))
+    };
+    let addr = match server::socket_addr(bind_address, port) {
+        Ok(addr) => addr,
+        Err(e) => {
+            eprintln!("❌ {} Gateway not started: {}", name, e);
+            return;
+        }
+    };
+    // 证书无效时不启动，避免退回明文 HTTP
+    let acceptor = match server_tls.as_ref().map(server::load_tls_acceptor).transpose() {
+        Ok(acceptor) => acceptor,
+        Err(e) => {
+            eprintln!("❌ {} Gateway not started: {}", name, e);
+            return;
+        }
+    };
+
+    let app_router = app_router
+        .layer(middleware::from_fn_with_state(state, access::guard::<R>))
+        .layer(cors);
 
-    let addr = format!("0.0.0.0:{}", port);
-    println!("🚀 {} Gateway listening on {}", name, addr);
+    let scheme = if acceptor.is_some() { "https" } else { "http" };
+    println!("🚀 {} Gateway listening on {}://{}", name, scheme, addr);
     
-    match tokio::net::TcpListener::bind(&addr).await {
-        Ok(listener) => {
-            if let Err(e) = axum::serve(listener, app_router).await {
-                eprintln!("❌ {} Server error: {}", name, e);
+    match tokio::net::TcpListener::bind(addr).await {
+        Ok(listener) => match acceptor {
+            Some(acceptor) => server::serve_tls(listener, app_router, acceptor).await,
+            None => {
+                let service = app_router.into_make_service_with_connect_info::<SocketAddr>();
+                if let Err(e) = axum::serve(listener, service).await {
+                    eprintln!("❌ {} Server error: {}", name, e);
+                }
             }
-        }
+        },
         Err(e) => {
             eprintln!("❌ Failed to bind {} to {}: {}", name, addr, e);
         }
     }
 }
 
+/// 读取当前端口是否启用及其可用供应商
+async fn port_providers<R: Runtime>(state: &ProxyState<R>) -> Option<Vec<Provider>> {
+    let config = state.config.read().await;
+    let enabled = match state.api_type {
+        ApiType::Anthropic => config.anthropic_enabled,
+        ApiType::OpenAIResponses => config.responses_enabled,
+        ApiType::OpenAIChat => config.chat_enabled,
+    };
+    if !enabled {
+        return None;
+    }
+    Some(
+        config
+            .get_providers_for_api_type(&state.api_type)
+            .into_iter()
+            .cloned()
+            .collect(),
+    )
+}
+
+/// 本地合成 `GET /v1/models`：汇总该端口所有启用供应商的模型及映射别名
+async fn handle_list_models<R: Runtime>(State(state): State<ProxyState<R>>) -> Response {
+    let Some(providers) = port_providers(&state).await else {
+        return errors::error_response(&state.api_type, ErrorKind::GatewayDisabled, "Gateway is disabled", None);
+    };
+
+    let served = models::collect_served_models(&state.models, &providers).await;
+    axum::Json(models::models_list_body(&state.api_type, &served)).into_response()
+}
+
+/// 本地合成 `GET /v1/models/{id}`
+async fn handle_get_model<R: Runtime>(
+    State(state): State<ProxyState<R>>,
+    Path(model_id): Path<String>,
+) -> Response {
+    let Some(providers) = port_providers(&state).await else {
+        return errors::error_response(&state.api_type, ErrorKind::GatewayDisabled, "Gateway is disabled", None);
+    };
+
+    let served = models::collect_served_models(&state.models, &providers).await;
+    match served.get(&model_id) {
+        Some(owner) => axum::Json(models::model_object_body(&state.api_type, &model_id, owner)).into_response(),
+        None => {
+            let message = format!("Model not found: {}", model_id);
+            errors::error_response(&state.api_type, ErrorKind::NotFound, &message, Some("model"))
+        }
+    }
+}
+
+/// `POST /v1/messages/count_tokens`
+/// 优先转发给原生 Anthropic 供应商；无可用供应商或上游不支持时本地估算。
+/// 该接口不计入供应商健康状态，也不影响熔断。
+async fn handle_count_tokens<R: Runtime>(
+    State(state): State<ProxyState<R>>,
+    req: Request<Body>,
+) -> Response {
+    const COUNT_TOKENS_TIMEOUT: Duration = Duration::from_secs(10);
+    const MAX_UPSTREAM_ATTEMPTS: usize = 2;
+
+    let Some(mut providers) = port_providers(&state).await else {
+        return errors::error_response(&state.api_type, ErrorKind::GatewayDisabled, "Gateway is disabled", None);
+    };
+
+    let path = req.uri().path().to_string();
+    let query = req.uri().query().map(|q| q.to_string());
+    let headers = req.headers().clone();
+    let (body_limit, validation_enabled) = {
+        let config = state.config.read().await;
+        (config.request_body_limit(&state.api_type), config.request_validation_enabled)
+    };
+
+    let body_bytes = match read_body_limited(&state.api_type, req, body_limit).await {
+        Ok(b) => b,
+        Err(response) => return response,
+    };
+    if validation_enabled {
+        if let Err(e) = validation::validate_request(&path, &body_bytes) {
+            return errors::error_response(&state.api_type, ErrorKind::InvalidRequest, &e.message, e.param.as_deref());
+        }
+    }
+    let request_json: serde_json::Value = match serde_json::from_slice(&body_bytes) {
+        Ok(v) => v,
+        Err(e) => {
+            let message = format!("Invalid JSON body: {}", e);
+            return errors::error_response(&state.api_type, ErrorKind::InvalidRequest, &message, None);
+        }
+    };
+
+    // 只有原生 Anthropic 供应商能提供该接口；跳过冷却中的供应商
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    providers.retain(|p| {
+        models::is_anthropic_native(p)
+            && state.circuits.get(&p.id).map(|c| !c.is_open(now)).unwrap_or(true)
+    });
+    providers.sort_by_key(|p| std::cmp::Reverse(p.weight));
+
+    for provider in providers.iter().take(MAX_UPSTREAM_ATTEMPTS) {
+        let model = request_json
+            .get("model")
+            .and_then(|m| m.as_str())
+            .map(|m| provider.mapped_model(m));
+        let url = provider_url(provider, &path, query.as_deref(), model);
+
+        let Ok(upstream) = state.clients.client_for(provider) else {
+            continue;
+        };
+        let mut upstream_req = upstream.client.post(&url);
+        upstream_req = forward_client_headers(upstream_req, &headers, provider);
+        let api_key = state.keys.peek(provider, now).unwrap_or_default();
+        upstream_req = apply_provider_auth(upstream_req, provider, &api_key, true);
+        upstream_req = upstream_req.header("Content-Type", "application/json");
+        upstream_req = apply_provider_headers(upstream_req, provider).body(body_bytes.to_vec());
+
+        let Ok(Ok(resp)) = timeout(COUNT_TOKENS_TIMEOUT, upstream_req.send()).await else {
+            continue;
+        };
+        if !resp.status().is_success() {
+            continue;
+        }
+        if let Ok(Ok(bytes)) = timeout(COUNT_TOKENS_TIMEOUT, resp.bytes()).await {
+            if serde_json::from_slice::<serde_json::Value>(&bytes)
+                .ok()
+                .and_then(|v| v.get("input_tokens").cloned())
+                .is_some()
+            {
+                return Response::builder()
+                    .status(StatusCode::OK)
+                    .header(axum::http::header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(bytes))
+                    .unwrap_or_default();
+            }
+        }
+    }
+
+    let input_tokens = tokens::count_anthropic_input_tokens(&request_json);
+    axum::Json(serde_json::json!({ "input_tokens": input_tokens })).into_response()
+}
+
 async fn handle_request<R: Runtime>(
     State(state): State<ProxyState<R>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
     req: Request<Body>,
 ) -> Response {
     const DEFAULT_MAX_ATTEMPTS: usize = 4;
@@ -179,7 +471,7 @@ async fn handle_request<R: Runtime>(
     let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
 
     // Read config quickly (do NOT hold across awaits).
-    let (gateway_enabled, cache_enabled, fallback_enabled, base_cooldown_seconds, providers) = {
+    let (gateway_enabled, (cache_enabled, cache_ttl), cache_admission, cache_ignored_fields, fallback_enabled, selection_strategy, affinity_settings, base_cooldown_seconds, (body_limit, validation_enabled, transform_rules), scan_settings, providers) = {
         let config = state.config.read().await;
         let gateway_enabled = match state.api_type {
             ApiType::Anthropic => config.anthropic_enabled,
@@ -195,19 +487,29 @@ async fn handle_request<R: Runtime>(
 
         (
             gateway_enabled,
-            config.cache_enabled,
+            config.cache_policy(&state.api_type),
+            config.cache_admission(),
+            config.cache_ignored_fields.clone(),
             config.fallback_enabled,
+            config.selection_strategy(&state.api_type),
+            config.affinity_settings(),
             config.circuit_breaker_cooldown_seconds.max(1),
+            (
+                config.request_body_limit(&state.api_type),
+                config.request_validation_enabled,
+                config.transform_rules.clone(),
+            ),
+            config.scan_settings(),
             providers,
         )
     };
 
     if !gateway_enabled {
-        return (StatusCode::SERVICE_UNAVAILABLE, "Gateway is disabled").into_response();
+        return errors::error_response(&state.api_type, ErrorKind::GatewayDisabled, "Gateway is disabled", None);
     }
 
     let path = req.uri().path().to_string();
-    let query = req.uri().query().map(|q| format!("?{}", q)).unwrap_or_default();
+    let query = req.uri().query().map(|q| q.to_string());
     let method = req.method().clone();
     let headers = req.headers().clone();
     let user_agent = headers
@@ -216,35 +518,163 @@ async fn handle_request<R: Runtime>(
         .unwrap_or("unknown")
         .to_string();
 
-    let body_bytes = match axum::body::to_bytes(req.into_body(), usize::MAX).await {
+    let body_bytes = match read_body_limited(&state.api_type, req, body_limit).await {
         Ok(b) => b,
-        Err(_) => return (StatusCode::BAD_REQUEST, "Failed to read body").into_response(),
+        Err(response) => return response,
     };
 
-    // Cache check
-    if cache_enabled {
-        let cache_key = CacheManager::generate_key(&path, &body_bytes);
-        if let Some(cached) = state.cache.get(&cache_key) {
-            state.stats.record_cache_hit();
+    // 明显不合法的请求直接返回 API 格式的错误，不发往上游，也不影响供应商健康状态
+    if validation_enabled && method == axum::http::Method::POST {
+        if let Err(e) = validation::validate_request(&path, &body_bytes) {
+            return errors::error_response(&state.api_type, ErrorKind::InvalidRequest, &e.message, e.param.as_deref());
+        }
+    }
 
-            let mut builder = Response::builder().status(cached.status);
-            if let Some(headers_mut) = builder.headers_mut() {
-                for (k, v) in &cached.headers {
-                    if let (Ok(name), Ok(val)) = (k.parse::<axum::http::HeaderName>(), HeaderValue::from_str(v)) {
-                        headers_mut.insert(name, val);
-                    }
-                }
+    let api_type_str = api_type_to_string(&state.api_type);
+    let endpoint = EndpointFamily::from_path(&path);
+    let content_type = headers
+        .get(axum::http::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok());
+    let requested_model = endpoints::extract_model(&body_bytes, content_type);
+
+    // 敏感信息检测：按策略拒绝、脱敏后转发或放行，命中结果写入请求日志
+    let mut security_findings = Vec::new();
+    let body_bytes = match security::scan(&scan_settings, &body_bytes) {
+        None => body_bytes,
+        Some(scan) => {
+            let model = requested_model.clone().unwrap_or_else(|| "unknown".to_string());
+            let _ = state.app.emit(
+                "gateway://security",
+                SecurityEvent {
+                    request_id: request_id.clone(),
+                    timestamp: now,
+                    api_type: api_type_str.clone(),
+                    path: path.clone(),
+                    model: model.clone(),
+                    action: scan.action().to_string(),
+                    findings: scan.findings.clone(),
+                },
+            );
+            if scan.blocked {
+                let (message, param) = security::block_message(&scan.findings);
+                state.stats.record_blocked(RequestLog {
+                    id: uuid::Uuid::new_v4().to_string(),
+                    timestamp: now,
+                    provider: "gateway".to_string(),
+                    model,
+                    status: ErrorKind::SensitiveData.status().as_u16(),
+                    duration_ms: duration_ms(overall_start),
+                    input_tokens: 0,
+                    output_tokens: 0,
+                    cost: 0.0,
+                    path: path.clone(),
+                    client_agent: user_agent.clone(),
+                    api_type: api_type_str.clone(),
+                    cached: false,
+                    error_message: Some(message.clone()),
+                    api_key_id: None,
+                    api_key_label: None,
+                    security_findings: scan.findings,
+                });
+                return errors::error_response(&state.api_type, ErrorKind::SensitiveData, &message, param.as_deref());
+            }
+            security_findings = scan.findings;
+            scan.body.map(bytes::Bytes::from).unwrap_or(body_bytes)
+        }
+    };
+
+    // 脚本插件：可拒绝请求、改写请求体或指定供应商
+    let mut plugin_route: Option<Vec<String>> = None;
+    let (body_bytes, requested_model) = if !state.plugins.has_request_hook(&state.api_type) {
+        (body_bytes, requested_model)
+    } else {
+        let ctx = RequestContext {
+            request_id: request_id.clone(),
+            api_type: api_type_str.clone(),
+            method: method.to_string(),
+            path: path.clone(),
+            model: requested_model.clone(),
+            body: serde_json::from_slice(&body_bytes).unwrap_or(serde_json::Value::Null),
+            client: ClientInfo {
+                ip: peer.ip().to_canonical().to_string(),
+                local: peer.ip().to_canonical().is_loopback(),
+                user_agent: user_agent.clone(),
+                headers: visible_headers(&headers),
+            },
+            providers: providers
+                .iter()
+                .map(|p| {
+                    let available = state.circuits.get(&p.id).is_none_or(|c| c.can_attempt(now));
+                    ProviderInfo::new(p, available)
+                })
+                .collect(),
+        };
+        // 脚本可能运行较久，放到阻塞线程池执行，避免占用异步工作线程
+        let host = state.plugins.clone();
+        let api_type = state.api_type.clone();
+        let decision = tokio::task::spawn_blocking(move || host.on_request(&api_type, ctx))
+            .await
+            .unwrap_or_default();
+        if let Some((plugin, reason)) = decision.reject {
+            let message = format!("Request rejected by plugin {}: {}", plugin, reason);
+            return errors::error_response(&state.api_type, ErrorKind::PluginRejected, &message, None);
+        }
+        plugin_route = decision.providers;
+        match decision.body {
+            // 插件可能改写了 model（如把子代理请求降级到更小的模型）
+            Some(body) => {
+                let model = endpoints::extract_model(&body, content_type);
+                (bytes::Bytes::from(body), model)
             }
-            return builder.body(Body::from(cached.response_body)).unwrap_or_default();
+            None => (body_bytes, requested_model),
+        }
+    };
+
+    // Cache check
+    let cache_directive = CacheDirective::from_headers(&headers);
+    let client_wants_stream = request_wants_stream(&body_bytes);
+    let cache_key = if cache_enabled
+        && (cache_directive.read || cache_directive.write)
+        && endpoint.admits_cache(&cache_admission, &body_bytes)
+    {
+        Some(CacheManager::generate_key(&api_type_str, &path, &body_bytes, &cache_ignored_fields))
+    } else {
+        None
+    };
+
+    if let Some(key) = cache_key.as_ref().filter(|_| cache_directive.read) {
+        if let Some(response) = cached_response(&state, key, client_wants_stream).await {
+            state.stats.record_cache_hit();
+            return response;
         }
         state.stats.record_cache_miss();
     }
+    let cache_key = cache_key.filter(|_| cache_directive.write);
+    let cache_meta = CacheMeta {
+        api_type: api_type_str.clone(),
+        path: path.clone(),
+        model: requested_model.clone().unwrap_or_else(|| "unknown".to_string()),
+        ttl_seconds: cache_ttl,
+        is_stream: false,
+    };
 
-    let input_tokens = calculate_input_tokens(&body_bytes);
-    let api_type_str = api_type_to_string(&state.api_type);
+    let input_tokens = endpoint.estimate_input_tokens(&body_bytes);
+
+    // 插件指定供应商时只尝试列出的供应商
+    let providers = match &plugin_route {
+        Some(ids) => {
+            let routed: Vec<Provider> = providers.into_iter().filter(|p| ids.contains(&p.id)).collect();
+            if routed.is_empty() {
+                let message = format!("Plugin selected no available provider: {}", ids.join(", "));
+                return errors::error_response(&state.api_type, ErrorKind::NoProviders, &message, None);
+            }
+            routed
+        }
+        None => providers,
+    };
 
     if providers.is_empty() {
-        return (StatusCode::SERVICE_UNAVAILABLE, "No active providers for this API type").into_response();
+        return errors::error_response(&state.api_type, ErrorKind::NoProviders, "No active providers for this API type", None);
     }
 
     let max_attempts = if fallback_enabled {
@@ -253,46 +683,52 @@ async fn handle_request<R: Runtime>(
         1
     };
 
-    // Deterministic tiebreak for this request.
-    let mut candidates = providers;
-    candidates.sort_by(|a, b| {
-        let ca = state.circuits.get(&a.id).map(|c| c.clone()).unwrap_or_default();
-        let cb = state.circuits.get(&b.id).map(|c| c.clone()).unwrap_or_default();
-        let sa = ca.score(a.weight, now);
-        let sb = cb.score(b.weight, now);
-
-        let a_can = ca.can_attempt(now);
-        let b_can = cb.can_attempt(now);
-
-        // Prefer providers we can attempt now.
-        match b_can.cmp(&a_can) {
-            std::cmp::Ordering::Equal => {
-                // Prefer sooner recovery for those in cooldown.
-                let a_until = ca.open_until;
-                let b_until = cb.open_until;
-                match a_until.cmp(&b_until) {
-                    std::cmp::Ordering::Equal => {
-                        // Higher score first.
-                        match sb.partial_cmp(&sa).unwrap_or(std::cmp::Ordering::Equal) {
-                            std::cmp::Ordering::Equal => {
-                                let ta = hash_u64(&(request_id.as_str(), a.id.as_str()));
-                                let tb = hash_u64(&(request_id.as_str(), b.id.as_str()));
-                                ta.cmp(&tb)
-                            }
-                            other => other,
-                        }
-                    }
-                    other => other,
-                }
-            }
-            other => other,
+    // Order candidates by the configured strategy (deterministic tiebreak for this request).
+    let circuits_snapshot: HashMap<String, Circuit> = providers
+        .iter()
+        .filter_map(|p| state.circuits.get(&p.id).map(|c| (p.id.clone(), c.clone())))
+        .collect();
+    let session_fingerprint = affinity::session_fingerprint(&state.api_type, &body_bytes);
+    let selection_ctx = SelectionContext {
+        now,
+        request_id: &request_id,
+        round_robin_counter: state.round_robin.fetch_add(1, AtomicOrdering::Relaxed),
+        sticky_key: session_fingerprint.as_deref(),
+    };
+    let mut candidates = selection::order_candidates(selection_strategy, providers, &circuits_snapshot, &selection_ctx);
+    // 插件给出的顺序优先于选择策略与会话亲和
+    if let Some(ids) = &plugin_route {
+        candidates.sort_by_key(|p| ids.iter().position(|id| *id == p.id));
+    }
+
+    // Session affinity: keep a conversation on the provider (and key) it first succeeded on.
+    let session_fingerprint = session_fingerprint.filter(|_| affinity_settings.enabled);
+    let session_pin = session_fingerprint
+        .as_deref()
+        .and_then(|fingerprint| state.affinity.pinned(fingerprint, now, affinity_settings.ttl_seconds));
+    if let Some(pin) = session_pin.as_ref().filter(|_| plugin_route.is_none()) {
+        let available = circuits_snapshot
+            .get(&pin.provider_id)
+            .is_none_or(|c| c.can_attempt(now));
+        if let Some(pos) = candidates.iter().position(|p| p.id == pin.provider_id).filter(|_| available) {
+            let pinned = candidates.remove(pos);
+            candidates.insert(0, pinned);
         }
-    });
+    }
 
     let mut tried: HashSet<String> = HashSet::new();
     let mut attempted_any = false;
+    // 每个供应商的尝试结果，全部失败时随错误返回
+    let mut attempts: Vec<AttemptSummary> = Vec::new();
+    let skipped = |provider: &Provider, reason: &str| AttemptSummary {
+        provider: provider.name.clone(),
+        status: None,
+        failure_kind: reason.to_string(),
+        message: None,
+    };
+    let mut queue: VecDeque<Provider> = candidates.into_iter().take(max_attempts).collect();
 
-    for provider in candidates.into_iter().take(max_attempts) {
+    while let Some(provider) = queue.pop_front() {
         if tried.contains(&provider.id) {
             continue;
         }
@@ -300,15 +736,31 @@ async fn handle_request<R: Runtime>(
 
         let force = !attempted_any;
         if !reserve_provider_attempt(&state.circuits, &provider.id, now, force) {
+            attempts.push(skipped(&provider, "circuit_open"));
             continue;
         }
 
         let Some(_permit) = try_acquire_provider_permit(&state.inflight_limits, &provider.id, MAX_INFLIGHT_PER_PROVIDER) else {
             // Busy provider; release probe flag by marking as failure with a tiny cooldown.
             mark_busy_failure(&state.circuits, &provider.id, now);
+            attempts.push(skipped(&provider, "busy"));
             continue;
         };
 
+        // Pick one of the provider's keys; skip the provider when every key is cooling down.
+        let preferred_key = session_pin
+            .as_ref()
+            .filter(|pin| pin.provider_id == provider.id)
+            .and_then(|pin| pin.key_id.as_deref());
+        let api_key = state.keys.select(&provider, now, preferred_key, force);
+        if api_key.is_none() && !provider.keys().is_empty() {
+            mark_busy_failure(&state.circuits, &provider.id, now);
+            attempts.push(skipped(&provider, "keys_cooling_down"));
+            continue;
+        }
+        let api_key_id = api_key.as_ref().map(|k| k.id.clone());
+        let api_key_label = api_key.as_ref().map(|k| k.label.clone());
+
         attempted_any = true;
         let attempt_start = SystemTime::now();
 
@@ -321,13 +773,23 @@ async fn handle_request<R: Runtime>(
             },
         );
 
+        // 请求改写规则按供应商执行，在格式转换之前
+        let transformed = transforms::apply(&transform_rules, &state.api_type, Some(&provider.id), &body_bytes);
+        let attempt_body: &[u8] = transformed.body.as_deref().unwrap_or(&body_bytes);
+
         // Claude Code proxy mode only for Anthropic /v1/messages.
-        let is_messages_path = path.starts_with("/v1/messages");
+        let is_messages_path = path.trim_end_matches('/') == "/v1/messages";
         let use_proxy_conversion = provider.claude_code_proxy && state.api_type == ApiType::Anthropic && is_messages_path;
-        let requested_model = extract_model(&body_bytes).unwrap_or_else(|| "unknown".to_string());
+        let upstream_model = requested_model.as_deref().map(|m| provider.mapped_model(m));
+        let requested_model = requested_model.clone().unwrap_or_else(|| "unknown".to_string());
+        let stop_sequences = if use_proxy_conversion {
+            converter::extract_stop_sequences(attempt_body)
+        } else {
+            Vec::new()
+        };
 
         let (request_body, target_path) = if use_proxy_conversion {
-            match converter::anthropic_to_openai(&body_bytes, &provider.model_mapping) {
+            match converter::anthropic_to_openai(attempt_body, &provider) {
                 Ok(converted) => (converted, "/v1/chat/completions".to_string()),
                 Err(e) => {
                     // Bad client request; retrying other providers won't help.
@@ -340,74 +802,72 @@ async fn handle_request<R: Runtime>(
                         },
                     );
                     mark_busy_failure(&state.circuits, &provider.id, now);
-                    return (StatusCode::BAD_REQUEST, format!("Failed to convert request: {}", e)).into_response();
+                    let message = format!("Failed to convert request: {}", e);
+                    return errors::error_response(&state.api_type, ErrorKind::InvalidRequest, &message, None);
                 }
             }
         } else {
-            (body_bytes.to_vec(), path.clone())
+            (attempt_body.to_vec(), path.clone())
         };
 
-        let base = provider.base_url.trim_end_matches('/');
-        let url = format!("{}{}{}", base, target_path, query);
+        let url = provider_url(
+            &provider,
+            &target_path,
+            query.as_deref(),
+            upstream_model,
+        );
 
-        let mut new_req = state.http_client.request(method.clone(), &url);
+        // One client per distinct outbound proxy / TLS setting.
+        let sent = match state.clients.client_for(&provider) {
+            Ok(upstream) => {
+                let mut new_req = upstream.client.request(method.clone(), &url);
 
-        // Forward headers (exclude hop-by-hop and auth headers; gateway provides its own auth).
-        for (key, value) in &headers {
-            let key_str = key.as_str();
-            if key_str == "host"
-                || key_str == "content-length"
-                || key_str == "authorization"
-                || key_str == "x-api-key"
-                || key_str == "anthropic-version"
-                || key_str == "anthropic-beta"
-            {
-                continue;
-            }
-            new_req = new_req.header(key, value);
-        }
+                new_req = forward_client_headers(new_req, &headers, &provider);
 
-        // Provider auth
-        if !provider.api_key.is_empty() {
-            if use_proxy_conversion {
-                let auth_val = format!("Bearer {}", provider.api_key);
-                if let Ok(val) = HeaderValue::from_str(&auth_val) {
-                    new_req = new_req.header("Authorization", val);
+                // Provider auth
+                let anthropic_native = !use_proxy_conversion && state.api_type == ApiType::Anthropic;
+                new_req = apply_provider_auth(new_req, &provider, api_key.as_ref().map_or("", |k| k.secret.as_str()), anthropic_native);
+
+                // multipart 上传（音频转写、图片编辑）保留客户端的 Content-Type 与 boundary
+                if use_proxy_conversion || content_type.is_none() {
+                    new_req = new_req.header("Content-Type", "application/json");
                 }
-            } else {
-                match state.api_type {
-                    ApiType::Anthropic => {
-                        if let Ok(val) = HeaderValue::from_str(&provider.api_key) {
-                            new_req = new_req.header("x-api-key", val);
-                            new_req = new_req.header("anthropic-version", "2023-06-01");
-                        }
-                    }
-                    ApiType::OpenAIResponses | ApiType::OpenAIChat => {
-                        let auth_val = format!("Bearer {}", provider.api_key);
-                        if let Ok(val) = HeaderValue::from_str(&auth_val) {
-                            new_req = new_req.header("Authorization", val);
-                        }
-                    }
+                new_req = apply_provider_headers(new_req, &provider);
+                new_req = new_req.body(request_body);
+
+                // 改写规则中的请求头最后应用，可覆盖转发与鉴权请求头
+                let send = async {
+                    let mut request = new_req.build()?;
+                    transformed.apply_headers(request.headers_mut());
+                    upstream.client.execute(request).await
+                };
+                match timeout(UPSTREAM_HEADERS_TIMEOUT, send).await {
+                    Ok(Ok(resp)) => Ok(resp),
+                    Ok(Err(e)) => Err(clients::classify_send_error(&e, upstream.via_proxy)),
+                    Err(_) => Err((FailureKind::Timeout, "Upstream timeout".to_string())),
                 }
             }
-        }
-
-        new_req = new_req.header("Content-Type", "application/json");
-        new_req = new_req.body(request_body);
+            Err(e) => Err(e),
+        };
 
-        let resp = match timeout(UPSTREAM_HEADERS_TIMEOUT, new_req.send()).await {
-            Ok(Ok(resp)) => resp,
-            Ok(Err(e)) => {
+        let resp = match sent {
+            Ok(resp) => resp,
+            Err((kind, error_message)) => {
                 let duration = duration_ms(attempt_start);
                 let (until, failure_kind) = open_circuit(
                     &state.circuits,
                     &provider.id,
                     now,
                     base_cooldown_seconds,
-                    FailureKind::Connect,
+                    kind,
                     None,
                     &(now, &provider.id, &request_id),
                 );
+                let status = if kind == FailureKind::Timeout {
+                    StatusCode::GATEWAY_TIMEOUT
+                } else {
+                    StatusCode::BAD_GATEWAY
+                };
 
                 let _ = state.app.emit(
                     "gateway://provider-status",
@@ -423,7 +883,7 @@ async fn handle_request<R: Runtime>(
                     timestamp: now,
                     provider: provider.name.clone(),
                     model: requested_model.clone(),
-                    status: 502,
+                    status: status.as_u16(),
                     duration_ms: duration,
                     input_tokens,
                     output_tokens: 0,
@@ -432,56 +892,28 @@ async fn handle_request<R: Runtime>(
                     client_agent: user_agent.clone(),
                     api_type: api_type_str.clone(),
                     cached: false,
-                    error_message: Some(format!("Connection failed: {}", e)),
+                    error_message: Some(error_message.clone()),
+                    api_key_id: api_key_id.clone(),
+                    api_key_label: api_key_label.clone(),
+                    security_findings: security_findings.clone(),
                 });
                 state.stats.set_provider_cooldown(&provider.name, until, failure_kind);
-
-                if !fallback_enabled {
-                    return (StatusCode::BAD_GATEWAY, format!("Provider {} failed: {}", provider.name, e)).into_response();
-                }
-                continue;
-            }
-            Err(_) => {
-                let duration = duration_ms(attempt_start);
-                let (until, failure_kind) = open_circuit(
-                    &state.circuits,
-                    &provider.id,
-                    now,
-                    base_cooldown_seconds,
-                    FailureKind::Timeout,
-                    None,
-                    &(now, &provider.id, &request_id),
-                );
-
-                let _ = state.app.emit(
-                    "gateway://provider-status",
-                    ProviderStatusEvent {
-                        provider_id: provider.id.clone(),
-                        status: "error".to_string(),
-                        api_type: api_type_str.clone(),
-                    },
-                );
-
-                state.stats.record_request(RequestLog {
-                    id: uuid::Uuid::new_v4().to_string(),
-                    timestamp: now,
+                state.affinity.release_provider(&provider.id);
+                attempts.push(AttemptSummary {
                     provider: provider.name.clone(),
-                    model: requested_model.clone(),
-                    status: 504,
-                    duration_ms: duration,
-                    input_tokens,
-                    output_tokens: 0,
-                    cost: 0.0,
-                    path: path.clone(),
-                    client_agent: user_agent.clone(),
-                    api_type: api_type_str.clone(),
-                    cached: false,
-                    error_message: Some("Upstream timeout".to_string()),
+                    status: None,
+                    failure_kind: failure_kind_to_string(kind),
+                    message: Some(error_message.clone()),
                 });
-                state.stats.set_provider_cooldown(&provider.name, until, failure_kind);
 
                 if !fallback_enabled {
-                    return (StatusCode::GATEWAY_TIMEOUT, "Upstream timeout").into_response();
+                    let error_kind = if kind == FailureKind::Timeout {
+                        ErrorKind::UpstreamTimeout
+                    } else {
+                        ErrorKind::UpstreamUnavailable
+                    };
+                    let message = format!("Provider {} failed: {}", provider.name, error_message);
+                    return errors::error_response_with_attempts(&state.api_type, error_kind, &message, &attempts);
                 }
                 continue;
             }
@@ -514,15 +946,44 @@ async fn handle_request<R: Runtime>(
                 _ => bytes::Bytes::new(),
             };
             let failure_kind = failure_kind_from_status(status);
-            let (until, failure_kind) = open_circuit(
-                &state.circuits,
-                &provider.id,
-                now,
-                base_cooldown_seconds,
-                failure_kind,
-                retry_after,
-                &(now, &provider.id, &request_id, status.as_u16()),
-            );
+
+            // Rate limits and auth failures only cool down the offending key; the provider
+            // cools down once none of its keys are left.
+            let mut retry_other_key = false;
+            let provider_cooldown = match api_key.as_ref().filter(|_| keys::is_key_failure(failure_kind)) {
+                Some(key) => {
+                    let key_until = state.keys.on_failure(
+                        &provider.id,
+                        &key.id,
+                        now,
+                        base_cooldown_seconds,
+                        failure_kind,
+                        retry_after,
+                    );
+                    state.stats.set_key_cooldown(&provider.name, &key.id, &key.label, key_until, failure_kind);
+                    state.affinity.release_key(&provider.id, &key.id);
+
+                    if state.keys.has_available(&provider, now) {
+                        mark_busy_failure(&state.circuits, &provider.id, now);
+                        retry_other_key = true;
+                        None
+                    } else {
+                        Some(failure_kind)
+                    }
+                }
+                None => Some(failure_kind),
+            };
+            let provider_cooldown = provider_cooldown.map(|kind| {
+                open_circuit(
+                    &state.circuits,
+                    &provider.id,
+                    now,
+                    base_cooldown_seconds,
+                    kind,
+                    retry_after,
+                    &(now, &provider.id, &request_id, status.as_u16()),
+                )
+            });
 
             let error_body = truncate_utf8(body.as_ref(), 500);
             let duration = duration_ms(attempt_start);
@@ -551,9 +1012,27 @@ async fn handle_request<R: Runtime>(
                 api_type: api_type_str.clone(),
                 cached: false,
                 error_message: Some(format!("HTTP {} - {}", status, error_body)),
+                api_key_id: api_key_id.clone(),
+                api_key_label: api_key_label.clone(),
+                security_findings: security_findings.clone(),
+            });
+            if let Some((until, failure_kind)) = provider_cooldown {
+                state.stats.set_provider_cooldown(&provider.name, until, failure_kind);
+                state.affinity.release_provider(&provider.id);
+            }
+            attempts.push(AttemptSummary {
+                provider: provider.name.clone(),
+                status: Some(status.as_u16()),
+                failure_kind: failure_kind_to_string(failure_kind),
+                message: Some(truncate_utf8(body.as_ref(), 200)),
             });
-            state.stats.set_provider_cooldown(&provider.name, until, failure_kind);
 
+            if fallback_enabled && retry_other_key {
+                // Same provider, next key.
+                tried.remove(&provider.id);
+                queue.push_front(provider);
+                continue;
+            }
             if fallback_enabled && should_fallback {
                 continue;
             }
@@ -573,7 +1052,17 @@ async fn handle_request<R: Runtime>(
         // Success path.
         let duration = duration_ms(attempt_start);
         mark_success(&state.circuits, &provider.id, now, duration);
+        if let Some(key) = &api_key {
+            state.keys.on_success(&provider.id, &key.id, duration);
+        }
         state.stats.clear_provider_cooldown(&provider.name);
+        if let Some(fingerprint) = &session_fingerprint {
+            let pin = SessionPin {
+                provider_id: provider.id.clone(),
+                key_id: api_key_id.clone(),
+            };
+            state.affinity.pin(fingerprint, pin, now, &affinity_settings);
+        }
 
         let _ = state.app.emit(
             "gateway://provider-status",
@@ -584,24 +1073,30 @@ async fn handle_request<R: Runtime>(
             },
         );
 
-        let output_tokens = 0;
-        let cost = calculate_cost(input_tokens, output_tokens, provider.input_price_per_1k, provider.output_price_per_1k);
-        state.stats.record_request(RequestLog {
+        // 先按估算值计费；响应结束后按上游 usage 修正（流式响应解析最后的 usage 事件）
+        let estimated_usage = Usage {
+            input_tokens,
+            output_tokens: 0,
+        };
+        let request_log = |usage: Usage| RequestLog {
             id: uuid::Uuid::new_v4().to_string(),
             timestamp: now,
             provider: provider.name.clone(),
             model: requested_model.clone(),
             status: status.as_u16(),
             duration_ms: duration,
-            input_tokens,
-            output_tokens,
-            cost,
+            input_tokens: usage.input_tokens,
+            output_tokens: usage.output_tokens,
+            cost: endpoint.cost(usage, provider.input_price_per_1k, provider.output_price_per_1k),
             path: path.clone(),
             client_agent: user_agent.clone(),
             api_type: api_type_str.clone(),
             cached: false,
             error_message: None,
-        });
+            api_key_id: api_key_id.clone(),
+            api_key_label: api_key_label.clone(),
+            security_findings: security_findings.clone(),
+        };
 
         // Collect response headers for cache (exclude content-length as body may change).
         let response_headers: Vec<(String, String)> = resp
@@ -621,6 +1116,19 @@ async fn handle_request<R: Runtime>(
             }
         }
 
+        let response_ctx = ResponseContext {
+            request_id: request_id.clone(),
+            api_type: api_type_str.clone(),
+            path: path.clone(),
+            model: requested_model.clone(),
+            provider_id: provider.id.clone(),
+            provider_name: provider.name.clone(),
+            status: status.as_u16(),
+            latency_ms: duration,
+            stream: false,
+            body: serde_json::Value::Null,
+        };
+
         if !is_stream {
             let bytes = match timeout(UPSTREAM_BODY_TIMEOUT, resp.bytes()).await {
                 Ok(Ok(bytes)) => bytes,
@@ -628,7 +1136,7 @@ async fn handle_request<R: Runtime>(
             };
 
             let final_bytes = if use_proxy_conversion {
-                match converter::openai_response_to_anthropic(&bytes, &requested_model) {
+                match converter::openai_response_to_anthropic(&bytes, &requested_model, &stop_sequences) {
                     Ok(converted) => bytes::Bytes::from(converted),
                     Err(e) => {
                         let (until, failure_kind) = open_circuit(
@@ -641,21 +1149,44 @@ async fn handle_request<R: Runtime>(
                             &(now, &provider.id, &request_id, "convert"),
                         );
                         state.stats.set_provider_cooldown(&provider.name, until, failure_kind);
+                        state.affinity.release_provider(&provider.id);
+                        let message = format!("Failed to convert upstream response: {}", e);
+                        attempts.push(AttemptSummary {
+                            provider: provider.name.clone(),
+                            status: Some(status.as_u16()),
+                            failure_kind: "conversion".to_string(),
+                            message: Some(message.clone()),
+                        });
                         if fallback_enabled {
                             continue;
                         }
-                        return (StatusCode::BAD_GATEWAY, format!("Failed to convert upstream response: {}", e)).into_response();
+                        return errors::error_response_with_attempts(&state.api_type, ErrorKind::ConversionFailed, &message, &attempts);
                     }
                 }
             } else {
                 bytes
             };
 
-            if cache_enabled {
-                let cache_key = CacheManager::generate_key(&path, &body_bytes);
+            let final_bytes = if state.plugins.has_response_hook(&state.api_type) {
+                let host = state.plugins.clone();
+                let api_type = state.api_type.clone();
+                let body = final_bytes.clone();
+                let rewritten = tokio::task::spawn_blocking(move || host.on_response(&api_type, response_ctx, &body))
+                    .await
+                    .ok()
+                    .flatten();
+                rewritten.map(bytes::Bytes::from).unwrap_or(final_bytes)
+            } else {
+                final_bytes
+            };
+
+            let usage = endpoints::parse_usage(&final_bytes).unwrap_or(estimated_usage);
+            state.stats.record_request(request_log(usage));
+
+            if let Some(key) = cache_key.as_ref().filter(|_| cache_admission.admits_response(status.as_u16(), final_bytes.len())) {
                 state
                     .cache
-                    .set(cache_key, final_bytes.to_vec(), status.as_u16(), response_headers);
+                    .set(key.clone(), final_bytes.to_vec(), status.as_u16(), response_headers, cache_meta.clone());
             }
 
             // Ensure JSON content-type for converted responses.
@@ -672,18 +1203,26 @@ async fn handle_request<R: Runtime>(
         }
 
         // Stream response.
+        let stream_log = StreamLog {
+            stats: state.stats.clone(),
+            log: Some(request_log(estimated_usage)),
+            usage: StreamUsage::default(),
+            endpoint,
+            input_price_per_1k: provider.input_price_per_1k,
+            output_price_per_1k: provider.output_price_per_1k,
+        };
         if use_proxy_conversion {
             let message_id = format!(
                 "msg_{}",
                 uuid::Uuid::new_v4().to_string().replace("-", "")[..24].to_string()
             );
 
-            let stream = resp.bytes_stream();
+            // 用量从转换前的上游流中解析
+            let stream = usage_stream(resp.bytes_stream(), stream_log);
             let model_name = requested_model.clone();
             let converted_stream = async_stream::stream! {
+                let mut converter = converter::SseConverter::new(&message_id, &model_name, stop_sequences);
                 let mut buffer = String::new();
-                let mut is_first = true;
-                let mut stream_ended = false;
 
                 tokio::pin!(stream);
 
@@ -701,46 +1240,33 @@ async fn handle_request<R: Runtime>(
                                     continue;
                                 }
 
-                                let converted_events = converter::openai_sse_to_anthropic(line, &message_id, &model_name, is_first);
-                                if !converted_events.is_empty() && is_first {
-                                    is_first = false;
-                                }
-
-                                for event in &converted_events {
+                                for event in converter.convert_line(line) {
                                     yield Ok::<_, std::io::Error>(bytes::Bytes::from(format!("{}\n\n", event)));
-                                    if event.contains("message_stop") {
-                                        stream_ended = true;
-                                    }
                                 }
                             }
                         }
                         Err(e) => {
+                            // 上游中断时把错误传给客户端（且不写入缓存），不补齐收尾事件伪装成正常结束
                             eprintln!("Stream error: {}", e);
-                            break;
+                            yield Err(std::io::Error::other(e));
+                            return;
                         }
                     }
                 }
 
                 if !buffer.trim().is_empty() {
-                    let converted_events = converter::openai_sse_to_anthropic(buffer.trim(), &message_id, &model_name, is_first);
-                    for event in &converted_events {
+                    for event in converter.convert_line(buffer.trim()) {
                         yield Ok::<_, std::io::Error>(bytes::Bytes::from(format!("{}\n\n", event)));
-                        if event.contains("message_stop") {
-                            stream_ended = true;
-                        }
                     }
                 }
 
-                if !stream_ended {
-                    yield Ok::<_, std::io::Error>(bytes::Bytes::from(
-                        "event: content_block_stop\ndata: {\"type\":\"content_block_stop\",\"index\":0}\n\n",
-                    ));
-                    yield Ok::<_, std::io::Error>(bytes::Bytes::from(
-                        "event: message_delta\ndata: {\"type\":\"message_delta\",\"delta\":{\"stop_reason\":\"end_turn\",\"stop_sequence\":null},\"usage\":{\"output_tokens\":0}}\n\n",
-                    ));
-                    yield Ok::<_, std::io::Error>(bytes::Bytes::from(
-                        "event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n",
-                    ));
+                // 上游未发送结束标记就断开：按错误结束，避免截断的响应被当作完整响应缓存
+                if !converter.completed() {
+                    yield Err(std::io::Error::other("Upstream stream ended before completion"));
+                    return;
+                }
+                for event in converter.finish() {
+                    yield Ok::<_, std::io::Error>(bytes::Bytes::from(format!("{}\n\n", event)));
                 }
             };
 
@@ -751,11 +1277,35 @@ async fn handle_request<R: Runtime>(
                 );
             }
 
-            let body = Body::from_stream(converted_stream);
+            let converted_stream = plugin_stream(&state, response_ctx, converted_stream);
+            let body = match cache_key {
+                Some(key) => Body::from_stream(cache_stream(
+                    converted_stream,
+                    state.cache.clone(),
+                    key,
+                    status.as_u16(),
+                    response_headers,
+                    CacheMeta { is_stream: true, ..cache_meta.clone() },
+                    cache_admission,
+                )),
+                None => Body::from_stream(converted_stream),
+            };
             return builder.body(body).unwrap_or_default();
         }
 
-        let body = Body::from_stream(resp.bytes_stream());
+        let stream = plugin_stream(&state, response_ctx, usage_stream(resp.bytes_stream(), stream_log));
+        let body = match cache_key {
+            Some(key) => Body::from_stream(cache_stream(
+                stream,
+                state.cache.clone(),
+                key,
+                status.as_u16(),
+                response_headers,
+                CacheMeta { is_stream: true, ..cache_meta.clone() },
+                cache_admission,
+            )),
+            None => Body::from_stream(stream),
+        };
         return builder.body(body).unwrap_or_default();
     }
 
@@ -767,7 +1317,221 @@ async fn handle_request<R: Runtime>(
         request_id,
         overall_duration
     );
-    (StatusCode::BAD_GATEWAY, "All providers failed").into_response()
+    let message = match attempts.len() {
+        0 => "All providers failed".to_string(),
+        n => format!("All providers failed ({} attempted, see attempts for details)", n),
+    };
+    errors::error_response_with_attempts(&state.api_type, ErrorKind::AllProvidersFailed, &message, &attempts)
+}
+
+/// 从缓存构造响应
+/// 流式客户端命中非流式缓存时重放为 SSE；非流式客户端无法使用流式缓存
+async fn cached_response<R: Runtime>(state: &ProxyState<R>, key: &str, client_wants_stream: bool) -> Option<Response> {
+    let cached = state.cache.get(key).await?;
+
+    let (body, replayed) = match (client_wants_stream, cached.is_stream) {
+        (false, false) | (true, true) => (cached.response_body, false),
+        (true, false) => {
+            let events = converter::replay_as_sse(&state.api_type, &cached.response_body)?;
+            let body: String = events.iter().map(|e| format!("{}\n\n", e)).collect();
+            (body.into_bytes(), true)
+        }
+        (false, true) => return None,
+    };
+
+    let mut builder = Response::builder().status(cached.status);
+    if let Some(headers_mut) = builder.headers_mut() {
+        for (k, v) in &cached.headers {
+            if let (Ok(name), Ok(val)) = (k.parse::<axum::http::HeaderName>(), HeaderValue::from_str(v)) {
+                headers_mut.insert(name, val);
+            }
+        }
+        if replayed {
+            headers_mut.insert(
+                axum::http::header::CONTENT_TYPE,
+                HeaderValue::from_static("text/event-stream; charset=utf-8"),
+            );
+        }
+    }
+    Some(builder.body(Body::from(body)).unwrap_or_default())
+}
+
+/// 流式响应的请求日志：转发结束或客户端断开时按解析到的 usage 记录
+struct StreamLog {
+    stats: Arc<StatsManager>,
+    log: Option<RequestLog>,
+    usage: StreamUsage,
+    endpoint: EndpointFamily,
+    input_price_per_1k: f64,
+    output_price_per_1k: f64,
+}
+
+impl Drop for StreamLog {
+    fn drop(&mut self) {
+        let Some(mut log) = self.log.take() else {
+            return;
+        };
+        // 没有 usage 事件时保留估算值
+        if let Some(usage) = std::mem::take(&mut self.usage).finish() {
+            log.input_tokens = usage.input_tokens;
+            log.output_tokens = usage.output_tokens;
+            log.cost = self.endpoint.cost(usage, self.input_price_per_1k, self.output_price_per_1k);
+        }
+        self.stats.record_request(log);
+    }
+}
+
+/// 边转发边解析流式响应中的 usage
+fn usage_stream<S, E>(stream: S, mut stream_log: StreamLog) -> impl futures::Stream<Item = Result<bytes::Bytes, E>>
+where
+    S: futures::Stream<Item = Result<bytes::Bytes, E>> + Send + 'static,
+    E: Send + 'static,
+{
+    async_stream::stream! {
+        tokio::pin!(stream);
+
+        while let Some(item) = futures::StreamExt::next(&mut stream).await {
+            if let Ok(chunk) = &item {
+                stream_log.usage.feed(chunk);
+            }
+            yield item;
+        }
+        drop(stream_log);
+    }
+}
+
+/// 有插件定义 on_response 时边转发边收集流式响应，完整结束后交给插件（只读）
+fn plugin_stream<R: Runtime, S, E>(
+    state: &ProxyState<R>,
+    ctx: ResponseContext,
+    stream: S,
+) -> futures::stream::BoxStream<'static, Result<bytes::Bytes, E>>
+where
+    S: futures::Stream<Item = Result<bytes::Bytes, E>> + Send + 'static,
+    E: Send + 'static,
+{
+    if !state.plugins.has_response_hook(&state.api_type) {
+        return Box::pin(stream);
+    }
+    let host = state.plugins.clone();
+    let api_type = state.api_type.clone();
+
+    Box::pin(async_stream::stream! {
+        let mut collected: Vec<u8> = Vec::new();
+        let mut complete = true;
+
+        tokio::pin!(stream);
+
+        while let Some(item) = futures::StreamExt::next(&mut stream).await {
+            match &item {
+                Ok(chunk) if complete => {
+                    if collected.len() + chunk.len() > MAX_PLUGIN_STREAM_BYTES {
+                        eprintln!("Streamed response exceeds {} bytes; skipping on_response plugins", MAX_PLUGIN_STREAM_BYTES);
+                        complete = false;
+                        collected = Vec::new();
+                    } else {
+                        collected.extend_from_slice(chunk);
+                    }
+                }
+                Ok(_) => {}
+                Err(_) => complete = false,
+            }
+            yield item;
+        }
+
+        if complete {
+            let _ = tokio::task::spawn_blocking(move || host.on_stream_response(&api_type, ctx, &collected)).await;
+        }
+    })
+}
+
+/// 边转发边收集流式响应，收到结束事件且未出错时写入缓存
+fn cache_stream<S, E>(
+    stream: S,
+    cache: Arc<CacheManager>,
+    key: String,
+    status: u16,
+    headers: Vec<(String, String)>,
+    meta: CacheMeta,
+    admission: CacheAdmission,
+) -> impl futures::Stream<Item = Result<bytes::Bytes, E>>
+where
+    S: futures::Stream<Item = Result<bytes::Bytes, E>> + Send + 'static,
+    E: Send + 'static,
+{
+    async_stream::stream! {
+        let mut collected: Vec<u8> = Vec::new();
+        let mut cacheable = true;
+
+        tokio::pin!(stream);
+
+        while let Some(item) = futures::StreamExt::next(&mut stream).await {
+            match &item {
+                Ok(chunk) if cacheable => {
+                    if !admission.admits_response(status, collected.len() + chunk.len()) {
+                        cacheable = false;
+                        collected = Vec::new();
+                    } else {
+                        collected.extend_from_slice(chunk);
+                    }
+                }
+                Ok(_) => {}
+                Err(_) => cacheable = false,
+            }
+            yield item;
+        }
+
+        // 只缓存收到结束事件的完整响应，上游提前断开的截断响应不缓存
+        if cacheable && stream_completed(&collected) {
+            cache.set(key, collected, status, headers, meta);
+        }
+    }
+}
+
+/// SSE 响应是否以结束事件收尾：message_stop（Anthropic）、[DONE]（Chat）、response.completed（Responses）
+fn stream_completed(body: &[u8]) -> bool {
+    String::from_utf8_lossy(body)
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("data:"))
+        .map(str::trim)
+        .any(|data| {
+            data == "[DONE]"
+                || serde_json::from_str::<serde_json::Value>(data).is_ok_and(|v| {
+                    matches!(v["type"].as_str(), Some("message_stop" | "response.completed"))
+                })
+        })
+}
+
+/// 按大小上限读取请求体；超出上限返回 413，读取失败返回 400（均为 API 格式的错误）
+async fn read_body_limited(api_type: &ApiType, req: Request<Body>, limit: usize) -> Result<bytes::Bytes, Response> {
+    let too_large = || {
+        let message = format!("Request body exceeds the gateway limit of {} bytes", limit);
+        errors::error_response(api_type, ErrorKind::RequestTooLarge, &message, None)
+    };
+
+    let declared_length = req
+        .headers()
+        .get(axum::http::header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+    if declared_length.is_some_and(|len| len > limit as u64) {
+        return Err(too_large());
+    }
+
+    axum::body::to_bytes(req.into_body(), limit).await.map_err(|e| {
+        if e.into_inner().is::<http_body_util::LengthLimitError>() {
+            too_large()
+        } else {
+            errors::error_response(api_type, ErrorKind::InvalidRequest, "Failed to read request body", None)
+        }
+    })
+}
+
+fn request_wants_stream(body: &[u8]) -> bool {
+    serde_json::from_slice::<serde_json::Value>(body)
+        .ok()
+        .and_then(|v| v.get("stream").and_then(|s| s.as_bool()))
+        .unwrap_or(false)
 }
 
 fn duration_ms(start: SystemTime) -> u64 {
@@ -777,17 +1541,6 @@ fn duration_ms(start: SystemTime) -> u64 {
         .as_millis() as u64
 }
 
-fn hash_u64<T: Hash>(value: &T) -> u64 {
-    let mut hasher = DefaultHasher::new();
-    value.hash(&mut hasher);
-    hasher.finish()
-}
-
-fn extract_model(body: &[u8]) -> Option<String> {
-    let v = serde_json::from_slice::<serde_json::Value>(body).ok()?;
-    v.get("model").and_then(|m| m.as_str()).map(|s| s.to_string())
-}
-
 fn truncate_utf8(bytes: &[u8], max_chars: usize) -> String {
     let s = String::from_utf8_lossy(bytes);
     if s.chars().count() <= max_chars {
@@ -797,7 +1550,7 @@ fn truncate_utf8(bytes: &[u8], max_chars: usize) -> String {
     format!("{}...(truncated)", truncated)
 }
 
-fn failure_kind_from_status(status: StatusCode) -> FailureKind {
+pub fn failure_kind_from_status(status: StatusCode) -> FailureKind {
     match status {
         StatusCode::TOO_MANY_REQUESTS => FailureKind::RateLimit,
         StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN | StatusCode::PAYMENT_REQUIRED => FailureKind::Auth,
@@ -879,31 +1632,170 @@ fn mark_success(circuits: &DashMap<String, Circuit>, provider_id: &str, _now: u6
     entry.on_success(latency_ms);
 }
 
-fn calculate_input_tokens(body: &[u8]) -> u32 {
-    if let Ok(json) = serde_json::from_slice::<serde_json::Value>(body) {
-        if let Some(messages) = json.get("messages").and_then(|m| m.as_array()) {
-            let mut char_count = 0;
-            for msg in messages {
-                if let Some(content) = msg.get("content") {
-                    if let Some(s) = content.as_str() {
-                        char_count += s.len();
-                    } else if let Some(arr) = content.as_array() {
-                        for part in arr {
-                            if let Some(text) = part.get("text").and_then(|t| t.as_str()) {
-                                char_count += text.len();
-                            }
-                        }
-                    }
-                }
+/// 转发客户端请求头（排除 hop-by-hop 与鉴权相关头，鉴权由网关提供）
+/// 以及供应商配置的 remove_headers
+fn forward_client_headers(
+    mut req: reqwest::RequestBuilder,
+    headers: &axum::http::HeaderMap,
+    provider: &Provider,
+) -> reqwest::RequestBuilder {
+    for (key, value) in headers {
+        let key_str = key.as_str();
+        if key_str == "host"
+            || key_str == "content-length"
+            || key_str == "authorization"
+            || key_str == "x-api-key"
+            || key_str == "api-key"
+            || key_str == "anthropic-version"
+            || key_str == "anthropic-beta"
+        {
+            continue;
+        }
+        if provider.remove_headers.iter().any(|h| h.trim().eq_ignore_ascii_case(key_str)) {
+            continue;
+        }
+        req = req.header(key, value);
+    }
+    req
+}
+
+/// 为上游请求附加供应商鉴权
+/// anthropic_native: 按 Anthropic 原生协议请求，附加 anthropic-version，Auto 鉴权时使用 x-api-key
+pub(crate) fn apply_provider_auth(
+    mut req: reqwest::RequestBuilder,
+    provider: &Provider,
+    api_key: &str,
+    anthropic_native: bool,
+) -> reqwest::RequestBuilder {
+    if anthropic_native {
+        let version = provider
+            .anthropic_version
+            .as_deref()
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .unwrap_or(DEFAULT_ANTHROPIC_VERSION);
+        req = req.header("anthropic-version", version);
+    }
+    if api_key.is_empty() {
+        return req;
+    }
+
+    let style = match provider.auth_style {
+        AuthStyle::Auto if provider.kind == ProviderKind::Azure => AuthStyle::ApiKey,
+        AuthStyle::Auto if anthropic_native => AuthStyle::XApiKey,
+        AuthStyle::Auto => AuthStyle::Bearer,
+        style => style,
+    };
+    let (name, value) = match style {
+        AuthStyle::XApiKey => ("x-api-key", api_key.to_string()),
+        AuthStyle::ApiKey => ("api-key", api_key.to_string()),
+        AuthStyle::QueryKey => return req.query(&[("key", api_key)]),
+        AuthStyle::NoAuth => return req,
+        AuthStyle::Auto | AuthStyle::Bearer => ("Authorization", format!("Bearer {}", api_key)),
+    };
+    if let Ok(val) = HeaderValue::from_str(&value) {
+        req = req.header(name, val);
+    }
+    req
+}
+
+/// 附加供应商配置的固定请求头，覆盖同名的转发头与鉴权头
+pub(crate) fn apply_provider_headers(
+    req: reqwest::RequestBuilder,
+    provider: &Provider,
+) -> reqwest::RequestBuilder {
+    if provider.extra_headers.is_empty() {
+        return req;
+    }
+    let mut extra = reqwest::header::HeaderMap::new();
+    for (name, value) in &provider.extra_headers {
+        match (
+            reqwest::header::HeaderName::from_bytes(name.trim().as_bytes()),
+            HeaderValue::from_str(value),
+        ) {
+            (Ok(name), Ok(value)) => {
+                extra.insert(name, value);
             }
-            return (char_count as f64 / 4.0) as u32;
+            _ => eprintln!("Ignoring invalid header {:?} for provider {}", name, provider.name),
         }
     }
-    (body.len() as f64 / 4.0) as u32
+    req.headers(extra)
 }
 
-fn calculate_cost(input_tokens: u32, output_tokens: u32, input_price: f64, output_price: f64) -> f64 {
-    (input_tokens as f64 / 1000.0 * input_price) + (output_tokens as f64 / 1000.0 * output_price)
+/// 构造上游 URL
+/// 有 URL 模板（或供应商类型的默认模板）时按模板填充，否则为 base_url + path；
+/// 合并客户端查询参数与供应商查询参数（同名时以供应商配置为准）
+/// model: 映射后的模型名，用于填充 {model} / {deployment}
+pub(crate) fn provider_url(
+    provider: &Provider,
+    path: &str,
+    client_query: Option<&str>,
+    model: Option<&str>,
+) -> String {
+    let base = provider.base_url.trim_end_matches('/');
+    let endpoint = path
+        .strip_prefix("/v1")
+        .filter(|e| e.is_empty() || e.starts_with('/'))
+        .unwrap_or(path);
+    let template = provider
+        .url_template
+        .as_deref()
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .or_else(|| provider.kind.default_url_template(endpoint, model.is_some()));
+
+    let target = match template {
+        Some(template) => {
+            let model = encode_path_segment(model.unwrap_or_default());
+            let filled = template
+                .replace("{base}", base)
+                .replace("{path}", path)
+                .replace("{endpoint}", endpoint)
+                .replace("{model}", &model)
+                .replace("{deployment}", &model);
+            if filled.starts_with('/') {
+                format!("{}{}", base, filled)
+            } else {
+                filled
+            }
+        }
+        None => format!("{}{}", base, path),
+    };
+
+    let url = match client_query.filter(|q| !q.is_empty()) {
+        Some(q) if target.contains('?') => format!("{}&{}", target, q),
+        Some(q) => format!("{}?{}", target, q),
+        None => target,
+    };
+    let extra = provider.upstream_query_params();
+    if extra.is_empty() {
+        return url;
+    }
+
+    let Ok(mut parsed) = reqwest::Url::parse(&url) else {
+        return url;
+    };
+    let mut pairs: Vec<(String, String)> = parsed
+        .query_pairs()
+        .filter(|(k, _)| !extra.iter().any(|(name, _)| name == k))
+        .map(|(k, v)| (k.into_owned(), v.into_owned()))
+        .collect();
+    pairs.extend(extra);
+    parsed.query_pairs_mut().clear().extend_pairs(pairs);
+    parsed.to_string()
+}
+
+/// URL 路径段编码（模型名可能包含 `/` 等字符）
+fn encode_path_segment(segment: &str) -> String {
+    let mut encoded = String::with_capacity(segment.len());
+    for byte in segment.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~' | b':') {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
 }
 
 fn api_type_to_string(api_type: &ApiType) -> String {
@@ -5,6 +5,10 @@ use std::hash::{Hash, Hasher};
 pub enum FailureKind {
     Timeout,
     Connect,
+    // 出站代理不可达或隧道建立失败
+    Proxy,
+    // TLS 握手失败（证书不受信任、客户端证书被拒绝等），通常是配置问题
+    Tls,
     RateLimit,
     Auth,
     NotFound,
@@ -131,9 +135,10 @@ fn base_cooldown_for_kind(base: u64, kind: FailureKind, retry_after: Option<u64>
     match kind {
         FailureKind::RateLimit => retry_after.unwrap_or(base).max(base),
         FailureKind::Auth => base.saturating_mul(10).max(600),
-        FailureKind::NotFound => quick_base.saturating_mul(3),
+        FailureKind::NotFound | FailureKind::Tls => quick_base.saturating_mul(3),
         FailureKind::Timeout
         | FailureKind::Connect
+        | FailureKind::Proxy
         | FailureKind::Upstream5xx
         | FailureKind::Other => quick_base,
     }
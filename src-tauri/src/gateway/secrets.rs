@@ -0,0 +1,254 @@
+// 供应商密钥存储：磁盘上加密保存，支持 env:/file: 引用，返回前端时脱敏
+
+use crate::gateway::keys::mask_key;
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use anyhow::{anyhow, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+use std::sync::LazyLock;
+
+/// 加密后的密钥前缀（版本号便于以后更换算法）
+const ENCRYPTED_PREFIX: &str = "enc:v1:";
+/// 从环境变量读取：`env:OPENAI_API_KEY`
+const ENV_PREFIX: &str = "env:";
+/// 从文件读取（去除首尾空白）：`file:/run/secrets/openai`
+const FILE_PREFIX: &str = "file:";
+/// 主密钥文件，与 gateway_config.json 位于同一目录（Windows 上使用 DPAPI 按当前用户加密保存）
+const MASTER_KEY_FILE: &str = "gateway.key";
+const NONCE_LEN: usize = 12;
+/// 脱敏值末尾的密钥标识：`sk-…abcd#1a2b3c4d5e6f`
+const MASK_TAG_SEPARATOR: char = '#';
+const MASK_TAG_LEN: usize = 12;
+
+/// 脱敏标识使用的随机盐（每次启动重新生成），标识无法用于离线猜测密钥
+static MASK_SALT: LazyLock<[u8; 32]> = LazyLock::new(|| {
+    let mut salt = [0u8; 32];
+    OsRng.fill_bytes(&mut salt);
+    salt
+});
+
+pub struct SecretStore {
+    cipher: Aes256Gcm,
+}
+
+impl SecretStore {
+    /// 打开 dir 下的主密钥，不存在时生成（仅当前用户可读）
+    pub fn open(dir: &Path) -> Result<Self> {
+        let path = dir.join(MASTER_KEY_FILE);
+        let key_bytes = if path.exists() {
+            let bytes = read_private(&path).context("Failed to read gateway master key")?;
+            if bytes.len() != 32 {
+                return Err(anyhow!("Invalid gateway master key: {}", path.display()));
+            }
+            bytes
+        } else {
+            let key = Aes256Gcm::generate_key(OsRng);
+            fs::create_dir_all(dir).context("Failed to create config directory")?;
+            write_private(&path, key.as_slice()).context("Failed to write gateway master key")?;
+            key.to_vec()
+        };
+
+        Ok(Self {
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key_bytes)),
+        })
+    }
+
+    pub fn encrypt(&self, plaintext: &str) -> Result<String> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, plaintext.as_bytes())
+            .map_err(|_| anyhow!("Failed to encrypt secret"))?;
+
+        let mut payload = nonce.to_vec();
+        payload.extend_from_slice(&ciphertext);
+        Ok(format!("{}{}", ENCRYPTED_PREFIX, STANDARD.encode(payload)))
+    }
+
+    /// 解密 `enc:v1:` 值；其他值原样返回
+    pub fn decrypt(&self, stored: &str) -> Result<String> {
+        let Some(encoded) = stored.strip_prefix(ENCRYPTED_PREFIX) else {
+            return Ok(stored.to_string());
+        };
+        let payload = STANDARD.decode(encoded).context("Invalid encrypted secret")?;
+        if payload.len() <= NONCE_LEN {
+            return Err(anyhow!("Invalid encrypted secret"));
+        }
+        let (nonce, ciphertext) = payload.split_at(NONCE_LEN);
+        let plaintext = self
+            .cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow!("Failed to decrypt secret (master key changed?)"))?;
+        String::from_utf8(plaintext).context("Decrypted secret is not UTF-8")
+    }
+
+    /// 落盘前加密：明文密钥加密，引用与已加密值保持不变
+    pub fn seal(&self, value: &str) -> Result<String> {
+        if needs_encryption(value) {
+            self.encrypt(value)
+        } else {
+            Ok(value.to_string())
+        }
+    }
+}
+
+pub fn is_encrypted(value: &str) -> bool {
+    value.starts_with(ENCRYPTED_PREFIX)
+}
+
+/// `env:` / `file:` 引用本身不是密钥，原样保存与展示
+pub fn is_reference(value: &str) -> bool {
+    value.starts_with(ENV_PREFIX) || value.starts_with(FILE_PREFIX)
+}
+
+/// 需要加密保存的明文密钥
+pub fn needs_encryption(value: &str) -> bool {
+    !value.is_empty() && !is_encrypted(value) && !is_reference(value)
+}
+
+/// 解析为实际使用的密钥；引用无法解析、结果为空或仍是密文（解密失败）时返回 None
+pub fn resolve(value: &str) -> Option<String> {
+    let value = value.trim();
+    if is_encrypted(value) {
+        return None;
+    }
+    let resolved = if let Some(var) = value.strip_prefix(ENV_PREFIX) {
+        std::env::var(var.trim()).ok()?
+    } else if let Some(path) = value.strip_prefix(FILE_PREFIX) {
+        fs::read_to_string(path.trim()).ok()?
+    } else {
+        value.to_string()
+    };
+    let resolved = resolved.trim();
+    (!resolved.is_empty()).then(|| resolved.to_string())
+}
+
+/// 返回前端的脱敏值：引用原样显示，明文只保留首尾几位，并附加标识用于保存时还原
+pub fn mask(value: &str) -> String {
+    if value.is_empty() || is_reference(value) {
+        value.to_string()
+    } else {
+        format!("{}{}{}", mask_key(value), MASK_TAG_SEPARATOR, mask_tag(value))
+    }
+}
+
+/// 是否为 `mask` 生成的脱敏值（前端未修改该密钥）
+pub fn is_masked(value: &str) -> bool {
+    let Some((shown, tag)) = value.rsplit_once(MASK_TAG_SEPARATOR) else {
+        return false;
+    };
+    (shown == "****" || shown.contains('…'))
+        && tag.len() == MASK_TAG_LEN
+        && tag.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// 按脱敏值找回原始密钥；找不到或对应多个不同密钥时报错
+pub fn unmask<'a>(masked: &str, candidates: impl IntoIterator<Item = &'a String>) -> Result<&'a String> {
+    let mut found: Option<&'a String> = None;
+    for candidate in candidates {
+        if mask(candidate) != masked {
+            continue;
+        }
+        match found {
+            Some(existing) if existing != candidate => return Err(anyhow!("Ambiguous masked secret {}", masked)),
+            _ => found = Some(candidate),
+        }
+    }
+    found.ok_or_else(|| anyhow!("Unknown masked secret {}", masked))
+}
+
+fn mask_tag(value: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(MASK_SALT.as_slice());
+    hasher.update(value.as_bytes());
+    format!("{:x}", hasher.finalize())[..MASK_TAG_LEN].to_string()
+}
+
+fn write_private(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::io::Write;
+        use std::os::unix::fs::OpenOptionsExt;
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(path)?;
+        file.write_all(bytes)
+    }
+    #[cfg(windows)]
+    {
+        fs::write(path, dpapi::protect(bytes)?)
+    }
+    #[cfg(not(any(unix, windows)))]
+    {
+        fs::write(path, bytes)
+    }
+}
+
+fn read_private(path: &Path) -> std::io::Result<Vec<u8>> {
+    let bytes = fs::read(path)?;
+    #[cfg(windows)]
+    {
+        // 旧版本直接保存 32 字节主密钥，读取后改为 DPAPI 加密保存
+        if bytes.len() == 32 {
+            if let Err(e) = fs::write(path, dpapi::protect(&bytes)?) {
+                eprintln!("Failed to protect gateway master key: {}", e);
+            }
+            return Ok(bytes);
+        }
+        dpapi::unprotect(&bytes)
+    }
+    #[cfg(not(windows))]
+    {
+        Ok(bytes)
+    }
+}
+
+/// Windows DPAPI：使用当前用户的登录凭据加解密，其他用户无法读取主密钥
+#[cfg(windows)]
+mod dpapi {
+    use std::io;
+    use std::ptr;
+    use windows_sys::Win32::Foundation::LocalFree;
+    use windows_sys::Win32::Security::Cryptography::{
+        CryptProtectData, CryptUnprotectData, CRYPTPROTECT_UI_FORBIDDEN, CRYPT_INTEGER_BLOB,
+    };
+
+    pub fn protect(data: &[u8]) -> io::Result<Vec<u8>> {
+        transform(data, |input, output| unsafe {
+            CryptProtectData(input, ptr::null(), ptr::null(), ptr::null(), ptr::null(), CRYPTPROTECT_UI_FORBIDDEN, output)
+        })
+    }
+
+    pub fn unprotect(data: &[u8]) -> io::Result<Vec<u8>> {
+        transform(data, |input, output| unsafe {
+            CryptUnprotectData(input, ptr::null_mut(), ptr::null(), ptr::null(), ptr::null(), CRYPTPROTECT_UI_FORBIDDEN, output)
+        })
+    }
+
+    fn transform(
+        data: &[u8],
+        call: impl FnOnce(*const CRYPT_INTEGER_BLOB, *mut CRYPT_INTEGER_BLOB) -> i32,
+    ) -> io::Result<Vec<u8>> {
+        let input = CRYPT_INTEGER_BLOB {
+            cbData: data.len() as u32,
+            pbData: data.as_ptr() as *mut u8,
+        };
+        let mut output = CRYPT_INTEGER_BLOB {
+            cbData: 0,
+            pbData: ptr::null_mut(),
+        };
+        if call(&input, &mut output) == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // 输出缓冲区由系统分配，复制后释放
+        let result = unsafe { std::slice::from_raw_parts(output.pbData, output.cbData as usize) }.to_vec();
+        unsafe { LocalFree(output.pbData.cast()) };
+        Ok(result)
+    }
+}
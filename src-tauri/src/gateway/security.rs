@@ -0,0 +1,366 @@
+// 出站请求敏感信息检测：扫描请求体中的私钥、云凭证、JWT、高熵字符串与自定义 PII 正则
+// 按策略拒绝请求、脱敏后转发，或放行并记录（结果写入请求日志并通过 gateway://security 事件通知前端）
+
+use crate::gateway::transforms;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::ops::Range;
+use std::sync::LazyLock;
+
+/// 单次请求最多记录的命中数
+const MAX_FINDINGS: usize = 50;
+/// 高熵字符串的最小长度与熵阈值（bit / 字符）
+const ENTROPY_MIN_LEN: usize = 24;
+const ENTROPY_THRESHOLD: f64 = 4.0;
+
+/// 命中后的附加检查，排除明显不是密钥的片段
+type Check = fn(&str) -> bool;
+
+/// 内置密钥规则：(名称, 正则, 附加检查)；有捕获组时只标记/替换第一个捕获组
+static SECRET_PATTERNS: LazyLock<Vec<(&'static str, Regex, Option<Check>)>> = LazyLock::new(|| {
+    [
+        (
+            "private_key",
+            r"-----BEGIN [A-Z ]*PRIVATE KEY( BLOCK)?-----[\s\S]*?(-----END [A-Z ]*PRIVATE KEY( BLOCK)?-----|$)",
+        ),
+        ("aws_access_key", r"\b(?:AKIA|ASIA|ABIA|ACCA)[0-9A-Z]{16}\b"),
+        (
+            "aws_secret_key",
+            r#"(?i)aws_?secret_?access_?key["']?\s*[=:]\s*["']?([A-Za-z0-9/+=]{40})"#,
+        ),
+        ("gcp_api_key", r"\bAIza[0-9A-Za-z_\-]{35}"),
+        ("gcp_service_account", r#""private_key_id"\s*:\s*"([0-9a-f]{40})""#),
+        ("azure_storage_key", r"(?i)AccountKey=([A-Za-z0-9+/=]{80,})"),
+        ("github_token", r"\b(?:gh[pousr]_[A-Za-z0-9]{36,}|github_pat_[A-Za-z0-9_]{40,})"),
+        ("slack_token", r"\bxox[abposr]-[A-Za-z0-9-]{10,}"),
+        ("stripe_key", r"\b[rs]k_live_[0-9A-Za-z]{20,}"),
+        ("anthropic_api_key", r"\bsk-ant-[A-Za-z0-9_\-]{20,}"),
+        ("openai_api_key", r"\bsk-(?:proj-|svcacct-)?[A-Za-z0-9_\-]{32,}"),
+        ("jwt", r"\beyJ[A-Za-z0-9_\-]{8,}\.eyJ[A-Za-z0-9_\-]{8,}\.[A-Za-z0-9_\-]{8,}"),
+        // .env / 配置文件中的赋值：只标记值部分
+        (
+            "secret_assignment",
+            r#"(?i)\b[A-Z0-9_]*(?:SECRET|PASSWORD|PASSWD|TOKEN|API_?KEY|ACCESS_?KEY|CREDENTIALS?)[A-Z0-9_]*["']?\s*[=:]\s*["']?([A-Za-z0-9+/=_\-.~]{12,})"#,
+        ),
+    ]
+    .into_iter()
+    .map(|(name, pattern)| {
+        // 赋值的值需同时包含字母与数字，排除 `token = tokenizer.encode` 之类的代码
+        let check: Option<Check> = (name == "secret_assignment").then_some(mixed_alphanumeric as Check);
+        (name, Regex::new(pattern).expect("invalid built-in secret pattern"), check)
+    })
+    .collect()
+});
+
+/// 高熵候选片段（不含 `/`，避免把文件路径当作密钥）
+static ENTROPY_CANDIDATE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"[A-Za-z0-9+_\-]{24,}={0,2}").expect("invalid entropy pattern"));
+
+/// 命中后的处理方式
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SecurityPolicy {
+    /// 放行并记录
+    #[default]
+    Warn,
+    /// 将命中内容替换为 [REDACTED:规则名] 后转发
+    Redact,
+    /// 拒绝请求，返回 API 格式的错误
+    Block,
+}
+
+/// 自定义 PII 规则
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PiiPattern {
+    pub name: String,
+    pub pattern: String,
+    // 覆盖全局策略（如密钥拒绝、邮箱仅脱敏）
+    #[serde(default)]
+    pub policy: Option<SecurityPolicy>,
+}
+
+/// 检测设置（从配置读取）
+#[derive(Debug, Clone)]
+pub struct ScanSettings {
+    pub enabled: bool,
+    pub policy: SecurityPolicy,
+    pub entropy: bool,
+    pub pii_patterns: Vec<PiiPattern>,
+}
+
+/// 单条命中（只保留脱敏预览，不记录原文）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SecurityFinding {
+    // 规则名称，如 aws_access_key / high_entropy / 自定义 PII 名称
+    pub detector: String,
+    // secret / pii
+    pub category: String,
+    // 所在的 JSON 路径，如 messages.0.content
+    pub path: String,
+    pub preview: String,
+    pub count: u32,
+    pub action: SecurityPolicy,
+}
+
+/// 检测结果
+#[derive(Debug, Clone)]
+pub struct ScanResult {
+    pub findings: Vec<SecurityFinding>,
+    pub blocked: bool,
+    // 脱敏后的请求体（没有需要脱敏的命中时为空）
+    pub body: Option<Vec<u8>>,
+}
+
+impl ScanResult {
+    /// 整体处理结果：blocked / redacted / allowed
+    pub fn action(&self) -> &'static str {
+        if self.blocked {
+            "blocked"
+        } else if self.body.is_some() {
+            "redacted"
+        } else {
+            "allowed"
+        }
+    }
+}
+
+/// gateway://security 事件
+#[derive(Debug, Clone, Serialize)]
+pub struct SecurityEvent {
+    pub request_id: String,
+    pub timestamp: u64,
+    pub api_type: String,
+    pub path: String,
+    pub model: String,
+    pub action: String,
+    pub findings: Vec<SecurityFinding>,
+}
+
+struct Detector {
+    name: String,
+    category: &'static str,
+    regex: Regex,
+    policy: SecurityPolicy,
+    check: Option<Check>,
+}
+
+/// 扫描请求体，没有命中时返回空
+/// 只扫描 JSON 请求中的字符串值；图片 base64、思考签名与各类 ID 字段不参与检测
+pub fn scan(settings: &ScanSettings, body: &[u8]) -> Option<ScanResult> {
+    if !settings.enabled {
+        return None;
+    }
+    let mut json = serde_json::from_slice::<Value>(body).ok()?;
+    let detectors = detectors(settings);
+
+    let mut findings = Vec::new();
+    let redacted = scan_value(&mut json, "", &detectors, &mut findings);
+    if findings.is_empty() {
+        return None;
+    }
+
+    let blocked = findings.iter().any(|f| f.action == SecurityPolicy::Block);
+    let body = if redacted && !blocked {
+        serde_json::to_vec(&json).ok()
+    } else {
+        None
+    };
+    Some(ScanResult {
+        findings,
+        blocked,
+        body,
+    })
+}
+
+/// 校验自定义 PII 正则（保存配置前调用）
+pub fn validate_patterns(patterns: &[PiiPattern]) -> Result<(), String> {
+    for p in patterns {
+        if p.name.trim().is_empty() {
+            return Err("PII pattern name must not be empty".to_string());
+        }
+        Regex::new(&p.pattern).map_err(|e| format!("PII pattern '{}': invalid pattern: {}", p.name, e))?;
+    }
+    Ok(())
+}
+
+/// 拒绝请求时返回给客户端的说明（不包含命中内容）
+pub fn block_message(findings: &[SecurityFinding]) -> (String, Option<String>) {
+    let blocked: Vec<&SecurityFinding> = findings
+        .iter()
+        .filter(|f| f.action == SecurityPolicy::Block)
+        .collect();
+    let detail = blocked
+        .iter()
+        .map(|f| format!("{} in {}", f.detector, f.path))
+        .collect::<Vec<_>>()
+        .join(", ");
+    (
+        format!("Request blocked by gateway security policy: detected {}", detail),
+        blocked.first().map(|f| f.path.clone()),
+    )
+}
+
+fn detectors(settings: &ScanSettings) -> Vec<Detector> {
+    let mut detectors: Vec<Detector> = SECRET_PATTERNS
+        .iter()
+        .map(|(name, regex, check)| Detector {
+            name: name.to_string(),
+            category: "secret",
+            regex: regex.clone(),
+            policy: settings.policy,
+            check: *check,
+        })
+        .collect();
+    detectors.extend(settings.pii_patterns.iter().filter_map(|p| {
+        Some(Detector {
+            name: p.name.clone(),
+            category: "pii",
+            regex: transforms::cached_regex(&p.pattern)?,
+            policy: p.policy.unwrap_or(settings.policy),
+            check: None,
+        })
+    }));
+    // 高熵检测放在最后，已被其他规则命中的片段不重复记录
+    if settings.entropy {
+        detectors.push(Detector {
+            name: "high_entropy".to_string(),
+            category: "secret",
+            regex: ENTROPY_CANDIDATE.clone(),
+            policy: settings.policy,
+            check: Some(high_entropy),
+        });
+    }
+    detectors
+}
+
+/// 跳过不含用户文本的字段：base64 图片 / 文件、思考签名、加密推理内容与各类 ID
+fn skipped_key(key: &str) -> bool {
+    matches!(key, "data" | "signature" | "encrypted_content" | "id") || key.ends_with("_id")
+}
+
+/// 递归扫描字符串值，返回是否有内容被脱敏
+fn scan_value(value: &mut Value, path: &str, detectors: &[Detector], findings: &mut Vec<SecurityFinding>) -> bool {
+    let child = |key: &str| {
+        if path.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", path, key)
+        }
+    };
+    match value {
+        Value::String(s) => scan_string(s, path, detectors, findings),
+        Value::Array(items) => items.iter_mut().enumerate().fold(false, |changed, (i, item)| {
+            scan_value(item, &child(&i.to_string()), detectors, findings) | changed
+        }),
+        Value::Object(map) => map
+            .iter_mut()
+            .filter(|(key, _)| !skipped_key(key))
+            .fold(false, |changed, (key, item)| {
+                scan_value(item, &child(key), detectors, findings) | changed
+            }),
+        _ => false,
+    }
+}
+
+fn scan_string(s: &mut String, path: &str, detectors: &[Detector], findings: &mut Vec<SecurityFinding>) -> bool {
+    // data URL（OpenAI 图片输入）
+    if s.starts_with("data:") {
+        return false;
+    }
+
+    let mut spans: Vec<(Range<usize>, &Detector)> = Vec::new();
+    for detector in detectors {
+        for caps in detector.regex.captures_iter(s) {
+            let Some(m) = caps.get(1).or_else(|| caps.get(0)) else {
+                continue;
+            };
+            let range = m.range();
+            if range.is_empty() || spans.iter().any(|(r, _)| r.start < range.end && range.start < r.end) {
+                continue;
+            }
+            if detector.check.is_some_and(|check| !check(m.as_str())) {
+                continue;
+            }
+            record(findings, detector, path, m.as_str());
+            spans.push((range, detector));
+        }
+    }
+
+    let mut redactions: Vec<(Range<usize>, &Detector)> = spans
+        .into_iter()
+        .filter(|(_, d)| d.policy == SecurityPolicy::Redact)
+        .collect();
+    if redactions.is_empty() {
+        return false;
+    }
+    redactions.sort_by_key(|(r, _)| r.start);
+    let mut out = String::with_capacity(s.len());
+    let mut last = 0;
+    for (range, detector) in redactions {
+        out.push_str(&s[last..range.start]);
+        out.push_str(&format!("[REDACTED:{}]", detector.name));
+        last = range.end;
+    }
+    out.push_str(&s[last..]);
+    *s = out;
+    true
+}
+
+/// 同一规则在同一字段的多次命中合并计数
+fn record(findings: &mut Vec<SecurityFinding>, detector: &Detector, path: &str, matched: &str) {
+    if let Some(existing) = findings
+        .iter_mut()
+        .find(|f| f.detector == detector.name && f.path == path)
+    {
+        existing.count += 1;
+        return;
+    }
+    if findings.len() >= MAX_FINDINGS {
+        return;
+    }
+    findings.push(SecurityFinding {
+        detector: detector.name.clone(),
+        category: detector.category.to_string(),
+        path: path.to_string(),
+        preview: mask(matched),
+        count: 1,
+        action: detector.policy,
+    });
+}
+
+/// 只保留前 4 个字符
+fn mask(matched: &str) -> String {
+    let prefix: String = matched.chars().take(4).collect();
+    format!("{}****", prefix)
+}
+
+fn mixed_alphanumeric(value: &str) -> bool {
+    value.bytes().any(|b| b.is_ascii_alphabetic()) && value.bytes().any(|b| b.is_ascii_digit())
+}
+
+/// 高熵判断：同时包含大写、小写与数字，且香农熵不低于阈值（排除十六进制哈希、UUID 与普通标识符）
+fn high_entropy(token: &str) -> bool {
+    let token = token.trim_end_matches('=');
+    if token.len() < ENTROPY_MIN_LEN
+        || !token.bytes().any(|b| b.is_ascii_uppercase())
+        || !token.bytes().any(|b| b.is_ascii_lowercase())
+        || !token.bytes().any(|b| b.is_ascii_digit())
+    {
+        return false;
+    }
+    let mut counts = [0usize; 256];
+    for b in token.bytes() {
+        counts[b as usize] += 1;
+    }
+    let len = token.len() as f64;
+    let entropy: f64 = counts
+        .iter()
+        .filter(|&&c| c > 0)
+        .map(|&c| {
+            let p = c as f64 / len;
+            -p * p.log2()
+        })
+        .sum();
+    entropy >= ENTROPY_THRESHOLD
+}
@@ -0,0 +1,248 @@
+// 供应商选择策略：决定一次请求中尝试供应商的顺序
+
+use crate::gateway::config::Provider;
+use crate::gateway::resilience::Circuit;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SelectionStrategy {
+    /// 权重 × 健康度 × 延迟综合评分（默认）
+    #[default]
+    Weighted,
+    /// EWMA 延迟最低优先，尚无延迟样本的供应商优先探测
+    LeastLatency,
+    /// 按配置顺序轮询，均匀分摊请求
+    RoundRobin,
+    /// 单价最低优先（输入 + 输出 $/1K tokens）
+    CostFirst,
+    /// 同一会话 Key 固定落到同一供应商（保持上游 prompt cache 命中），无 Key 时退化为 Weighted
+    Sticky,
+}
+
+/// 单次请求的选择上下文
+pub struct SelectionContext<'a> {
+    pub now: u64,
+    // 用于同分时的确定性打散
+    pub request_id: &'a str,
+    // 轮询计数（每个 API 类型独立递增）
+    pub round_robin_counter: u64,
+    // 会话 Key（Sticky 策略使用）
+    pub sticky_key: Option<&'a str>,
+}
+
+/// 按策略对候选供应商排序
+/// 所有策略都优先可立即尝试的供应商，其次是冷却最早结束的供应商，之后才应用策略本身的排序
+pub fn order_candidates(
+    strategy: SelectionStrategy,
+    mut providers: Vec<Provider>,
+    circuits: &HashMap<String, Circuit>,
+    ctx: &SelectionContext,
+) -> Vec<Provider> {
+    let len = providers.len().max(1);
+    let rotation = (ctx.round_robin_counter % len as u64) as usize;
+    // 配置顺序（用于轮询）
+    let positions: HashMap<String, usize> = providers
+        .iter()
+        .enumerate()
+        .map(|(i, p)| (p.id.clone(), i))
+        .collect();
+
+    let circuit_of = |id: &str| circuits.get(id).cloned().unwrap_or_default();
+
+    providers.sort_by(|a, b| {
+        let ca = circuit_of(&a.id);
+        let cb = circuit_of(&b.id);
+
+        // Prefer providers we can attempt now.
+        let a_can = ca.can_attempt(ctx.now);
+        let b_can = cb.can_attempt(ctx.now);
+
+        b_can
+            .cmp(&a_can)
+            // Prefer sooner recovery when both are in cooldown; an expired
+            // cooldown must not rank behind never-failed providers.
+            .then_with(|| {
+                if a_can || b_can {
+                    Ordering::Equal
+                } else {
+                    ca.open_until.cmp(&cb.open_until)
+                }
+            })
+            .then_with(|| match strategy {
+                SelectionStrategy::Weighted => compare_score(a, &ca, b, &cb, ctx.now),
+                SelectionStrategy::LeastLatency => ca
+                    .ewma_latency_ms
+                    .partial_cmp(&cb.ewma_latency_ms)
+                    .unwrap_or(Ordering::Equal)
+                    .then_with(|| ca.consecutive_failures.cmp(&cb.consecutive_failures)),
+                SelectionStrategy::RoundRobin => {
+                    let pa = (positions[&a.id] + len - rotation) % len;
+                    let pb = (positions[&b.id] + len - rotation) % len;
+                    pa.cmp(&pb)
+                }
+                SelectionStrategy::CostFirst => unit_price(a)
+                    .partial_cmp(&unit_price(b))
+                    .unwrap_or(Ordering::Equal)
+                    .then_with(|| compare_score(a, &ca, b, &cb, ctx.now)),
+                SelectionStrategy::Sticky => match ctx.sticky_key {
+                    Some(key) => {
+                        let ha = hash_u64(&(key, a.id.as_str()));
+                        let hb = hash_u64(&(key, b.id.as_str()));
+                        hb.cmp(&ha)
+                    }
+                    None => compare_score(a, &ca, b, &cb, ctx.now),
+                },
+            })
+            .then_with(|| {
+                let ta = hash_u64(&(ctx.request_id, a.id.as_str()));
+                let tb = hash_u64(&(ctx.request_id, b.id.as_str()));
+                ta.cmp(&tb)
+            })
+    });
+
+    providers
+}
+
+/// Higher score first.
+fn compare_score(a: &Provider, ca: &Circuit, b: &Provider, cb: &Circuit, now: u64) -> Ordering {
+    let sa = ca.score(a.weight, now);
+    let sb = cb.score(b.weight, now);
+    sb.partial_cmp(&sa).unwrap_or(Ordering::Equal)
+}
+
+fn unit_price(provider: &Provider) -> f64 {
+    provider.input_price_per_1k + provider.output_price_per_1k
+}
+
+fn hash_u64<T: Hash>(value: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn provider(id: &str, weight: u32, input_price: f64) -> Provider {
+        Provider {
+            id: id.to_string(),
+            name: id.to_string(),
+            weight,
+            input_price_per_1k: input_price,
+            enabled: true,
+            ..Default::default()
+        }
+    }
+
+    fn ctx(counter: u64, sticky_key: Option<&str>) -> SelectionContext<'_> {
+        SelectionContext {
+            now: 1_000,
+            request_id: "req",
+            round_robin_counter: counter,
+            sticky_key,
+        }
+    }
+
+    fn ids(providers: &[Provider]) -> Vec<&str> {
+        providers.iter().map(|p| p.id.as_str()).collect()
+    }
+
+    #[test]
+    fn weighted_prefers_higher_weight() {
+        let providers = vec![provider("a", 10, 0.0), provider("b", 100, 0.0)];
+        let ordered = order_candidates(SelectionStrategy::Weighted, providers, &HashMap::new(), &ctx(0, None));
+        assert_eq!(ids(&ordered), vec!["b", "a"]);
+    }
+
+    #[test]
+    fn open_circuit_sorts_last_for_every_strategy() {
+        let mut circuits = HashMap::new();
+        circuits.insert(
+            "cheap".to_string(),
+            Circuit { open_until: 2_000, ..Default::default() },
+        );
+        for strategy in [
+            SelectionStrategy::Weighted,
+            SelectionStrategy::LeastLatency,
+            SelectionStrategy::RoundRobin,
+            SelectionStrategy::CostFirst,
+            SelectionStrategy::Sticky,
+        ] {
+            let providers = vec![provider("cheap", 100, 0.1), provider("pricey", 100, 5.0)];
+            let ordered = order_candidates(strategy, providers, &circuits, &ctx(0, Some("s")));
+            assert_eq!(ids(&ordered), vec!["pricey", "cheap"], "{:?}", strategy);
+        }
+    }
+
+    #[test]
+    fn expired_cooldown_keeps_strategy_order() {
+        let mut circuits = HashMap::new();
+        circuits.insert(
+            "cheap".to_string(),
+            Circuit { open_until: 500, ewma_latency_ms: 100.0, ..Default::default() },
+        );
+        circuits.insert("pricey".to_string(), Circuit { ewma_latency_ms: 200.0, ..Default::default() });
+        for strategy in [
+            SelectionStrategy::LeastLatency,
+            SelectionStrategy::RoundRobin,
+            SelectionStrategy::CostFirst,
+        ] {
+            let providers = vec![provider("cheap", 100, 0.1), provider("pricey", 100, 5.0)];
+            let ordered = order_candidates(strategy, providers, &circuits, &ctx(0, None));
+            assert_eq!(ids(&ordered), vec!["cheap", "pricey"], "{:?}", strategy);
+        }
+    }
+
+    #[test]
+    fn least_latency_prefers_faster_provider() {
+        let mut circuits = HashMap::new();
+        circuits.insert("slow".to_string(), Circuit { ewma_latency_ms: 900.0, ..Default::default() });
+        circuits.insert("fast".to_string(), Circuit { ewma_latency_ms: 120.0, ..Default::default() });
+        let providers = vec![provider("slow", 100, 0.0), provider("fast", 1, 0.0)];
+        let ordered = order_candidates(SelectionStrategy::LeastLatency, providers, &circuits, &ctx(0, None));
+        assert_eq!(ids(&ordered), vec!["fast", "slow"]);
+    }
+
+    #[test]
+    fn round_robin_rotates_through_config_order() {
+        let make = || vec![provider("a", 1, 0.0), provider("b", 100, 0.0), provider("c", 50, 0.0)];
+        let first: Vec<Vec<String>> = (0..4)
+            .map(|counter| {
+                let ordered = order_candidates(SelectionStrategy::RoundRobin, make(), &HashMap::new(), &ctx(counter, None));
+                ordered.iter().map(|p| p.id.clone()).collect()
+            })
+            .collect();
+        assert_eq!(first[0], vec!["a", "b", "c"]);
+        assert_eq!(first[1], vec!["b", "c", "a"]);
+        assert_eq!(first[2], vec!["c", "a", "b"]);
+        assert_eq!(first[3], first[0]);
+    }
+
+    #[test]
+    fn cost_first_prefers_cheapest() {
+        let providers = vec![provider("pricey", 100, 3.0), provider("cheap", 1, 0.5)];
+        let ordered = order_candidates(SelectionStrategy::CostFirst, providers, &HashMap::new(), &ctx(0, None));
+        assert_eq!(ids(&ordered), vec!["cheap", "pricey"]);
+    }
+
+    #[test]
+    fn sticky_is_stable_for_a_session_key() {
+        let make = || vec![provider("a", 100, 0.0), provider("b", 100, 0.0), provider("c", 100, 0.0)];
+        let first = order_candidates(SelectionStrategy::Sticky, make(), &HashMap::new(), &ctx(0, Some("session-1")));
+        for counter in 1..5 {
+            let request_id = format!("req-{}", counter);
+            let context = SelectionContext {
+                request_id: &request_id,
+                ..ctx(counter, Some("session-1"))
+            };
+            let again = order_candidates(SelectionStrategy::Sticky, make(), &HashMap::new(), &context);
+            assert_eq!(ids(&again), ids(&first));
+        }
+    }
+}
@@ -0,0 +1,76 @@
+// 网关监听：绑定地址解析与可选的 HTTPS 终止
+
+use crate::gateway::config::ServerTls;
+use axum::{extract::ConnectInfo, Router};
+use hyper::body::Incoming;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use std::fs;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio_native_tls::{native_tls, TlsAcceptor};
+use tower::ServiceExt;
+
+/// TLS 握手超时，避免半开连接长期占用
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 解析监听地址（IPv4 / IPv6，不含端口）
+pub fn parse_bind_address(bind_address: &str) -> Result<IpAddr, String> {
+    let trimmed = bind_address.trim().trim_start_matches('[').trim_end_matches(']');
+    trimmed
+        .parse::<IpAddr>()
+        .map_err(|_| format!("Invalid bind address: {}", bind_address))
+}
+
+pub fn socket_addr(bind_address: &str, port: u16) -> Result<SocketAddr, String> {
+    parse_bind_address(bind_address).map(|ip| SocketAddr::new(ip, port))
+}
+
+/// 读取证书与私钥，构建 TLS acceptor（保存配置时也用于校验）
+pub fn load_tls_acceptor(tls: &ServerTls) -> Result<TlsAcceptor, String> {
+    let cert = fs::read(tls.cert_path.trim())
+        .map_err(|e| format!("Failed to read certificate '{}': {}", tls.cert_path, e))?;
+    let key = fs::read(tls.key_path.trim())
+        .map_err(|e| format!("Failed to read private key '{}': {}", tls.key_path, e))?;
+    let identity = native_tls::Identity::from_pkcs8(&cert, &key)
+        .map_err(|e| format!("Invalid certificate or key (key must be PKCS#8 PEM): {}", e))?;
+    let acceptor = native_tls::TlsAcceptor::new(identity)
+        .map_err(|e| format!("Failed to initialize TLS: {}", e))?;
+    Ok(TlsAcceptor::from(acceptor))
+}
+
+/// 以 HTTPS 提供服务；每个连接单独握手，对端地址作为 ConnectInfo 传给访问控制
+pub async fn serve_tls(listener: TcpListener, router: Router, acceptor: TlsAcceptor) {
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                eprintln!("Failed to accept connection: {}", e);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+
+        let acceptor = acceptor.clone();
+        let router = router.clone();
+        tokio::spawn(async move {
+            let stream = match tokio::time::timeout(TLS_HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                Ok(Ok(stream)) => stream,
+                Ok(Err(e)) => {
+                    eprintln!("TLS handshake with {} failed: {}", peer, e);
+                    return;
+                }
+                Err(_) => return,
+            };
+
+            let service = hyper::service::service_fn(move |mut req: hyper::Request<Incoming>| {
+                req.extensions_mut().insert(ConnectInfo(peer));
+                router.clone().oneshot(req)
+            });
+            let _ = auto::Builder::new(TokioExecutor::new())
+                .serve_connection_with_upgrades(TokioIo::new(stream), service)
+                .await;
+        });
+    }
+}
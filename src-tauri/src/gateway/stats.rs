@@ -1,4 +1,5 @@
 use crate::gateway::resilience::FailureKind;
+use crate::gateway::security::SecurityFinding;
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, VecDeque};
 use std::fs;
@@ -26,6 +27,14 @@ pub struct RequestLog {
     pub cached: bool,
     #[serde(default)]
     pub error_message: Option<String>, // 完整错误信息
+    // 使用的 API Key（标识 + 脱敏显示），供应商未配置 Key 时为空
+    #[serde(default)]
+    pub api_key_id: Option<String>,
+    #[serde(default)]
+    pub api_key_label: Option<String>,
+    // 请求体中检测到的敏感信息（仅脱敏预览）
+    #[serde(default)]
+    pub security_findings: Vec<SecurityFinding>,
 }
 
 fn default_path() -> String {
@@ -73,6 +82,10 @@ pub struct ProviderStats {
     #[serde(default)]
     pub cooldown_reason: Option<String>,
 
+    // 每个 API Key 的用量（key_id -> 统计）
+    #[serde(default)]
+    pub key_stats: HashMap<String, KeyStats>,
+
     // 延迟样本 (用于计算分位数，保留最近100个)
     #[serde(skip)]
     latency_samples: VecDeque<u64>,
@@ -159,6 +172,45 @@ impl ProviderStats {
     }
 }
 
+/// 单个 API Key 的用量统计，用于判断哪个账号额度耗尽
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct KeyStats {
+    pub key_id: String,
+    pub label: String,
+    pub total_requests: u64,
+    pub successful_requests: u64,
+    pub failed_requests: u64,
+    pub rate_limited_requests: u64,
+    pub total_input_tokens: u64,
+    pub total_output_tokens: u64,
+    pub last_used_at: Option<u64>,
+    pub last_error_message: Option<String>,
+    #[serde(default)]
+    pub cooldown_until: Option<u64>,
+    #[serde(default)]
+    pub cooldown_reason: Option<String>,
+}
+
+impl KeyStats {
+    fn record(&mut self, log: &RequestLog, success: bool) {
+        self.total_requests += 1;
+        self.last_used_at = Some(log.timestamp);
+        self.total_input_tokens += log.input_tokens as u64;
+        self.total_output_tokens += log.output_tokens as u64;
+        if success {
+            self.successful_requests += 1;
+            self.cooldown_until = None;
+            self.cooldown_reason = None;
+        } else {
+            self.failed_requests += 1;
+            if log.status == 429 {
+                self.rate_limited_requests += 1;
+            }
+            self.last_error_message = log.error_message.clone();
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct HourlyStat {
     pub timestamp: u64,
@@ -168,6 +220,20 @@ pub struct HourlyStat {
     pub cost: f64,
 }
 
+/// 响应缓存统计（由缓存管理器在读取统计时填充）
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct CacheStats {
+    pub memory_entries: u64,
+    pub memory_bytes: u64,
+    pub disk_entries: u64,
+    pub disk_bytes: u64,
+    pub max_memory_bytes: u64,
+    pub max_disk_bytes: u64,
+    // 本次运行期间因容量淘汰的条目数
+    pub evictions: u64,
+    pub hit_ratio: f64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct GatewayStats {
     // 全局统计
@@ -177,6 +243,14 @@ pub struct GatewayStats {
     pub total_cost: f64,
     pub cache_hits: u64,
     pub cache_misses: u64,
+    #[serde(default)]
+    pub cache: CacheStats,
+    // 当前固定到供应商的会话数（会话亲和）
+    #[serde(default)]
+    pub pinned_sessions: usize,
+    // 被安全策略拒绝的请求数
+    #[serde(default)]
+    pub blocked_requests: u64,
 
     // 按 API 类型统计
     pub anthropic_requests: u64,
@@ -241,6 +315,18 @@ impl StatsManager {
             .entry(log.provider.clone())
             .or_insert_with(|| ProviderStats::new(log.provider.clone(), log.provider.clone()));
 
+        if let Some(key_id) = &log.api_key_id {
+            provider_stats
+                .key_stats
+                .entry(key_id.clone())
+                .or_insert_with(|| KeyStats {
+                    key_id: key_id.clone(),
+                    label: log.api_key_label.clone().unwrap_or_default(),
+                    ..Default::default()
+                })
+                .record(&log, is_success);
+        }
+
         provider_stats.record_request(
             is_success,
             log.duration_ms,
@@ -320,6 +406,36 @@ impl StatsManager {
         self.persist_locked(&stats);
     }
 
+    /// 记录单个 Key 的冷却（限流或鉴权失败），供应商本身不受影响
+    pub fn set_key_cooldown(
+        &self,
+        provider_name: &str,
+        key_id: &str,
+        label: &str,
+        cooldown_until: u64,
+        kind: FailureKind,
+    ) {
+        let mut stats = self.stats.lock().unwrap();
+        let k = stats
+            .provider_stats
+            .entry(provider_name.to_string())
+            .or_insert_with(|| {
+                ProviderStats::new(provider_name.to_string(), provider_name.to_string())
+            })
+            .key_stats
+            .entry(key_id.to_string())
+            .or_insert_with(|| KeyStats {
+                key_id: key_id.to_string(),
+                label: label.to_string(),
+                ..Default::default()
+            });
+
+        k.cooldown_until = Some(cooldown_until);
+        k.cooldown_reason = Some(failure_kind_to_string(kind));
+
+        self.persist_locked(&stats);
+    }
+
     pub fn clear_provider_cooldown(&self, provider_name: &str) {
         let mut stats = self.stats.lock().unwrap();
         if let Some(p) = stats.provider_stats.get_mut(provider_name) {
@@ -332,6 +448,17 @@ impl StatsManager {
         self.persist_locked(&stats);
     }
 
+    /// 记录被安全策略拒绝的请求：只计入最近请求列表，不影响供应商统计
+    pub fn record_blocked(&self, log: RequestLog) {
+        let mut stats = self.stats.lock().unwrap();
+        stats.blocked_requests += 1;
+        stats.recent_requests.push_front(log);
+        if stats.recent_requests.len() > 50 {
+            stats.recent_requests.pop_back();
+        }
+        self.persist_locked(&stats);
+    }
+
     pub fn record_cache_hit(&self) {
         let mut stats = self.stats.lock().unwrap();
         stats.cache_hits += 1;
@@ -362,10 +489,12 @@ impl StatsManager {
     }
 }
 
-fn failure_kind_to_string(kind: FailureKind) -> String {
+pub fn failure_kind_to_string(kind: FailureKind) -> String {
     match kind {
         FailureKind::Timeout => "timeout".to_string(),
         FailureKind::Connect => "connect".to_string(),
+        FailureKind::Proxy => "proxy".to_string(),
+        FailureKind::Tls => "tls".to_string(),
         FailureKind::RateLimit => "rate_limit".to_string(),
         FailureKind::Auth => "auth".to_string(),
         FailureKind::NotFound => "not_found".to_string(),
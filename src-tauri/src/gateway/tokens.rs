@@ -0,0 +1,83 @@
+// Token 估算：Claude 分词器未公开，使用 cl100k 分词器近似计算
+
+use serde_json::Value;
+use tiktoken_rs::cl100k_base_singleton;
+
+/// 每条消息的固定开销（角色标记、分隔符）
+const MESSAGE_OVERHEAD_TOKENS: u32 = 4;
+/// 图片无法获知尺寸时的估算值（约 1092x1092 图片）
+const IMAGE_TOKENS: u32 = 1600;
+
+/// 计算文本的 token 数
+pub fn count_text_tokens(text: &str) -> u32 {
+    if text.is_empty() {
+        return 0;
+    }
+    cl100k_base_singleton().encode_ordinary(text).len() as u32
+}
+
+/// 估算 Anthropic Messages 请求 (`/v1/messages/count_tokens`) 的输入 token 数
+/// 计入 system、messages 中的所有内容块以及 tools 定义
+pub fn count_anthropic_input_tokens(request: &Value) -> u32 {
+    let mut total = 0u32;
+
+    if let Some(system) = request.get("system") {
+        total += count_content_tokens(system);
+    }
+
+    if let Some(messages) = request.get("messages").and_then(|m| m.as_array()) {
+        for msg in messages {
+            total += MESSAGE_OVERHEAD_TOKENS;
+            if let Some(content) = msg.get("content") {
+                total += count_content_tokens(content);
+            }
+        }
+    }
+
+    if let Some(tools) = request.get("tools").and_then(|t| t.as_array()) {
+        for tool in tools {
+            total += count_json_tokens(tool);
+        }
+    }
+
+    total
+}
+
+/// 计算 content 字段（字符串或内容块数组）的 token 数
+fn count_content_tokens(content: &Value) -> u32 {
+    match content {
+        Value::String(s) => count_text_tokens(s),
+        Value::Array(blocks) => blocks.iter().map(count_block_tokens).sum(),
+        _ => 0,
+    }
+}
+
+fn count_block_tokens(block: &Value) -> u32 {
+    let block_type = block.get("type").and_then(|t| t.as_str()).unwrap_or("text");
+    match block_type {
+        "text" => block
+            .get("text")
+            .and_then(|t| t.as_str())
+            .map(count_text_tokens)
+            .unwrap_or(0),
+        "thinking" => block
+            .get("thinking")
+            .and_then(|t| t.as_str())
+            .map(count_text_tokens)
+            .unwrap_or(0),
+        "image" => IMAGE_TOKENS,
+        "tool_use" => {
+            let name = block.get("name").and_then(|n| n.as_str()).unwrap_or("");
+            let input = block.get("input").map(count_json_tokens).unwrap_or(0);
+            count_text_tokens(name) + input
+        }
+        "tool_result" => block.get("content").map(count_content_tokens).unwrap_or(0),
+        _ => count_json_tokens(block),
+    }
+}
+
+fn count_json_tokens(value: &Value) -> u32 {
+    serde_json::to_string(value)
+        .map(|s| count_text_tokens(&s))
+        .unwrap_or(0)
+}
@@ -0,0 +1,398 @@
+// 请求改写规则：按顺序对转发给上游的请求体与请求头执行设置/删除字段、正则脱敏、追加系统提示词等操作
+// 规则可限定 API 类型与供应商，在每次尝试选定供应商后、格式转换前执行
+
+use crate::gateway::config::ApiType;
+use dashmap::DashMap;
+use regex::Regex;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::LazyLock;
+
+/// 已编译的正则（按表达式缓存，避免每次请求重新编译；敏感信息检测的自定义规则也使用）
+static REGEX_CACHE: LazyLock<DashMap<String, Regex>> = LazyLock::new(DashMap::new);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransformRule {
+    // 规则名称（预览结果中标识命中的规则）
+    pub name: String,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    // 生效的 API 类型，为空表示全部
+    #[serde(default)]
+    pub api_types: Vec<ApiType>,
+    // 生效的供应商 ID，为空表示全部
+    #[serde(default)]
+    pub provider_ids: Vec<String>,
+    pub action: TransformAction,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TransformAction {
+    /// 设置字段（点号路径，数字表示数组下标，缺失的中间对象自动创建）
+    SetField { path: String, value: Value },
+    /// 删除字段
+    RemoveField { path: String },
+    /// 将所有字符串值中匹配正则的内容替换为 replacement
+    Redact {
+        pattern: String,
+        #[serde(default = "default_redaction")]
+        replacement: String,
+    },
+    /// 在系统提示词前插入文本
+    PrependSystem { text: String },
+    /// 在系统提示词后追加文本
+    AppendSystem { text: String },
+    /// 设置请求头（覆盖同名请求头）
+    SetHeader { name: String, value: String },
+    /// 删除请求头
+    RemoveHeader { name: String },
+}
+
+fn default_true() -> bool { true }
+fn default_redaction() -> String { "[REDACTED]".to_string() }
+
+/// 请求头改动，value 为空表示删除
+#[derive(Debug, Clone, Serialize)]
+pub struct HeaderChange {
+    pub name: String,
+    pub value: Option<String>,
+}
+
+/// 规则执行结果
+#[derive(Debug, Clone, Default)]
+pub struct TransformOutcome {
+    // 请求体被改写时为新请求体，否则为空（沿用原始字节）
+    pub body: Option<Vec<u8>>,
+    pub headers: Vec<HeaderChange>,
+    // 实际生效的规则名称
+    pub applied: Vec<String>,
+}
+
+impl TransformOutcome {
+    /// 按顺序把请求头改动应用到上游请求
+    pub fn apply_headers(&self, headers: &mut HeaderMap) {
+        for change in &self.headers {
+            let Ok(name) = HeaderName::from_bytes(change.name.trim().as_bytes()) else {
+                continue;
+            };
+            match &change.value {
+                Some(value) => {
+                    if let Ok(value) = HeaderValue::from_str(value) {
+                        headers.insert(name, value);
+                    }
+                }
+                None => {
+                    headers.remove(name);
+                }
+            }
+        }
+    }
+}
+
+/// dry-run 预览结果
+#[derive(Debug, Clone, Serialize)]
+pub struct TransformPreview {
+    pub body: String,
+    pub changed: bool,
+    pub headers: Vec<HeaderChange>,
+    pub applied: Vec<String>,
+}
+
+impl TransformRule {
+    fn matches(&self, api_type: &ApiType, provider_id: Option<&str>) -> bool {
+        self.enabled
+            && (self.api_types.is_empty() || self.api_types.contains(api_type))
+            && (self.provider_ids.is_empty()
+                || provider_id.is_some_and(|id| self.provider_ids.iter().any(|p| p == id)))
+    }
+}
+
+/// 执行适用于该 API 类型与供应商的规则
+/// 请求体不是 JSON（如 multipart 上传）时跳过请求体规则，只执行请求头规则
+pub fn apply(rules: &[TransformRule], api_type: &ApiType, provider_id: Option<&str>, body: &[u8]) -> TransformOutcome {
+    let mut outcome = TransformOutcome::default();
+    let mut json: Option<Value> = None;
+    let mut body_changed = false;
+
+    for rule in rules.iter().filter(|r| r.matches(api_type, provider_id)) {
+        let applied = match &rule.action {
+            TransformAction::SetHeader { name, value } => {
+                outcome.headers.push(HeaderChange {
+                    name: name.clone(),
+                    value: Some(value.clone()),
+                });
+                true
+            }
+            TransformAction::RemoveHeader { name } => {
+                outcome.headers.push(HeaderChange {
+                    name: name.clone(),
+                    value: None,
+                });
+                true
+            }
+            action => {
+                if json.is_none() {
+                    json = serde_json::from_slice::<Value>(body).ok().filter(|v| v.is_object());
+                }
+                let Some(json) = json.as_mut() else {
+                    continue;
+                };
+                let changed = apply_body_action(action, api_type, json);
+                body_changed |= changed;
+                changed
+            }
+        };
+        if applied {
+            outcome.applied.push(rule.name.clone());
+        }
+    }
+
+    if body_changed {
+        outcome.body = json.and_then(|j| serde_json::to_vec(&j).ok());
+    }
+    outcome
+}
+
+/// 校验规则（保存配置与预览前调用）
+pub fn validate_rules(rules: &[TransformRule]) -> Result<(), String> {
+    for rule in rules {
+        let context = |e: String| format!("Transform rule '{}': {}", rule.name, e);
+        match &rule.action {
+            TransformAction::SetField { path, .. } | TransformAction::RemoveField { path } => {
+                if path_segments(path).is_empty() {
+                    return Err(context("path must not be empty".to_string()));
+                }
+            }
+            TransformAction::Redact { pattern, .. } => {
+                Regex::new(pattern).map_err(|e| context(format!("invalid pattern: {}", e)))?;
+            }
+            TransformAction::PrependSystem { .. } | TransformAction::AppendSystem { .. } => {}
+            TransformAction::SetHeader { name, value } => {
+                HeaderName::from_bytes(name.trim().as_bytes())
+                    .map_err(|_| context(format!("invalid header name: {}", name)))?;
+                HeaderValue::from_str(value).map_err(|_| context("invalid header value".to_string()))?;
+            }
+            TransformAction::RemoveHeader { name } => {
+                HeaderName::from_bytes(name.trim().as_bytes())
+                    .map_err(|_| context(format!("invalid header name: {}", name)))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 预览规则对请求体的改写效果
+pub fn preview(rules: &[TransformRule], api_type: &ApiType, provider_id: Option<&str>, body: &str) -> TransformPreview {
+    let outcome = apply(rules, api_type, provider_id, body.as_bytes());
+    let changed = outcome.body.is_some();
+    let body = match &outcome.body {
+        Some(bytes) => serde_json::from_slice::<Value>(bytes)
+            .ok()
+            .and_then(|v| serde_json::to_string_pretty(&v).ok())
+            .unwrap_or_else(|| String::from_utf8_lossy(bytes).to_string()),
+        None => body.to_string(),
+    };
+    TransformPreview {
+        body,
+        changed,
+        headers: outcome.headers,
+        applied: outcome.applied,
+    }
+}
+
+/// 执行请求体规则，返回请求体是否被修改
+fn apply_body_action(action: &TransformAction, api_type: &ApiType, json: &mut Value) -> bool {
+    match action {
+        TransformAction::SetField { path, value } => set_json_path(json, path, value.clone()),
+        TransformAction::RemoveField { path } => remove_json_path(json, path),
+        TransformAction::Redact { pattern, replacement } => match cached_regex(pattern) {
+            Some(regex) => redact(json, &regex, replacement),
+            None => false,
+        },
+        TransformAction::PrependSystem { text } => add_system_text(json, api_type, text, true),
+        TransformAction::AppendSystem { text } => add_system_text(json, api_type, text, false),
+        TransformAction::SetHeader { .. } | TransformAction::RemoveHeader { .. } => false,
+    }
+}
+
+/// 获取已编译的正则，表达式无效时返回空（规则在保存时已校验）
+pub fn cached_regex(pattern: &str) -> Option<Regex> {
+    if let Some(regex) = REGEX_CACHE.get(pattern) {
+        return Some(regex.clone());
+    }
+    match Regex::new(pattern) {
+        Ok(regex) => {
+            REGEX_CACHE.insert(pattern.to_string(), regex.clone());
+            Some(regex)
+        }
+        Err(e) => {
+            eprintln!("Skipping invalid pattern '{}': {}", pattern, e);
+            None
+        }
+    }
+}
+
+fn path_segments(path: &str) -> Vec<&str> {
+    path.split('.').map(str::trim).filter(|p| !p.is_empty()).collect()
+}
+
+/// 按点号路径设置字段，例如 `metadata.team` 或 `messages.0.content`
+fn set_json_path(value: &mut Value, path: &str, new_value: Value) -> bool {
+    let mut parts = path_segments(path);
+    let Some(last) = parts.pop() else {
+        return false;
+    };
+
+    let mut current = value;
+    for part in parts {
+        current = match current {
+            Value::Object(map) => map.entry(part).or_insert_with(|| json!({})),
+            Value::Array(items) => match part.parse::<usize>().ok().and_then(|i| items.get_mut(i)) {
+                Some(item) => item,
+                None => return false,
+            },
+            _ => return false,
+        };
+    }
+    match current {
+        Value::Object(map) => {
+            if map.get(last) == Some(&new_value) {
+                return false;
+            }
+            map.insert(last.to_string(), new_value);
+            true
+        }
+        Value::Array(items) => match last.parse::<usize>().ok().and_then(|i| items.get_mut(i)) {
+            Some(item) if *item != new_value => {
+                *item = new_value;
+                true
+            }
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// 按点号路径删除字段，数组下标对应的元素会被移除
+fn remove_json_path(value: &mut Value, path: &str) -> bool {
+    let mut parts = path_segments(path);
+    let Some(last) = parts.pop() else {
+        return false;
+    };
+
+    let mut current = value;
+    for part in parts {
+        let next = match current {
+            Value::Object(map) => map.get_mut(part),
+            Value::Array(items) => part.parse::<usize>().ok().and_then(|i| items.get_mut(i)),
+            _ => None,
+        };
+        match next {
+            Some(next) => current = next,
+            None => return false,
+        }
+    }
+    match current {
+        Value::Object(map) => map.remove(last).is_some(),
+        Value::Array(items) => match last.parse::<usize>() {
+            Ok(i) if i < items.len() => {
+                items.remove(i);
+                true
+            }
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// 对所有字符串值（不含字段名）做正则替换
+fn redact(value: &mut Value, regex: &Regex, replacement: &str) -> bool {
+    match value {
+        Value::String(s) => {
+            let replaced = regex.replace_all(s, replacement);
+            if replaced == s.as_str() {
+                return false;
+            }
+            *s = replaced.into_owned();
+            true
+        }
+        Value::Array(items) => items
+            .iter_mut()
+            .fold(false, |changed, item| redact(item, regex, replacement) | changed),
+        Value::Object(map) => map
+            .values_mut()
+            .fold(false, |changed, item| redact(item, regex, replacement) | changed),
+        _ => false,
+    }
+}
+
+/// 在系统提示词前后加入文本
+/// Anthropic 使用 `system`（字符串或内容块），Responses 使用 `instructions`，
+/// Chat 使用第一条 system / developer 消息（没有时在开头插入一条）
+fn add_system_text(json: &mut Value, api_type: &ApiType, text: &str, prepend: bool) -> bool {
+    if text.is_empty() {
+        return false;
+    }
+    let Some(body) = json.as_object_mut() else {
+        return false;
+    };
+
+    match api_type {
+        ApiType::Anthropic => {
+            let system = body.entry("system").or_insert(Value::Null);
+            merge_text(system, text, prepend);
+            true
+        }
+        ApiType::OpenAIResponses => {
+            let instructions = body.entry("instructions").or_insert(Value::Null);
+            merge_text(instructions, text, prepend);
+            true
+        }
+        ApiType::OpenAIChat => {
+            let Some(messages) = body.get_mut("messages").and_then(|m| m.as_array_mut()) else {
+                return false;
+            };
+            let existing = messages.iter_mut().find(|m| {
+                matches!(m.get("role").and_then(|r| r.as_str()), Some("system") | Some("developer"))
+            });
+            match existing {
+                Some(message) => {
+                    let content = message
+                        .as_object_mut()
+                        .map(|m| m.entry("content").or_insert(Value::Null));
+                    match content {
+                        Some(content) => merge_text(content, text, prepend),
+                        None => return false,
+                    }
+                }
+                None => messages.insert(0, json!({ "role": "system", "content": text })),
+            }
+            true
+        }
+    }
+}
+
+/// 合并文本：字符串以空行分隔拼接，内容块数组插入文本块，缺失时直接写入
+fn merge_text(target: &mut Value, text: &str, prepend: bool) {
+    match target {
+        Value::String(existing) if existing.is_empty() => *existing = text.to_string(),
+        Value::String(existing) => {
+            *existing = if prepend {
+                format!("{}\n\n{}", text, existing)
+            } else {
+                format!("{}\n\n{}", existing, text)
+            };
+        }
+        Value::Array(blocks) => {
+            let block = json!({ "type": "text", "text": text });
+            if prepend {
+                blocks.insert(0, block);
+            } else {
+                blocks.push(block);
+            }
+        }
+        _ => *target = Value::String(text.to_string()),
+    }
+}
@@ -0,0 +1,183 @@
+// 请求体校验：转发前检查必填字段与取值范围，明显错误的请求不发往上游（避免误触发熔断）
+
+use crate::gateway::endpoints::EndpointFamily;
+use serde_json::{Map, Value};
+
+/// max_tokens 等输出上限字段的合理范围
+const MAX_OUTPUT_TOKENS: u64 = 1_000_000;
+
+/// 校验失败：说明与出错字段
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationError {
+    pub message: String,
+    pub param: Option<String>,
+}
+
+impl ValidationError {
+    fn new(param: &str, message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            param: Some(param.to_string()),
+        }
+    }
+}
+
+type Rules = fn(&Map<String, Value>) -> Result<(), ValidationError>;
+
+/// 校验 POST 请求体；只检查已知的 JSON 端点，其他路径（批处理、文件上传等）直接放行
+pub fn validate_request(path: &str, body: &[u8]) -> Result<(), ValidationError> {
+    let path = path.trim_end_matches('/');
+    let rules: Rules = match (EndpointFamily::from_path(path), path) {
+        (EndpointFamily::Messages, "/v1/messages") => anthropic_messages,
+        (EndpointFamily::Messages, "/v1/messages/count_tokens") => anthropic_count_tokens,
+        (EndpointFamily::ChatCompletions, _) => chat_completions,
+        (EndpointFamily::Responses, "/v1/responses") => responses,
+        (EndpointFamily::Completions, _) => completions,
+        (EndpointFamily::Embeddings, _) => embeddings,
+        _ => return Ok(()),
+    };
+
+    let json: Value = serde_json::from_slice(body).map_err(|e| ValidationError {
+        message: format!("Invalid JSON body: {}", e),
+        param: None,
+    })?;
+    let Some(object) = json.as_object() else {
+        return Err(ValidationError {
+            message: "Request body must be a JSON object".to_string(),
+            param: None,
+        });
+    };
+    rules(object)
+}
+
+fn anthropic_messages(body: &Map<String, Value>) -> Result<(), ValidationError> {
+    require_model(body)?;
+    require_messages(body, &["user", "assistant"])?;
+    match body.get("max_tokens") {
+        None | Some(Value::Null) => return Err(ValidationError::new("max_tokens", "max_tokens: field required")),
+        Some(_) => output_tokens(body, "max_tokens")?,
+    }
+    number_range(body, "temperature", 0.0, 1.0)
+}
+
+fn anthropic_count_tokens(body: &Map<String, Value>) -> Result<(), ValidationError> {
+    require_model(body)?;
+    require_messages(body, &["user", "assistant"])
+}
+
+fn chat_completions(body: &Map<String, Value>) -> Result<(), ValidationError> {
+    require_model(body)?;
+    require_messages(body, &["system", "developer", "user", "assistant", "tool", "function"])?;
+    output_tokens(body, "max_tokens")?;
+    output_tokens(body, "max_completion_tokens")?;
+    number_range(body, "temperature", 0.0, 2.0)
+}
+
+fn responses(body: &Map<String, Value>) -> Result<(), ValidationError> {
+    require_model(body)?;
+    output_tokens(body, "max_output_tokens")?;
+    number_range(body, "temperature", 0.0, 2.0)
+}
+
+fn completions(body: &Map<String, Value>) -> Result<(), ValidationError> {
+    require_model(body)?;
+    require_input(body, "prompt")?;
+    output_tokens(body, "max_tokens")?;
+    number_range(body, "temperature", 0.0, 2.0)
+}
+
+fn embeddings(body: &Map<String, Value>) -> Result<(), ValidationError> {
+    require_model(body)?;
+    require_input(body, "input")
+}
+
+fn require_model(body: &Map<String, Value>) -> Result<(), ValidationError> {
+    match body.get("model") {
+        Some(Value::String(model)) if !model.trim().is_empty() => Ok(()),
+        Some(Value::String(_)) | None | Some(Value::Null) => {
+            Err(ValidationError::new("model", "model: field required"))
+        }
+        Some(_) => Err(ValidationError::new("model", "model: must be a string")),
+    }
+}
+
+/// messages 必须为非空数组，每条消息需有合法的 role 与 content
+fn require_messages(body: &Map<String, Value>, roles: &[&str]) -> Result<(), ValidationError> {
+    let messages = match body.get("messages") {
+        Some(Value::Array(messages)) if !messages.is_empty() => messages,
+        Some(Value::Array(_)) => {
+            return Err(ValidationError::new("messages", "messages: at least one message is required"))
+        }
+        None | Some(Value::Null) => return Err(ValidationError::new("messages", "messages: field required")),
+        Some(_) => return Err(ValidationError::new("messages", "messages: must be an array")),
+    };
+
+    for (i, message) in messages.iter().enumerate() {
+        let param = format!("messages.{}", i);
+        let role = message.get("role").and_then(|r| r.as_str());
+        match role {
+            Some(role) if roles.contains(&role) => {}
+            Some(role) => {
+                return Err(ValidationError::new(
+                    &format!("{}.role", param),
+                    format!("{}.role: unexpected role '{}'", param, role),
+                ))
+            }
+            None => {
+                return Err(ValidationError::new(
+                    &format!("{}.role", param),
+                    format!("{}.role: field required", param),
+                ))
+            }
+        }
+        // assistant 调用工具时 content 可为空，其他角色必须提供
+        if message.get("content").is_none_or(|c| c.is_null()) && role != Some("assistant") {
+            return Err(ValidationError::new(
+                &format!("{}.content", param),
+                format!("{}.content: field required", param),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// 文本输入字段：非空字符串或非空数组
+fn require_input(body: &Map<String, Value>, field: &str) -> Result<(), ValidationError> {
+    match body.get(field) {
+        Some(Value::String(s)) if !s.is_empty() => Ok(()),
+        Some(Value::Array(items)) if !items.is_empty() => Ok(()),
+        Some(Value::String(_)) | Some(Value::Array(_)) => {
+            Err(ValidationError::new(field, format!("{}: must not be empty", field)))
+        }
+        None | Some(Value::Null) => Err(ValidationError::new(field, format!("{}: field required", field))),
+        Some(_) => Err(ValidationError::new(field, format!("{}: must be a string or an array", field))),
+    }
+}
+
+/// 输出 token 上限（可选）：1 到 MAX_OUTPUT_TOKENS 之间的整数
+fn output_tokens(body: &Map<String, Value>, field: &str) -> Result<(), ValidationError> {
+    match body.get(field) {
+        None | Some(Value::Null) => Ok(()),
+        Some(value) => match value.as_u64() {
+            Some(n) if (1..=MAX_OUTPUT_TOKENS).contains(&n) => Ok(()),
+            _ => Err(ValidationError::new(
+                field,
+                format!("{}: must be an integer between 1 and {}", field, MAX_OUTPUT_TOKENS),
+            )),
+        },
+    }
+}
+
+/// 数值字段（可选）的取值范围
+fn number_range(body: &Map<String, Value>, field: &str, min: f64, max: f64) -> Result<(), ValidationError> {
+    match body.get(field) {
+        None | Some(Value::Null) => Ok(()),
+        Some(value) => match value.as_f64() {
+            Some(n) if (min..=max).contains(&n) => Ok(()),
+            _ => Err(ValidationError::new(
+                field,
+                format!("{}: must be a number between {} and {}", field, min, max),
+            )),
+        },
+    }
+}
@@ -51,6 +51,13 @@ fn main() {
             gateway::get_gateway_config,
             gateway::save_gateway_config,
             gateway::get_gateway_stats,
+            gateway::get_provider_models,
+            gateway::test_provider,
+            gateway::preview_transform,
+            gateway::get_gateway_notices,
+            gateway::list_cache_entries,
+            gateway::invalidate_cache,
+            gateway::clear_cache,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
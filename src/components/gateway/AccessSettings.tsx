@@ -0,0 +1,180 @@
+import { useState, useEffect } from 'react';
+import { useTranslation } from 'react-i18next';
+import { ClientKey, GatewayConfig } from '@/types/gateway';
+import { Card, CardContent, CardDescription, CardHeader, CardTitle } from '@/components/ui/card';
+import { Input } from '@/components/ui/input';
+import { Label } from '@/components/ui/label';
+import { Button } from '@/components/ui/button';
+import { Switch } from '@/components/ui/switch';
+import { Plus, X } from 'lucide-react';
+
+type BindAddressKey = 'anthropic_bind_address' | 'responses_bind_address' | 'chat_bind_address' | 'unified_bind_address';
+
+type AccessDraft = Pick<GatewayConfig, BindAddressKey | 'client_keys'>;
+
+interface AccessSettingsProps {
+    config: GatewayConfig;
+    onSave: (draft: AccessDraft) => Promise<boolean>;
+}
+
+const BIND_ADDRESS_FIELDS: { key: BindAddressKey; label: string }[] = [
+    { key: 'anthropic_bind_address', label: 'gateway.claudeCode' },
+    { key: 'responses_bind_address', label: 'gateway.codex' },
+    { key: 'chat_bind_address', label: 'gateway.openaiChat' },
+    { key: 'unified_bind_address', label: 'gateway.access.unified' },
+];
+
+function draftFrom(config: GatewayConfig): AccessDraft {
+    return {
+        anthropic_bind_address: config.anthropic_bind_address,
+        responses_bind_address: config.responses_bind_address,
+        chat_bind_address: config.chat_bind_address,
+        unified_bind_address: config.unified_bind_address,
+        client_keys: config.client_keys.map(k => ({ ...k })),
+    };
+}
+
+export function AccessSettings({ config, onSave }: AccessSettingsProps) {
+    const { t } = useTranslation();
+    const [draft, setDraft] = useState<AccessDraft>(() => draftFrom(config));
+    const [newKeyName, setNewKeyName] = useState('');
+    const [newKeyValue, setNewKeyValue] = useState('');
+    const [saving, setSaving] = useState(false);
+
+    // 配置保存或重新加载后同步（客户端 Key 以脱敏值回显，保存时由后端还原）
+    useEffect(() => {
+        setDraft(draftFrom(config));
+    }, [config]);
+
+    const updateKey = (index: number, patch: Partial<ClientKey>) => {
+        setDraft({
+            ...draft,
+            client_keys: draft.client_keys.map((k, i) => i === index ? { ...k, ...patch } : k),
+        });
+    };
+
+    const handleAddKey = () => {
+        if (!newKeyName.trim() || !newKeyValue.trim()) return;
+        setDraft({
+            ...draft,
+            client_keys: [...draft.client_keys, { name: newKeyName.trim(), key: newKeyValue.trim(), enabled: true }],
+        });
+        setNewKeyName('');
+        setNewKeyValue('');
+    };
+
+    const handleRemoveKey = (index: number) => {
+        setDraft({ ...draft, client_keys: draft.client_keys.filter((_, i) => i !== index) });
+    };
+
+    const handleSave = async () => {
+        setSaving(true);
+        try {
+            await onSave(draft);
+        } finally {
+            setSaving(false);
+        }
+    };
+
+    return (
+        <Card>
+            <CardHeader>
+                <CardTitle>{t('gateway.access.title')}</CardTitle>
+                <CardDescription>{t('gateway.access.description')}</CardDescription>
+            </CardHeader>
+            <CardContent className="space-y-4">
+                {/* 监听地址 */}
+                <div className="space-y-2">
+                    <Label className="font-medium">{t('gateway.access.bindAddress')}</Label>
+                    <p className="text-xs text-muted-foreground">{t('gateway.access.bindAddressDesc')}</p>
+                    <div className="grid grid-cols-1 sm:grid-cols-2 gap-3">
+                        {BIND_ADDRESS_FIELDS.map(field => (
+                            <div key={field.key} className="space-y-1">
+                                <Label htmlFor={field.key} className="text-xs font-normal">{t(field.label)}</Label>
+                                <Input
+                                    id={field.key}
+                                    value={draft[field.key]}
+                                    onChange={e => setDraft({ ...draft, [field.key]: e.target.value })}
+                                    placeholder="127.0.0.1"
+                                    className="h-8 text-xs font-mono"
+                                />
+                            </div>
+                        ))}
+                    </div>
+                </div>
+
+                {/* 客户端 Key */}
+                <div className="space-y-2 border rounded-lg p-3 bg-muted/30">
+                    <Label className="font-medium">{t('gateway.access.clientKeys')}</Label>
+                    <p className="text-xs text-muted-foreground">{t('gateway.access.clientKeysDesc')}</p>
+
+                    {draft.client_keys.length > 0 && (
+                        <div className="space-y-1 mt-2">
+                            {draft.client_keys.map((clientKey, index) => (
+                                <div key={index} className="flex items-center gap-2 text-sm bg-background/50 rounded px-2 py-1">
+                                    <Switch
+                                        checked={clientKey.enabled}
+                                        onCheckedChange={(checked) => updateKey(index, { enabled: checked })}
+                                    />
+                                    <Input
+                                        value={clientKey.name}
+                                        onChange={e => updateKey(index, { name: e.target.value })}
+                                        className="h-7 text-xs flex-1"
+                                    />
+                                    <Input
+                                        value={clientKey.key}
+                                        onChange={e => updateKey(index, { key: e.target.value })}
+                                        className="h-7 text-xs font-mono flex-1"
+                                    />
+                                    <Button
+                                        type="button"
+                                        variant="ghost"
+                                        size="icon"
+                                        className="h-6 w-6 text-destructive/70 hover:text-destructive shrink-0"
+                                        onClick={() => handleRemoveKey(index)}
+                                    >
+                                        <X className="h-3 w-3" />
+                                    </Button>
+                                </div>
+                            ))}
+                        </div>
+                    )}
+
+                    {/* 添加新 Key */}
+                    <div className="flex items-center gap-2 mt-2">
+                        <Input
+                            placeholder={t('gateway.access.keyName')}
+                            value={newKeyName}
+                            onChange={e => setNewKeyName(e.target.value)}
+                            className="h-8 text-xs"
+                        />
+                        <Input
+                            type="password"
+                            placeholder={t('gateway.access.keyValue')}
+                            value={newKeyValue}
+                            onChange={e => setNewKeyValue(e.target.value)}
+                            className="h-8 text-xs"
+                        />
+                        <Button
+                            type="button"
+                            variant="outline"
+                            size="icon"
+                            className="h-8 w-8 shrink-0"
+                            onClick={handleAddKey}
+                            disabled={!newKeyName.trim() || !newKeyValue.trim()}
+                        >
+                            <Plus className="h-4 w-4" />
+                        </Button>
+                    </div>
+                </div>
+
+                <div className="flex items-center justify-between gap-2">
+                    <p className="text-xs text-muted-foreground">{t('gateway.access.restartHint')}</p>
+                    <Button onClick={handleSave} disabled={saving}>
+                        {t('gateway.form.save')}
+                    </Button>
+                </div>
+            </CardContent>
+        </Card>
+    );
+}
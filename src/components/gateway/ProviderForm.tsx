@@ -46,7 +46,9 @@ export function ProviderForm({ initialData, onSubmit, onCancel }: ProviderFormPr
         if (formData.claude_code_proxy && !apiTypes.includes('Anthropic')) {
             apiTypes = ['Anthropic', ...apiTypes];
         }
+        // 保留表单未展示的字段（多 Key、请求头、TLS 等），避免编辑时丢失
         onSubmit({
+            ...formData,
             id: initialData?.id || crypto.randomUUID(),
             name: formData.name || 'New Provider',
             base_url: formData.base_url || '',
@@ -58,6 +60,8 @@ export function ProviderForm({ initialData, onSubmit, onCancel }: ProviderFormPr
             input_price_per_1k: formData.input_price_per_1k || 0,
             output_price_per_1k: formData.output_price_per_1k || 0,
             claude_code_proxy: formData.claude_code_proxy || false,
+            send_reasoning_effort: formData.send_reasoning_effort || false,
+            forward_top_k: formData.forward_top_k || false,
         });
     };
 
@@ -155,6 +159,36 @@ export function ProviderForm({ initialData, onSubmit, onCancel }: ProviderFormPr
                 <p className="text-xs text-muted-foreground ml-6">
                     {t('gateway.form.claudeCodeProxyDesc')}
                 </p>
+                {formData.claude_code_proxy && (
+                    <div className="ml-6 pt-1 space-y-1">
+                        <div className="flex items-center space-x-2">
+                            <Checkbox
+                                id="send_reasoning_effort"
+                                checked={formData.send_reasoning_effort}
+                                onCheckedChange={(checked) => setFormData({ ...formData, send_reasoning_effort: !!checked })}
+                            />
+                            <Label htmlFor="send_reasoning_effort" className="font-normal">
+                                {t('gateway.form.sendReasoningEffort')}
+                            </Label>
+                        </div>
+                        <p className="text-xs text-muted-foreground ml-6">
+                            {t('gateway.form.sendReasoningEffortDesc')}
+                        </p>
+                        <div className="flex items-center space-x-2">
+                            <Checkbox
+                                id="forward_top_k"
+                                checked={formData.forward_top_k}
+                                onCheckedChange={(checked) => setFormData({ ...formData, forward_top_k: !!checked })}
+                            />
+                            <Label htmlFor="forward_top_k" className="font-normal">
+                                {t('gateway.form.forwardTopK')}
+                            </Label>
+                        </div>
+                        <p className="text-xs text-muted-foreground ml-6">
+                            {t('gateway.form.forwardTopKDesc')}
+                        </p>
+                    </div>
+                )}
             </div>
 
             {/* 模型映射区块 */}
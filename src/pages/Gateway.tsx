@@ -5,12 +5,13 @@ import { listen } from '@tauri-apps/api/event';
 import { Card, CardContent, CardHeader, CardTitle, CardDescription } from '@/components/ui/card';
 import { Switch } from '@/components/ui/switch';
 import { Dialog, DialogContent, DialogHeader, DialogTitle } from '@/components/ui/dialog';
-import { Server, Coins, Database, Zap, Bot, MessageSquare, Code2, Copy, Check } from 'lucide-react';
+import { Server, Coins, Database, Zap, Bot, MessageSquare, Code2, Copy, Check, AlertTriangle } from 'lucide-react';
 import { GatewayConfig, Provider, GatewayStats } from '@/types/gateway';
 import { ProviderForm } from '@/components/gateway/ProviderForm';
 import { StatsCard } from '@/components/gateway/StatsCard';
 import { RequestChart } from '@/components/gateway/RequestChart';
 import { ProviderList } from '@/components/gateway/ProviderList';
+import { AccessSettings } from '@/components/gateway/AccessSettings';
 import { formatDistanceToNow } from 'date-fns';
 import { Badge } from '@/components/ui/badge';
 
@@ -28,6 +29,8 @@ export function Gateway() {
     const [editingProvider, setEditingProvider] = useState<Provider | undefined>(undefined);
     const [providerStatuses, setProviderStatuses] = useState<Record<string, string>>({});
     const [copiedPort, setCopiedPort] = useState<string | null>(null);
+    const [notices, setNotices] = useState<string[]>([]);
+    const [saveError, setSaveError] = useState<string | null>(null);
 
     const copyToClipboard = async (port: number) => {
         const url = `http://localhost:${port}`;
@@ -40,6 +43,7 @@ export function Gateway() {
         try {
             const c = await invoke<GatewayConfig>('get_gateway_config');
             setConfig(c);
+            setNotices(await invoke<string[]>('get_gateway_notices'));
         } catch (e) {
             console.error('Failed to load config:', e);
         }
@@ -83,12 +87,17 @@ export function Gateway() {
         };
     }, []);
 
-    const handleSaveConfig = async (newConfig: GatewayConfig) => {
+    const handleSaveConfig = async (newConfig: GatewayConfig): Promise<boolean> => {
         try {
             await invoke('save_gateway_config', { config: newConfig });
             setConfig(newConfig);
+            setSaveError(null);
+            setNotices(await invoke<string[]>('get_gateway_notices'));
+            return true;
         } catch (e) {
             console.error('Failed to save config:', e);
+            setSaveError(String(e));
+            return false;
         }
     };
 
@@ -106,16 +115,18 @@ export function Gateway() {
     const handleAddProvider = async (provider: Provider) => {
         if (!config) return;
         const newProviders = [...config.providers, provider];
-        await handleSaveConfig({ ...config, providers: newProviders });
-        setIsFormOpen(false);
+        if (await handleSaveConfig({ ...config, providers: newProviders })) {
+            setIsFormOpen(false);
+        }
     };
 
     const handleEditProvider = async (provider: Provider) => {
         if (!config) return;
         const newProviders = config.providers.map(p => p.id === provider.id ? provider : p);
-        await handleSaveConfig({ ...config, providers: newProviders });
-        setIsFormOpen(false);
-        setEditingProvider(undefined);
+        if (await handleSaveConfig({ ...config, providers: newProviders })) {
+            setIsFormOpen(false);
+            setEditingProvider(undefined);
+        }
     };
 
     const handleDeleteProvider = async (id: string) => {
@@ -141,6 +152,22 @@ export function Gateway() {
                 </div>
             </div>
 
+            {/* Save Error */}
+            {saveError && (
+                <div className="flex items-start gap-2 rounded-md border border-red-500/30 bg-red-500/5 p-3 text-sm">
+                    <AlertTriangle className="h-4 w-4 mt-0.5 shrink-0 text-red-500" />
+                    <span>{t('gateway.saveFailed')}: {saveError}</span>
+                </div>
+            )}
+
+            {/* Gateway Notices */}
+            {notices.map((notice) => (
+                <div key={notice} className="flex items-start gap-2 rounded-md border border-yellow-500/30 bg-yellow-500/5 p-3 text-sm">
+                    <AlertTriangle className="h-4 w-4 mt-0.5 shrink-0 text-yellow-500" />
+                    <span>{notice}</span>
+                </div>
+            ))}
+
             {/* Gateway Status Cards */}
             <div className="grid gap-4 md:grid-cols-3">
                 {/* Anthropic Gateway */}
@@ -305,6 +332,12 @@ export function Gateway() {
                 />
             </div>
 
+            {/* Access Settings */}
+            <AccessSettings
+                config={config}
+                onSave={(draft) => handleSaveConfig({ ...config, ...draft })}
+            />
+
             <Card>
                 <CardHeader>
                     <CardTitle>{t('gateway.recentRequests')}</CardTitle>
@@ -1,10 +1,22 @@
 export type ApiType = 'Anthropic' | 'OpenAIResponses' | 'OpenAIChat';
 
+export type SelectionStrategy = 'weighted' | 'least_latency' | 'round_robin' | 'cost_first' | 'sticky';
+
+export type ProviderKind = 'generic' | 'azure';
+
+export type AuthStyle = 'auto' | 'bearer' | 'x_api_key' | 'api_key' | 'query_key' | 'none';
+
+export type KeySelection = 'round_robin' | 'least_used';
+
 export interface Provider {
     id: string;
     name: string;
+    kind?: ProviderKind;
     base_url: string;
-    api_key: string;
+    url_template?: string | null;  // 占位符：{base} {path} {endpoint} {model} {deployment}
+    api_key: string;  // 读取时为脱敏值；可填写 env:VAR_NAME 或 file:/path 引用
+    api_keys?: string[];  // 额外的 API Key，与 api_key 一起轮换
+    key_selection?: KeySelection;
     model_mapping: Record<string, string>;
     enabled: boolean;
     api_types: ApiType[];
@@ -12,6 +24,82 @@ export interface Provider {
     input_price_per_1k: number;
     output_price_per_1k: number;
     claude_code_proxy: boolean;  // 是否作为 Claude Code 代理（将 Anthropic 请求转换为 OpenAI 格式）
+    send_reasoning_effort?: boolean;  // 转换时将 thinking 映射为 reasoning_effort（仅推理模型支持）
+    forward_top_k?: boolean;  // 转换时转发 top_k（OpenAI 不支持，仅上游接受时开启）
+    auth_style?: AuthStyle;
+    anthropic_version?: string | null;  // 覆盖 anthropic-version
+    extra_headers?: Record<string, string>;
+    remove_headers?: string[];
+    query_params?: Record<string, string>;
+    proxy?: OutboundProxy | null;  // 覆盖全局代理；url 填 "direct" 表示直连
+    tls?: TlsSettings | null;
+}
+
+// 供应商 TLS 设置（证书均为 PEM 文件路径）
+export interface TlsSettings {
+    ca_cert_path?: string | null;      // 额外信任的 CA（可包含多个证书）
+    client_cert_path?: string | null;  // mTLS 客户端证书
+    client_key_path?: string | null;   // mTLS 客户端私钥（PKCS#8）
+    danger_accept_invalid_certs?: boolean;  // 危险：跳过证书校验，仅用于本地 mock
+}
+
+export interface OutboundProxy {
+    url: string;  // http:// https:// socks5:// socks5h:// 或 direct
+    username?: string | null;
+    password?: string | null;  // 读取时为脱敏值
+    no_proxy?: string[];
+}
+
+export interface ServerTls {
+    cert_path: string;  // PEM 证书链
+    key_path: string;   // PKCS#8 私钥
+}
+
+export interface ClientKey {
+    name: string;
+    key: string;  // 读取时为脱敏值
+    enabled: boolean;
+}
+
+export interface CacheOverride {
+    enabled?: boolean | null;
+    ttl_seconds?: number | null;
+}
+
+// 请求改写动作（path 为点号分隔的 JSON 路径）
+export type TransformAction =
+    | { type: 'set_field'; path: string; value: unknown }
+    | { type: 'remove_field'; path: string }
+    | { type: 'redact'; pattern: string; replacement?: string }
+    | { type: 'prepend_system'; text: string }
+    | { type: 'append_system'; text: string }
+    | { type: 'set_header'; name: string; value: string }
+    | { type: 'remove_header'; name: string };
+
+export interface TransformRule {
+    name: string;
+    enabled: boolean;
+    api_types: ApiType[];     // 为空表示全部
+    provider_ids: string[];   // 为空表示全部
+    action: TransformAction;
+}
+
+// 敏感信息命中后的处理：放行并记录 / 脱敏后转发 / 拒绝请求
+export type SecurityPolicy = 'warn' | 'redact' | 'block';
+
+export interface PiiPattern {
+    name: string;
+    pattern: string;
+    policy?: SecurityPolicy | null;  // 覆盖全局策略
+}
+
+// 脚本插件（Rhai）：脚本定义 on_request(ctx) / on_response(ctx)
+export interface PluginConfig {
+    name: string;
+    enabled: boolean;
+    api_types: ApiType[];        // 为空表示全部
+    script: string;              // 内联脚本，为空时读取 script_path
+    script_path?: string | null;
 }
 
 export interface GatewayConfig {
@@ -20,6 +108,25 @@ export interface GatewayConfig {
     responses_port: number;
     chat_port: number;
 
+    // 监听地址（默认 127.0.0.1；非回环地址对局域网开放，需配置客户端 Key），重启生效
+    anthropic_bind_address: string;
+    responses_bind_address: string;
+    chat_bind_address: string;
+
+    // 统一端口：按路径分发三种 API，独立端口仍然可用（重启生效）
+    unified_enabled: boolean;
+    unified_port: number;
+    unified_bind_address: string;
+
+    // HTTPS 证书（重启生效）
+    server_tls?: ServerTls | null;
+
+    // 访问控制：IP 或 CIDR，denylist 优先；本机始终允许
+    allowed_ips: string[];
+    denied_ips: string[];
+    client_keys: ClientKey[];          // 远程客户端通过 x-api-key / Bearer 提供
+    cors_allowed_origins: string[];    // 对外开放时允许的 CORS 来源
+
     // 三个独立开关
     anthropic_enabled: boolean;
     responses_enabled: boolean;
@@ -32,15 +139,74 @@ export interface GatewayConfig {
     providers: Provider[];
     fallback_enabled: boolean;
 
+    // 按 API 类型配置供应商选择策略
+    selection_strategies: Partial<Record<ApiType, SelectionStrategy>>;
+
+    // 全局出站代理
+    outbound_proxy?: OutboundProxy | null;
+
+    // 会话亲和：同一会话固定到首次成功的供应商
+    session_affinity_enabled: boolean;
+    session_affinity_ttl_seconds: number;
+    session_affinity_max_sessions: number;
+
     // 缓存配置
     cache_enabled: boolean;
     cache_ttl_seconds: number;
     cache_max_entries: number;
+    cache_max_memory_bytes: number;
+    cache_persist_enabled: boolean;  // 磁盘缓存 (data/cache/)
+    cache_max_disk_bytes: number;
+    cache_compression: boolean;
+    cache_overrides: Partial<Record<ApiType, CacheOverride>>;  // 按 API 类型覆盖缓存开关/TTL
+    // 缓存准入规则
+    cache_allow_nonzero_temperature: boolean;
+    cache_allow_tools: boolean;
+    cache_max_body_bytes: number;
+    cache_ignored_fields: string[];  // 生成缓存 Key 时忽略的字段 (如 metadata.user_id)
+
+    // 请求体大小上限（字节）与按 API 类型的覆盖；转发前校验请求体
+    max_request_body_bytes: number;
+    request_body_limits: Partial<Record<ApiType, number>>;
+    request_validation_enabled: boolean;
+
+    // 请求改写规则（按顺序执行）
+    transform_rules: TransformRule[];
+
+    // 出站请求敏感信息检测
+    secret_scan_enabled: boolean;
+    secret_scan_policy: SecurityPolicy;
+    secret_scan_entropy: boolean;  // 检测高熵字符串（默认关闭，可能误报）
+    pii_patterns: PiiPattern[];
+
+    // 脚本插件（按顺序调用）
+    plugins: PluginConfig[];
 
     // 熔断配置
     circuit_breaker_cooldown_seconds: number;
 }
 
+// 敏感信息命中（preview 只保留前 4 个字符）
+export interface SecurityFinding {
+    detector: string;
+    category: 'secret' | 'pii';
+    path: string;  // JSON 路径，如 messages.0.content
+    preview: string;
+    count: number;
+    action: SecurityPolicy;
+}
+
+// gateway://security 事件
+export interface SecurityEvent {
+    request_id: string;
+    timestamp: number;
+    api_type: string;
+    path: string;
+    model: string;
+    action: 'blocked' | 'redacted' | 'allowed';
+    findings: SecurityFinding[];
+}
+
 export interface RequestLog {
     id: string;
     timestamp: number;
@@ -56,6 +222,9 @@ export interface RequestLog {
     api_type: string;
     cached: boolean;
     error_message?: string;  // 完整错误信息
+    api_key_id?: string | null;
+    api_key_label?: string | null;  // 脱敏后的 Key
+    security_findings?: SecurityFinding[];
 }
 
 export interface ProviderStats {
@@ -91,6 +260,22 @@ export interface ProviderStats {
 
     cooldown_until?: number | null;
     cooldown_reason?: string | null;
+    key_stats?: Record<string, KeyStats>;
+}
+
+export interface KeyStats {
+    key_id: string;
+    label: string;
+    total_requests: number;
+    successful_requests: number;
+    failed_requests: number;
+    rate_limited_requests: number;
+    total_input_tokens: number;
+    total_output_tokens: number;
+    last_used_at?: number | null;
+    last_error_message?: string | null;
+    cooldown_until?: number | null;
+    cooldown_reason?: string | null;
 }
 
 export interface HourlyStat {
@@ -101,6 +286,17 @@ export interface HourlyStat {
     cost: number;
 }
 
+export interface CacheStats {
+    memory_entries: number;
+    memory_bytes: number;
+    disk_entries: number;
+    disk_bytes: number;
+    max_memory_bytes: number;
+    max_disk_bytes: number;
+    evictions: number;  // 本次运行期间的淘汰数
+    hit_ratio: number;
+}
+
 export interface GatewayStats {
     // 全局统计
     total_requests: number;
@@ -109,6 +305,9 @@ export interface GatewayStats {
     total_cost: number;
     cache_hits: number;
     cache_misses: number;
+    cache: CacheStats;
+    pinned_sessions: number;
+    blocked_requests: number;  // 被安全策略拒绝的请求
 
     // 按 API 类型统计
     anthropic_requests: number;
@@ -121,3 +320,62 @@ export interface GatewayStats {
     recent_requests: RequestLog[];
     hourly_activity: HourlyStat[];
 }
+
+export interface MappingIssue {
+    alias: string;
+    target: string;
+}
+
+// 上游模型发现结果
+export interface ProviderModels {
+    provider_id: string;
+    provider_name: string;
+    models: string[];
+    fetched_at: number;
+    error: string | null;
+    invalid_mappings: MappingIssue[];        // 映射目标在上游不存在
+    suggested_mappings: Record<string, string>;  // 常见 Claude 模型名的映射建议
+}
+
+// 供应商连通性测试结果
+export interface ProviderTestResult {
+    provider_id: string;
+    ok: boolean;
+    status: number | null;
+    latency_ms: number;
+    error_kind: string | null;  // tls / proxy / connect / timeout / auth / not_found / upstream_5xx / other
+    error: string | null;
+    model_count: number | null;
+}
+
+// 请求改写预览 (dry-run)
+export interface TransformPreview {
+    body: string;
+    changed: boolean;
+    headers: { name: string; value: string | null }[];  // value 为 null 表示删除
+    applied: string[];
+}
+
+export interface CacheEntrySummary {
+    key: string;
+    api_type: string;
+    path: string;
+    model: string;
+    status: number;
+    size_bytes: number;
+    created_at: number;
+    age_seconds: number;
+    ttl_seconds: number;
+    hits: number;
+    is_stream: boolean;
+    in_memory: boolean;
+    on_disk: boolean;
+}
+
+// 缓存失效条件 (各字段为“且”关系)
+export interface CacheInvalidation {
+    key?: string;
+    model?: string;
+    path_prefix?: string;
+    api_type?: string;
+}